@@ -0,0 +1,640 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::encode;
+
+// Assembles the syntax the disassembler prints, e.g. `MOV AL, #$80` or `ADC A, [[$1234]+IDX]`, one instruction per line
+//
+// - `;` starts a comment, `name:` defines a label at the current address
+// - Values are `$` hex, decimal or a label
+// - Byte mode comes from byte registers (`AL`, `BH`), a `.B` mnemonic suffix, or a two digit immediate when no registers are used
+// - `[$nn]` with at most two hex digits is the zero-page mode, labels always use full addresses
+// - Extended modes (`[IDY+]`, `[SP+$nn]`, ...) get their EXT prefix automatically
+// - `[PC+label]` reaches the label position-independently, `[PC+$nnnn]` and `[PC-$nnnn]` are raw offsets
+// - ADCL and SBCL take register pairs written high word first, `A:B`, `B:C` or `C:D`
+// - DBNZ and the conditional branches take a bare label as their source and encode the displacement to it, branches have no destination
+// - Directives: `ORG addr` moves the current address (padding with zeroes), `DB`/`DW` emit bytes/little-endian words
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut items = vec![];
+    let mut labels = BTreeMap::new();
+    let mut origin = None;
+    let mut address: usize = 0;
+
+    // First pass parses every line and places labels, operand sizes never depend on label values
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = text.split(';').next().unwrap_or("").trim();
+
+        while let Some((label, rest)) = split_label(text) {
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(AssembleError::DuplicateLabel { line, label: label.to_string() });
+            }
+
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, split_operands(operands)),
+            None => (text, vec![]),
+        };
+
+        let item = parse_item(line, mnemonic, &operands)?;
+
+        if let Item::Org(target) = item {
+            if origin.is_some() && target < address {
+                return Err(AssembleError::OrgBackwards { line, address: target });
+            }
+
+            origin.get_or_insert(target);
+            address = target;
+            continue;
+        }
+
+        origin.get_or_insert(address);
+
+        let size = item.size();
+
+        items.push((line, address, item));
+
+        address += size;
+    }
+
+    let mut output = vec![];
+    let origin = origin.unwrap_or(0);
+
+    for (line, address, item) in items {
+        // Gaps left by ORG are zero-filled
+        output.resize(address - origin, 0x00);
+
+        item.encode(line, address, &labels, &mut output)?;
+    }
+
+    Ok(output)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AssembleError {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    InvalidOperand { line: usize, operand: String },
+    OperandCount { line: usize, expected: usize, actual: usize },
+    OperandWidthMismatch { line: usize },
+    UndefinedLabel { line: usize, label: String },
+    DuplicateLabel { line: usize, label: String },
+    ValueOutOfRange { line: usize, value: i64 },
+    OrgBackwards { line: usize, address: usize },
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownMnemonic { line, mnemonic } => write!(f, "Line {}: unknown mnemonic {}", line, mnemonic),
+            Self::InvalidOperand { line, operand } => write!(f, "Line {}: invalid operand {}", line, operand),
+            Self::OperandCount { line, expected, actual } => write!(f, "Line {}: expected {} operands but got {}", line, expected, actual),
+            Self::OperandWidthMismatch { line } => write!(f, "Line {}: operands disagree on width or byte half", line),
+            Self::UndefinedLabel { line, label } => write!(f, "Line {}: undefined label {}", line, label),
+            Self::DuplicateLabel { line, label } => write!(f, "Line {}: label {} is already defined", line, label),
+            Self::ValueOutOfRange { line, value } => write!(f, "Line {}: value {} is out of range", line, value),
+            Self::OrgBackwards { line, address } => write!(f, "Line {}: ORG ${:04X} is behind the current address", line, address),
+        }
+    }
+}
+
+impl Error for AssembleError {}
+
+// Opcode and operand count for each mnemonic
+fn operation(mnemonic: &str) -> Option<(u16, usize)> {
+    match mnemonic {
+        "MOV" => Some((encode::OPCODE_MOV, 2)),
+        "ADC" => Some((encode::OPCODE_ADC, 2)),
+        "SBC" => Some((encode::OPCODE_SBC, 2)),
+        "XCHG" => Some((encode::OPCODE_XCHG, 2)),
+        "BIT" => Some((encode::OPCODE_BIT, 2)),
+        "INX" => Some((encode::OPCODE_INX, 0)),
+        "DEX" => Some((encode::OPCODE_DEX, 0)),
+        "INY" => Some((encode::OPCODE_INY, 0)),
+        "DEY" => Some((encode::OPCODE_DEY, 0)),
+        "SWAP" => Some((encode::OPCODE_SWAP, 1)),
+        "JTB" => Some((encode::OPCODE_JTB, 2)),
+        "DBNZ" => Some((encode::OPCODE_DBNZ, 2)),
+        "SWP" => Some((encode::OPCODE_SWP, 2)),
+        "CMP" => Some((encode::OPCODE_CMP, 2)),
+        "ADCL" => Some((encode::OPCODE_ADCL, 2)),
+        "SBCL" => Some((encode::OPCODE_SBCL, 2)),
+        "BGT" => Some((encode::OPCODE_BGT, 1)),
+        "BLT" => Some((encode::OPCODE_BLT, 1)),
+        "BGE" => Some((encode::OPCODE_BGE, 1)),
+        "BLE" => Some((encode::OPCODE_BLE, 1)),
+        "BHI" => Some((encode::OPCODE_BHI, 1)),
+        "BLO" => Some((encode::OPCODE_BLO, 1)),
+        "BHS" => Some((encode::OPCODE_BHS, 1)),
+        "BLS" => Some((encode::OPCODE_BLS, 1)),
+        "ADDSAT" => Some((encode::OPCODE_ADDSAT, 2)),
+        "SUBSAT" => Some((encode::OPCODE_SUBSAT, 2)),
+        "MIN" => Some((encode::OPCODE_MIN, 2)),
+        "MAX" => Some((encode::OPCODE_MAX, 2)),
+        "MOVZ" => Some((encode::OPCODE_MOVIF | encode::CONDITION_ZERO, 2)),
+        "MOVNZ" => Some((encode::OPCODE_MOVIF | encode::CONDITION_ZERO | encode::CONDITION_NEGATE, 2)),
+        "MOVC" => Some((encode::OPCODE_MOVIF | encode::CONDITION_CARRY, 2)),
+        "MOVNC" => Some((encode::OPCODE_MOVIF | encode::CONDITION_CARRY | encode::CONDITION_NEGATE, 2)),
+        "MOVS" => Some((encode::OPCODE_MOVIF | encode::CONDITION_SIGN, 2)),
+        "MOVNS" => Some((encode::OPCODE_MOVIF | encode::CONDITION_SIGN | encode::CONDITION_NEGATE, 2)),
+        "MOVO" => Some((encode::OPCODE_MOVIF | encode::CONDITION_OVERFLOW, 2)),
+        "MOVNO" => Some((encode::OPCODE_MOVIF | encode::CONDITION_OVERFLOW | encode::CONDITION_NEGATE, 2)),
+        "LEA" => Some((encode::OPCODE_LEA, 2)),
+        "CLZ" => Some((encode::OPCODE_CLZ, 2)),
+        "POPCNT" => Some((encode::OPCODE_POPCNT, 2)),
+        "SYSCALL" => Some((encode::OPCODE_SYSCALL, 0)),
+        "STP" => Some((encode::OPCODE_STP, 0)),
+        "RST" => Some((encode::OPCODE_RST, 0)),
+        "WAI" => Some((encode::OPCODE_WAI, 0)),
+        "NOP" => Some((encode::OPCODE_NOP, 0)),
+        _ => None,
+    }
+}
+
+// Conditional branches take their only operand as the source
+fn is_branch(mnemonic: &str) -> bool {
+    matches!(mnemonic, "BGT" | "BLT" | "BGE" | "BLE" | "BHI" | "BLO" | "BHS" | "BLS")
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(usize),
+    Label(String),
+}
+
+// Bytes that follow the instruction word for an operand
+#[derive(Clone, Debug)]
+enum Payload {
+    None,
+    Byte(Value),
+    Word(Value),
+    Immediate(Value), // Width follows the instruction's byte mode
+    Relative(Value), // Displacement from the next instruction, width follows the byte mode
+    ProgramCounterRelative(Value), // Word displacement from the end of the operand's own bytes
+}
+
+#[derive(Clone, Debug)]
+struct Operand {
+    location: u16,
+    extended: bool, // Location is an extension prefix code rather than an instruction nibble
+    payload: Payload,
+    register_half: Option<Option<bool>>, // Some for registers, holding whether a byte register is the high half
+    short_immediate: bool,
+    register_pair: bool,
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction { opcode: u16, byte_mode: bool, lo_hi: bool, destination: Option<Operand>, source: Option<Operand> },
+    Bytes(Vec<Value>),
+    Words(Vec<Value>),
+    Org(usize),
+}
+
+impl Item {
+    fn size(&self) -> usize {
+        match self {
+            Self::Instruction { byte_mode, destination, source, .. } => {
+                let extended = [destination, source].iter().any(|operand| operand.as_ref().is_some_and(|operand| operand.extended));
+                let operand_bytes: usize = [destination, source].iter().filter_map(|operand| operand.as_ref()).map(|operand| payload_size(&operand.payload, *byte_mode)).sum();
+
+                let prefix_bytes = if extended { 2 } else { 0 };
+
+                prefix_bytes + 2 + operand_bytes
+            },
+            Self::Bytes(values) => values.len(),
+            Self::Words(values) => values.len() * 2,
+            Self::Org(_) => 0,
+        }
+    }
+
+    fn encode(&self, line: usize, address: usize, labels: &BTreeMap<String, usize>, output: &mut Vec<u8>) -> Result<(), AssembleError> {
+        let resolve = |value: &Value| match value {
+            Value::Number(number) => Ok(*number),
+            Value::Label(label) => labels.get(label).copied().ok_or(AssembleError::UndefinedLabel { line, label: label.clone() }),
+        };
+
+        match self {
+            Self::Instruction { opcode, byte_mode, lo_hi, destination, source } => {
+                let start = output.len();
+                // Extended locations go in the EXT prefix's nibbles, leaving the instruction's nibble 0
+                let nibbles = |extended: bool| {
+                    [destination, source].map(|operand| operand.as_ref().filter(|operand| operand.extended == extended).map_or(0, |operand| operand.location))
+                };
+
+                let [destination_location, source_location] = nibbles(false);
+                let [destination_extension, source_extension] = nibbles(true);
+                let instruction = encode::encode_instruction(*opcode, *byte_mode, *lo_hi, destination_location, source_location);
+
+                if destination_extension != 0 || source_extension != 0 {
+                    output.extend_from_slice(&encode::encode_extension(destination_extension, source_extension).to_le_bytes());
+                }
+
+                output.extend_from_slice(&instruction.to_le_bytes());
+
+                let next_address = address + self.size();
+
+                // Source operands come before destination operands in the instruction stream
+                for operand in [source, destination].into_iter().flatten() {
+                    let (value, width) = match &operand.payload {
+                        Payload::None => continue,
+                        Payload::Byte(value) => (resolve(value)? as i64, 1),
+                        Payload::Word(value) => (resolve(value)? as i64, 2),
+                        Payload::Immediate(value) => (resolve(value)? as i64, if *byte_mode { 1 } else { 2 }),
+                        Payload::Relative(value) => {
+                            let displacement = resolve(value)? as i64 - next_address as i64;
+
+                            if *byte_mode && !(-0x80..=0x7F).contains(&displacement) {
+                                return Err(AssembleError::ValueOutOfRange { line, value: displacement });
+                            }
+
+                            (displacement & if *byte_mode { 0xFF } else { 0xFFFF }, if *byte_mode { 1 } else { 2 })
+                        },
+                        Payload::ProgramCounterRelative(value) => {
+                            let operand_end = address + output.len() - start + 2;
+                            let displacement = resolve(value)? as i64 - operand_end as i64;
+
+                            (displacement & 0xFFFF, 2)
+                        },
+                    };
+
+                    push_value(line, value, width, output)?;
+                }
+            },
+            Self::Bytes(values) => {
+                for value in values {
+                    push_value(line, resolve(value)? as i64, 1, output)?;
+                }
+            },
+            Self::Words(values) => {
+                for value in values {
+                    push_value(line, resolve(value)? as i64, 2, output)?;
+                }
+            },
+            Self::Org(_) => (),
+        }
+
+        Ok(())
+    }
+}
+
+fn payload_size(payload: &Payload, byte_mode: bool) -> usize {
+    match payload {
+        Payload::None => 0,
+        Payload::Byte(_) => 1,
+        Payload::Word(_) | Payload::ProgramCounterRelative(_) => 2,
+        Payload::Immediate(_) | Payload::Relative(_) => {
+            if byte_mode { 1 } else { 2 }
+        },
+    }
+}
+
+fn push_value(line: usize, value: i64, width: usize, output: &mut Vec<u8>) -> Result<(), AssembleError> {
+    if value < 0 || value >= 1 << (width * 8) {
+        return Err(AssembleError::ValueOutOfRange { line, value });
+    }
+
+    output.extend_from_slice(&(value as u16).to_le_bytes()[..width]);
+
+    Ok(())
+}
+
+fn parse_item(line: usize, mnemonic: &str, operands: &[&str]) -> Result<Item, AssembleError> {
+    let upper = mnemonic.to_ascii_uppercase();
+
+    match upper.as_str() {
+        "ORG" => {
+            let [operand] = operands else {
+                return Err(AssembleError::OperandCount { line, expected: 1, actual: operands.len() });
+            };
+
+            match parse_value(operand) {
+                Some(Value::Number(address)) => return Ok(Item::Org(address)),
+                _ => return Err(AssembleError::InvalidOperand { line, operand: operand.to_string() }),
+            }
+        },
+        "DB" | "DW" => {
+            let values = operands.iter().map(|operand| parse_value(operand).ok_or(AssembleError::InvalidOperand { line, operand: operand.to_string() })).collect::<Result<Vec<_>, _>>()?;
+
+            return Ok(if upper == "DB" { Item::Bytes(values) } else { Item::Words(values) });
+        },
+        _ => (),
+    }
+
+    let (name, forced_byte_mode) = match upper.strip_suffix(".B") {
+        Some(name) => (name, true),
+        None => (upper.as_str(), false),
+    };
+
+    let Some((opcode, operand_count)) = operation(name) else {
+        return Err(AssembleError::UnknownMnemonic { line, mnemonic: mnemonic.to_string() });
+    };
+
+    if operands.len() != operand_count {
+        return Err(AssembleError::OperandCount { line, expected: operand_count, actual: operands.len() });
+    }
+
+    let parse = |text: &str| parse_operand(text).ok_or(AssembleError::InvalidOperand { line, operand: text.to_string() });
+
+    let (destination_text, source_text) = if is_branch(name) {
+        (None, operands.first())
+    } else {
+        (operands.first(), operands.get(1))
+    };
+
+    let destination = destination_text.map(|text| parse(text)).transpose()?;
+    let source = source_text.map(|text| parse(text)).transpose()?;
+
+    // An immediate destination is written as `-`, a source always needs a value, and only DBNZ and branches go to a label
+    if let Some(operand) = &destination
+        && let Payload::Immediate(_) | Payload::Relative(_) = operand.payload
+        && let Some(text) = destination_text
+    {
+        return Err(AssembleError::InvalidOperand { line, operand: text.to_string() });
+    }
+
+    if let Some(operand) = &source
+        && let Some(text) = source_text
+    {
+        let valid = match operand.payload {
+            Payload::None => operand.location != 0 || operand.extended,
+            Payload::Relative(_) => name == "DBNZ" || is_branch(name),
+            _ => true,
+        };
+
+        if !valid {
+            return Err(AssembleError::InvalidOperand { line, operand: text.to_string() });
+        }
+    }
+
+    // 32-bit operations take register pairs, which nothing else accepts
+    let pair_operation = name == "ADCL" || name == "SBCL";
+
+    for (operand, text) in [(&destination, destination_text), (&source, source_text)] {
+        if let Some(operand) = operand
+            && let Some(text) = text
+            && operand.register_pair != pair_operation
+        {
+            return Err(AssembleError::InvalidOperand { line, operand: text.to_string() });
+        }
+    }
+
+    let registers: Vec<Option<bool>> = [&destination, &source].into_iter().flatten().filter_map(|operand| operand.register_half).collect();
+    let halves: Vec<bool> = registers.iter().flatten().copied().collect();
+
+    if !halves.is_empty() && halves.len() != registers.len() {
+        return Err(AssembleError::OperandWidthMismatch { line });
+    }
+
+    if halves.windows(2).any(|pair| pair[0] != pair[1]) {
+        return Err(AssembleError::OperandWidthMismatch { line });
+    }
+
+    let short_immediate = registers.is_empty() && source.as_ref().is_some_and(|operand| operand.short_immediate);
+    let byte_mode = forced_byte_mode || !halves.is_empty() || short_immediate;
+
+    if forced_byte_mode && !registers.is_empty() && halves.is_empty() {
+        return Err(AssembleError::OperandWidthMismatch { line });
+    }
+
+    Ok(Item::Instruction {
+        opcode,
+        byte_mode,
+        lo_hi: halves.first().copied().unwrap_or(false),
+        destination,
+        source,
+    })
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let upper = text.to_ascii_uppercase();
+
+    let operand = |location: u16, extended: bool, payload: Payload| Operand {
+        location,
+        extended,
+        payload,
+        register_half: None,
+        short_immediate: false,
+        register_pair: false,
+    };
+
+    if text == "-" {
+        return Some(operand(0x0, false, Payload::None));
+    }
+
+    if let Some(value) = text.strip_prefix('#') {
+        return Some(Operand {
+            short_immediate: is_short_literal(value),
+            ..operand(0x0, false, Payload::Immediate(parse_value(value)?))
+        });
+    }
+
+    // Written high word first, the location is the high word's register
+    let pair = match upper.as_str() {
+        "A:B" => Some(0x1),
+        "B:C" => Some(0x2),
+        "C:D" => Some(0x3),
+        _ => None,
+    };
+
+    if let Some(location) = pair {
+        return Some(Operand {
+            register_pair: true,
+            ..operand(location, false, Payload::None)
+        });
+    }
+
+    if let Some((location, half)) = parse_register(&upper) {
+        return Some(Operand {
+            register_half: Some(half),
+            ..operand(location, false, Payload::None)
+        });
+    }
+
+    let Some(inner) = text.strip_prefix('[').and_then(|inner| inner.strip_suffix(']')) else {
+        return Some(operand(0x0, false, Payload::Relative(parse_value(&text)?)));
+    };
+
+    let inner_upper = &upper[1..upper.len() - 1];
+
+    match inner_upper {
+        "IDY+" => return Some(operand(0x1, true, Payload::None)),
+        "-IDY" => return Some(operand(0x2, true, Payload::None)),
+        "IDY+XL" => return Some(operand(0x5, true, Payload::None)),
+        "IDY+IDX" => return Some(operand(0xC, false, Payload::None)),
+        "[IDY]" => return Some(operand(0xD, false, Payload::None)),
+        "[IDY]+IDX" => return Some(operand(0xE, false, Payload::None)),
+        "[IDY+IDX]" => return Some(operand(0xF, false, Payload::None)),
+        _ => (),
+    }
+
+    if let Some(offset) = inner_upper.strip_prefix("PC+").or(inner_upper.strip_prefix("PC-")) {
+        let offset = &inner[inner.len() - offset.len()..];
+        let negative = inner_upper.starts_with("PC-");
+
+        return match parse_value(offset)? {
+            Value::Number(offset) if negative => Some(operand(0x7, true, Payload::Word(Value::Number(offset.wrapping_neg() & 0xFFFF)))),
+            Value::Number(offset) => Some(operand(0x7, true, Payload::Word(Value::Number(offset)))),
+            Value::Label(_) if negative => None,
+            label => Some(operand(0x7, true, Payload::ProgramCounterRelative(label))),
+        };
+    }
+
+    if inner_upper.starts_with("SP+") {
+        return Some(operand(0x3, true, Payload::Byte(parse_value(&inner[3..])?)));
+    }
+
+    if inner_upper.starts_with('[') {
+        if inner_upper.ends_with("]+IDX") {
+            return Some(operand(0xA, false, Payload::Word(parse_value(&inner[1..inner.len() - 5])?)));
+        }
+        if inner_upper.ends_with("+IDX]") {
+            return Some(operand(0xB, false, Payload::Word(parse_value(&inner[1..inner.len() - 5])?)));
+        }
+        if inner_upper.ends_with(']') {
+            return Some(operand(0x9, false, Payload::Word(parse_value(&inner[1..inner.len() - 1])?)));
+        }
+        return None;
+    }
+
+    if inner_upper.ends_with("+IDX") {
+        return Some(operand(0x8, false, Payload::Word(parse_value(&inner[..inner.len() - 4])?)));
+    }
+
+    if inner_upper.ends_with("+XL") {
+        return Some(operand(0x4, true, Payload::Word(parse_value(&inner[..inner.len() - 3])?)));
+    }
+
+    if is_short_literal(inner) {
+        return Some(operand(0x6, true, Payload::Byte(parse_value(inner)?)));
+    }
+
+    Some(operand(0x7, false, Payload::Word(parse_value(inner)?)))
+}
+
+// Returns the location nibble, and for byte registers whether it is the high half
+fn parse_register(upper: &str) -> Option<(u16, Option<bool>)> {
+    let registers = [("A", 0x1), ("B", 0x2), ("C", 0x3), ("D", 0x4), ("IDX", 0x5), ("IDY", 0x6)];
+
+    for (name, location) in registers {
+        let Some(suffix) = upper.strip_prefix(name) else {
+            continue;
+        };
+
+        match suffix {
+            "" => return Some((location, None)),
+            "L" => return Some((location, Some(false))),
+            "H" => return Some((location, Some(true))),
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(hex) = text.strip_prefix('$') {
+        return usize::from_str_radix(hex, 16).ok().map(Value::Number);
+    }
+
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        return text.parse().ok().map(Value::Number);
+    }
+
+    if is_identifier(text) {
+        return Some(Value::Label(text.to_string()));
+    }
+
+    None
+}
+
+fn is_short_literal(text: &str) -> bool {
+    text.strip_prefix('$').is_some_and(|hex| (1..=2).contains(&hex.len()))
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (label, rest) = text.split_once(':')?;
+
+    is_identifier(label).then_some((label, rest))
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return vec![];
+    }
+
+    text.split(',').map(str::trim).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    #[test]
+    fn encodes_immediates_and_byte_mode() {
+        assert_eq!(assemble("MOV A, #$1234").unwrap(), [0x00, 0x10, 0x34, 0x12]);
+        assert_eq!(assemble("MOV AL, #$80").unwrap(), [0x40, 0x10, 0x80]);
+        assert_eq!(assemble("STP").unwrap(), [0x30, 0x00]);
+    }
+
+    #[test]
+    fn labels_and_directives_resolve() {
+        let code = assemble("ORG $8000\nstart: MOV A, #data\nDB $01, $02\ndata: DW $BEEF").unwrap();
+
+        assert_eq!(code, [0x00, 0x10, 0x06, 0x80, 0x01, 0x02, 0xEF, 0xBE]);
+    }
+
+    // Everything the disassembler prints assembles back to the same bytes
+    #[test]
+    fn disassembly_reassembles_to_the_same_code() {
+        let source = "\
+            MOV A, #$1234
+            ADC [[$1000]], B
+            SBC AL, [$42]
+            MOV [IDY+], A
+            MOV B, [SP+$04]
+            XCHG [$0010], C
+            loop: DBNZ D, loop
+            BGT loop
+            ADCL A:B, C:D
+            MOVNZ A, B
+            LEA A, [PC+$0010]
+            JTB [$9000], A
+            SWAP BL
+            NOP
+            STP";
+        let code = assemble(source).unwrap();
+        let cpu = MachineBuilder::new().rom(0x8000, 0x8000).program(0x8000, &code).build().unwrap();
+
+        let lines: Vec<String> = cpu.disassemble_range(0x8000, source.lines().count()).into_iter().map(|(_, line)| line).collect();
+
+        assert_eq!(assemble(&lines.join("\n")).unwrap(), code, "{lines:#?}");
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(assemble("NOP\nFOO A"), Err(AssembleError::UnknownMnemonic { line: 2, mnemonic: "FOO".to_string() }));
+        assert_eq!(assemble("BGT nowhere"), Err(AssembleError::UndefinedLabel { line: 1, label: "nowhere".to_string() }));
+        assert_eq!(assemble("a: NOP\na: NOP"), Err(AssembleError::DuplicateLabel { line: 2, label: "a".to_string() }));
+        assert_eq!(assemble("MOV AL, B"), Err(AssembleError::OperandWidthMismatch { line: 1 }));
+        assert_eq!(assemble("MOV A"), Err(AssembleError::OperandCount { line: 1, expected: 2, actual: 1 }));
+        assert_eq!(assemble("ORG $10\nORG $08"), Err(AssembleError::OrgBackwards { line: 2, address: 0x08 }));
+    }
+}
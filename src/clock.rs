@@ -0,0 +1,198 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+// Falling further behind than this is forgiven rather than caught up in a burst, e.g. after a debugger pause
+const MAX_LAG_NANOS: i128 = 100_000_000;
+
+pub trait TimeSource {
+    // Time elapsed since some fixed point, only differences between calls matter
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+pub struct SystemTimeSource {
+    origin: Instant,
+}
+
+impl SystemTimeSource {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemTimeSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Paces execution to a target frequency by sleeping off the time the consumed cycles should have taken
+pub struct Clock<T: TimeSource = SystemTimeSource> {
+    frequency: u64,
+    time_source: T,
+    start: Option<(Duration, u64)>, // Time and cycle count of the first throttle, for the effective frequency
+    last: Option<(Duration, u64)>,
+    debt: i128, // Nanoseconds ahead of real time, negative when running behind
+    remainder: u128, // Fractional nanoseconds carried between throttles so rounding doesn't drift
+}
+
+impl Clock<SystemTimeSource> {
+    pub fn new(frequency: u64) -> Self {
+        Self::with_time_source(frequency, SystemTimeSource::new())
+    }
+}
+
+impl<T: TimeSource> Clock<T> {
+    pub fn with_time_source(frequency: u64, time_source: T) -> Self {
+        assert!(frequency > 0, "Clock frequency should be non-zero");
+
+        Self {
+            frequency,
+            time_source,
+            start: None,
+            last: None,
+            debt: 0,
+            remainder: 0,
+        }
+    }
+
+    pub fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    pub fn time_source(&self) -> &T {
+        &self.time_source
+    }
+
+    // Call after executing with the CPU's total cycle count, sleeps if execution is ahead of real time
+    pub fn throttle(&mut self, cycles: u64) {
+        let now = self.time_source.now();
+
+        let Some((last_time, last_cycles)) = self.last else {
+            self.start = Some((now, cycles));
+            self.last = Some((now, cycles));
+
+            return;
+        };
+
+        let owed = cycles.wrapping_sub(last_cycles) as u128 * NANOS_PER_SECOND + self.remainder;
+
+        self.remainder = owed % self.frequency as u128;
+
+        // Elapsed time includes any previous sleep, so oversleeping is paid back here
+        self.debt += (owed / self.frequency as u128) as i128 - now.saturating_sub(last_time).as_nanos() as i128;
+
+        self.debt = self.debt.max(-MAX_LAG_NANOS);
+
+        self.last = Some((now, cycles));
+
+        if self.debt > 0 {
+            self.time_source.sleep(Duration::from_nanos(self.debt as u64));
+        }
+    }
+
+    // Effective frequency in MHz from the first throttle up to now, 0.0 until any time has passed
+    pub fn effective_mhz(&self) -> f64 {
+        let (Some((start_time, start_cycles)), Some((_, last_cycles))) = (self.start, self.last) else {
+            return 0.0;
+        };
+
+        let elapsed = self.time_source.now().saturating_sub(start_time).as_secs_f64();
+
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+
+        last_cycles.wrapping_sub(start_cycles) as f64 / elapsed / 1_000_000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    // Time only moves when the clock sleeps or the test advances it
+    struct MockTimeSource {
+        now: Cell<Duration>,
+        sleeps: Vec<Duration>,
+    }
+
+    impl MockTimeSource {
+        fn new() -> Self {
+            Self {
+                now: Cell::new(Duration::ZERO),
+                sleeps: vec![],
+            }
+        }
+    }
+
+    impl TimeSource for MockTimeSource {
+        fn now(&self) -> Duration {
+            self.now.get()
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.sleeps.push(duration);
+            self.now.set(self.now.get() + duration);
+        }
+    }
+
+    #[test]
+    fn sleeps_off_the_time_cycles_should_take() {
+        let mut clock = Clock::with_time_source(1000, MockTimeSource::new());
+
+        clock.throttle(0);
+        clock.throttle(500);
+
+        assert_eq!(clock.time_source().sleeps, [Duration::from_millis(500)]);
+
+        clock.throttle(1000);
+
+        assert!((clock.effective_mhz() - 0.001).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rounding_remainders_carry_over() {
+        let mut clock = Clock::with_time_source(3, MockTimeSource::new());
+
+        for cycles in 0..=3 {
+            clock.throttle(cycles);
+        }
+
+        let sleeps: Vec<u128> = clock.time_source().sleeps.iter().map(Duration::as_nanos).collect();
+
+        assert_eq!(sleeps, [333_333_333, 333_333_333, 333_333_334]);
+    }
+
+    #[test]
+    fn lag_is_forgiven_rather_than_caught_up() {
+        let mut clock = Clock::with_time_source(1000, MockTimeSource::new());
+
+        clock.throttle(0);
+        clock.time_source.now.set(Duration::from_secs(10));
+        clock.throttle(1000);
+
+        assert!(clock.time_source().sleeps.is_empty());
+
+        // Still 100 ms behind, so a second's worth of cycles only sleeps for 900 ms
+        clock.throttle(2000);
+
+        assert_eq!(clock.time_source().sleeps, [Duration::from_millis(900)]);
+    }
+}
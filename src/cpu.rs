@@ -1,34 +1,298 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
 use core::panic;
 
-use crate::memory::MemoryController;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::devices::RegisterWindow;
+use crate::encode;
+use crate::memory::{ADDRESS_SPACE, MemoryController, MemoryError, MemorySnapshot};
+
+mod history;
+#[cfg(test)]
+mod tests;
+
+pub use history::*;
 
 pub const ADDRESS_BUS_WIDTH: u32 = 16;
+// Default vector locations
 pub const RESET_VECTOR: usize = 0xFFFE;
 pub const NMI_VECTOR: usize = 0xFFFC;
 pub const IRQ_VECTOR: usize = 0xFFFA;
 
+// Status register bit masks
+pub const SIGN_FLAG: u8 = 0x80;
+pub const ZERO_FLAG: u8 = 0x40;
+pub const PARITY_FLAG: u8 = 0x20;
+pub const CARRY_FLAG: u8 = 0x10;
+pub const OVERFLOW_FLAG: u8 = 0x08;
+pub const INTERRUPT_DISABLE_FLAG: u8 = 0x04;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flag {
+    Sign,
+    Zero,
+    Parity,
+    Carry,
+    Overflow,
+    InterruptDisable,
+}
+
+impl Flag {
+    pub const ALL: [Self; 6] = [Self::Sign, Self::Zero, Self::Parity, Self::Carry, Self::Overflow, Self::InterruptDisable];
+
+    // The flag's bit in the status register
+    pub fn mask(self) -> u8 {
+        match self {
+            Self::Sign => SIGN_FLAG,
+            Self::Zero => ZERO_FLAG,
+            Self::Parity => PARITY_FLAG,
+            Self::Carry => CARRY_FLAG,
+            Self::Overflow => OVERFLOW_FLAG,
+            Self::InterruptDisable => INTERRUPT_DISABLE_FLAG,
+        }
+    }
+
+    fn letter(self) -> char {
+        match self {
+            Self::Sign => 'S',
+            Self::Zero => 'Z',
+            Self::Parity => 'P',
+            Self::Carry => 'C',
+            Self::Overflow => 'O',
+            Self::InterruptDisable => 'I',
+        }
+    }
+}
+
+// Renders the status register most significant bit first, a letter for each set flag and `-` for clear flags and unused bits,
+// e.g. `S--C----` for sign and carry
+pub fn decode_status(status: u8) -> String {
+    (0..8)
+        .rev()
+        .map(|bit| {
+            let mask = 1 << bit;
+
+            match Flag::ALL.iter().find(|flag| flag.mask() == mask) {
+                Some(flag) if status & mask != 0 => flag.letter(),
+                _ => '-',
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
 enum Operation {
     Mov,
     Adc,
     Sbc,
+    Xchg,
+    Bit,
+    Inx,
+    Dex,
+    Iny,
+    Dey,
+    Swap,
+    Jtb,
+    Dbnz,
+    Swp,
+    Cmp,
+    Adcl,
+    Sbcl,
+    Bgt,
+    Blt,
+    Bge,
+    Ble,
+    Bhi,
+    Blo,
+    Bhs,
+    Bls,
+    AddSat,
+    SubSat,
+    Min,
+    Max,
+    Lea,
+    Clz,
+    Popcnt,
+    Syscall,
+    MovIf,
     Stp,
     Rst,
+    Wai,
     Nop,
+    Ext,
+    Illegal,
 }
 
 impl Operation {
+    // In opcode order, the index of each is its discriminant
+    const ALL: [Self; 39] = [
+        Self::Mov,
+        Self::Adc,
+        Self::Sbc,
+        Self::Xchg,
+        Self::Bit,
+        Self::Inx,
+        Self::Dex,
+        Self::Iny,
+        Self::Dey,
+        Self::Swap,
+        Self::Jtb,
+        Self::Dbnz,
+        Self::Swp,
+        Self::Cmp,
+        Self::Adcl,
+        Self::Sbcl,
+        Self::Bgt,
+        Self::Blt,
+        Self::Bge,
+        Self::Ble,
+        Self::Bhi,
+        Self::Blo,
+        Self::Bhs,
+        Self::Bls,
+        Self::AddSat,
+        Self::SubSat,
+        Self::Min,
+        Self::Max,
+        Self::Lea,
+        Self::Clz,
+        Self::Popcnt,
+        Self::Syscall,
+        Self::MovIf,
+        Self::Stp,
+        Self::Rst,
+        Self::Wai,
+        Self::Nop,
+        Self::Ext,
+        Self::Illegal,
+    ];
+
     fn get_operation_from_instruction(instruction: u16) -> Self {
-        let operation = instruction & 0x003F;
+        let operation = instruction & encode::OPERATION_MASK;
 
         match operation {
-            0x00 => Self::Mov,
-            0x01 => Self::Adc,
-            0x02 => Self::Sbc,
-            0x30 => Self::Stp,
-            0x31 => Self::Rst,
-            _ => Self::Nop,
+            encode::OPCODE_MOV => Self::Mov,
+            encode::OPCODE_ADC => Self::Adc,
+            encode::OPCODE_SBC => Self::Sbc,
+            encode::OPCODE_XCHG => Self::Xchg,
+            encode::OPCODE_BIT => Self::Bit,
+            encode::OPCODE_INX => Self::Inx,
+            encode::OPCODE_DEX => Self::Dex,
+            encode::OPCODE_INY => Self::Iny,
+            encode::OPCODE_DEY => Self::Dey,
+            encode::OPCODE_SWAP => Self::Swap,
+            encode::OPCODE_JTB => Self::Jtb,
+            encode::OPCODE_DBNZ => Self::Dbnz,
+            encode::OPCODE_SWP => Self::Swp,
+            encode::OPCODE_CMP => Self::Cmp,
+            encode::OPCODE_ADCL => Self::Adcl,
+            encode::OPCODE_SBCL => Self::Sbcl,
+            encode::OPCODE_BGT => Self::Bgt,
+            encode::OPCODE_BLT => Self::Blt,
+            encode::OPCODE_BGE => Self::Bge,
+            encode::OPCODE_BLE => Self::Ble,
+            encode::OPCODE_BHI => Self::Bhi,
+            encode::OPCODE_BLO => Self::Blo,
+            encode::OPCODE_BHS => Self::Bhs,
+            encode::OPCODE_BLS => Self::Bls,
+            encode::OPCODE_ADDSAT => Self::AddSat,
+            encode::OPCODE_SUBSAT => Self::SubSat,
+            encode::OPCODE_MIN => Self::Min,
+            encode::OPCODE_MAX => Self::Max,
+            encode::OPCODE_LEA => Self::Lea,
+            encode::OPCODE_CLZ => Self::Clz,
+            encode::OPCODE_POPCNT => Self::Popcnt,
+            encode::OPCODE_SYSCALL => Self::Syscall,
+            encode::OPCODE_MOVIF..=encode::OPCODE_MOVIF_LAST => Self::MovIf,
+            encode::OPCODE_STP => Self::Stp,
+            encode::OPCODE_RST => Self::Rst,
+            encode::OPCODE_WAI => Self::Wai,
+            encode::OPCODE_NOP => Self::Nop, // The one defined no-op, other unassigned opcodes are illegal
+            encode::OPCODE_EXT => Self::Ext,
+            _ => Self::Illegal,
         }
     }
+
+    // Whether the operation takes a destination and a source operand
+    fn operands(&self) -> (bool, bool) {
+        match self {
+            Self::Mov | Self::Adc | Self::Sbc | Self::Xchg | Self::Bit | Self::Jtb | Self::Dbnz | Self::Swp | Self::Cmp
+            | Self::AddSat | Self::SubSat | Self::Min | Self::Max | Self::Lea | Self::Clz | Self::Popcnt | Self::MovIf => (true, true),
+            Self::Swap => (true, false),
+            Self::Bgt | Self::Blt | Self::Bge | Self::Ble | Self::Bhi | Self::Blo | Self::Bhs | Self::Bls => (false, true),
+            Self::Inx | Self::Dex | Self::Iny | Self::Dey | Self::Syscall | Self::Stp | Self::Rst | Self::Wai | Self::Nop | Self::Ext | Self::Illegal => (false, false),
+            // Register pairs are named by the location nibbles but have no operand bytes
+            Self::Adcl | Self::Sbcl => (false, false),
+        }
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Mov => "MOV",
+            Self::Adc => "ADC",
+            Self::Sbc => "SBC",
+            Self::Xchg => "XCHG",
+            Self::Bit => "BIT",
+            Self::Inx => "INX",
+            Self::Dex => "DEX",
+            Self::Iny => "INY",
+            Self::Dey => "DEY",
+            Self::Swap => "SWAP",
+            Self::Jtb => "JTB",
+            Self::Dbnz => "DBNZ",
+            Self::Swp => "SWP",
+            Self::Cmp => "CMP",
+            Self::Adcl => "ADCL",
+            Self::Sbcl => "SBCL",
+            Self::Bgt => "BGT",
+            Self::Blt => "BLT",
+            Self::Bge => "BGE",
+            Self::Ble => "BLE",
+            Self::Bhi => "BHI",
+            Self::Blo => "BLO",
+            Self::Bhs => "BHS",
+            Self::Bls => "BLS",
+            Self::AddSat => "ADDSAT",
+            Self::SubSat => "SUBSAT",
+            Self::Min => "MIN",
+            Self::Max => "MAX",
+            Self::Lea => "LEA",
+            Self::Clz => "CLZ",
+            Self::Popcnt => "POPCNT",
+            Self::Syscall => "SYSCALL",
+            Self::MovIf => "MOVIF", // Disassembled with its condition, see move_if_mnemonic
+            Self::Stp => "STP",
+            Self::Rst => "RST",
+            Self::Wai => "WAI",
+            Self::Nop => "NOP",
+            Self::Ext => "EXT",
+            Self::Illegal => "ILLEGAL",
+        }
+    }
+}
+
+// The assembler's mnemonic for a MOVIF opcode, which names its condition
+fn move_if_mnemonic(instruction: u16) -> &'static str {
+    let condition = instruction & encode::CONDITION_MASK;
+    let negated = condition & encode::CONDITION_NEGATE != 0;
+
+    match (condition & !encode::CONDITION_NEGATE, negated) {
+        (encode::CONDITION_ZERO, false) => "MOVZ",
+        (encode::CONDITION_ZERO, true) => "MOVNZ",
+        (encode::CONDITION_CARRY, false) => "MOVC",
+        (encode::CONDITION_CARRY, true) => "MOVNC",
+        (encode::CONDITION_SIGN, false) => "MOVS",
+        (encode::CONDITION_SIGN, true) => "MOVNS",
+        (_, false) => "MOVO",
+        (_, true) => "MOVNO",
+    }
 }
 
 enum Location {
@@ -48,58 +312,408 @@ enum Location {
     IndirectPointer,
     IndirectIndexedPointer,
     IndexedIndirectPointer,
+    PostIncrementPointer,
+    PreDecrementPointer,
+    StackRelative,
+    ByteIndexedAddress,
+    ByteIndexedPointer,
+    ZeroPage,
+    ProgramCounterRelative,
 }
 
 impl Location {
-    fn get_destination_from_instruction(instruction: u16) -> Self {
-        let destination = (instruction & 0xF000) >> 12;
+    // A non-zero nibble in an extension prefix replaces the instruction's own location with an extended one
+    fn get_extended_location(location: u16) -> Option<Self> {
+        match location {
+            encode::EXTENDED_POST_INCREMENT_POINTER => Some(Self::PostIncrementPointer),
+            encode::EXTENDED_PRE_DECREMENT_POINTER => Some(Self::PreDecrementPointer),
+            encode::EXTENDED_STACK_RELATIVE => Some(Self::StackRelative),
+            encode::EXTENDED_BYTE_INDEXED_ADDRESS => Some(Self::ByteIndexedAddress),
+            encode::EXTENDED_BYTE_INDEXED_POINTER => Some(Self::ByteIndexedPointer),
+            encode::EXTENDED_ZERO_PAGE => Some(Self::ZeroPage),
+            encode::EXTENDED_PROGRAM_COUNTER_RELATIVE => Some(Self::ProgramCounterRelative),
+            _ => None,
+        }
+    }
+
+    // 32-bit operands are a register holding the high word and the register after it holding the low word, A:B, B:C or C:D
+    fn register_pair(&self) -> Option<(Register, Register)> {
+        match self {
+            Self::A => Some((Register::A, Register::B)),
+            Self::B => Some((Register::B, Register::C)),
+            Self::C => Some((Register::C, Register::D)),
+            _ => None,
+        }
+    }
+
+    fn register_pair_name(&self) -> Option<&'static str> {
+        match self {
+            Self::A => Some("A:B"),
+            Self::B => Some("B:C"),
+            Self::C => Some("C:D"),
+            _ => None,
+        }
+    }
+
+    // Bytes the location fetches from the instruction stream
+    fn operand_bytes(&self, is_source: bool, byte_mode: bool) -> usize {
+        match self {
+            Self::Immediate => {
+                if !is_source {
+                    0 // Discarded destinations have no operand
+                } else if byte_mode {
+                    1
+                } else {
+                    2
+                }
+            },
+            Self::Address | Self::IndexedAddress | Self::IndirectAddress | Self::IndirectIndexedAddress | Self::IndexedIndirectAddress
+            | Self::ByteIndexedAddress | Self::ProgramCounterRelative => 2,
+            Self::StackRelative | Self::ZeroPage => 1,
+            Self::A | Self::B | Self::C | Self::D | Self::Idx | Self::Idy
+            | Self::IndexedPointer | Self::IndirectPointer | Self::IndirectIndexedPointer | Self::IndexedIndirectPointer
+            | Self::PostIncrementPointer | Self::PreDecrementPointer | Self::ByteIndexedPointer => 0,
+        }
+    }
+
+    fn get_destination_from_instruction(instruction: u16, extension: u16) -> Self {
+        if let Some(location) = Self::get_extended_location((extension >> encode::DESTINATION_SHIFT) & encode::LOCATION_MASK) {
+            return location;
+        }
+
+        let destination = (instruction >> encode::DESTINATION_SHIFT) & encode::LOCATION_MASK;
 
         match destination {
-            0x0 => Self::Immediate,
-            0x1 => Self::A,
-            0x2 => Self::B,
-            0x3 => Self::C,
-            0x4 => Self::D,
-            0x5 => Self::Idx,
-            0x6 => Self::Idy,
-            0x7 => Self::Address,
-            0x8 => Self::IndexedAddress,
-            0x9 => Self::IndirectAddress,
-            0xA => Self::IndirectIndexedAddress,
-            0xB => Self::IndexedIndirectAddress,
-            0xC => Self::IndexedPointer,
-            0xD => Self::IndirectPointer,
-            0xE => Self::IndirectIndexedPointer,
-            0xF => Self::IndexedIndirectPointer,
+            encode::LOCATION_IMMEDIATE => Self::Immediate,
+            encode::LOCATION_A => Self::A,
+            encode::LOCATION_B => Self::B,
+            encode::LOCATION_C => Self::C,
+            encode::LOCATION_D => Self::D,
+            encode::LOCATION_IDX => Self::Idx,
+            encode::LOCATION_IDY => Self::Idy,
+            encode::LOCATION_ADDRESS => Self::Address,
+            encode::LOCATION_INDEXED_ADDRESS => Self::IndexedAddress,
+            encode::LOCATION_INDIRECT_ADDRESS => Self::IndirectAddress,
+            encode::LOCATION_INDIRECT_INDEXED_ADDRESS => Self::IndirectIndexedAddress,
+            encode::LOCATION_INDEXED_INDIRECT_ADDRESS => Self::IndexedIndirectAddress,
+            encode::LOCATION_INDEXED_POINTER => Self::IndexedPointer,
+            encode::LOCATION_INDIRECT_POINTER => Self::IndirectPointer,
+            encode::LOCATION_INDIRECT_INDEXED_POINTER => Self::IndirectIndexedPointer,
+            encode::LOCATION_INDEXED_INDIRECT_POINTER => Self::IndexedIndirectPointer,
             _ => panic!("Illegal destination value: {}", destination),
         }
     }
 
-    fn get_source_from_instruction(instruction: u16) -> Self {
-        let source = (instruction & 0x0F00) >> 8;
+    fn get_source_from_instruction(instruction: u16, extension: u16) -> Self {
+        if let Some(location) = Self::get_extended_location((extension >> encode::SOURCE_SHIFT) & encode::LOCATION_MASK) {
+            return location;
+        }
+
+        let source = (instruction >> encode::SOURCE_SHIFT) & encode::LOCATION_MASK;
 
         match source {
-            0x0 => Self::Immediate,
-            0x1 => Self::A,
-            0x2 => Self::B,
-            0x3 => Self::C,
-            0x4 => Self::D,
-            0x5 => Self::Idx,
-            0x6 => Self::Idy,
-            0x7 => Self::Address,
-            0x8 => Self::IndexedAddress,
-            0x9 => Self::IndirectAddress,
-            0xA => Self::IndirectIndexedAddress,
-            0xB => Self::IndexedIndirectAddress,
-            0xC => Self::IndexedPointer,
-            0xD => Self::IndirectPointer,
-            0xE => Self::IndirectIndexedPointer,
-            0xF => Self::IndexedIndirectPointer,
+            encode::LOCATION_IMMEDIATE => Self::Immediate,
+            encode::LOCATION_A => Self::A,
+            encode::LOCATION_B => Self::B,
+            encode::LOCATION_C => Self::C,
+            encode::LOCATION_D => Self::D,
+            encode::LOCATION_IDX => Self::Idx,
+            encode::LOCATION_IDY => Self::Idy,
+            encode::LOCATION_ADDRESS => Self::Address,
+            encode::LOCATION_INDEXED_ADDRESS => Self::IndexedAddress,
+            encode::LOCATION_INDIRECT_ADDRESS => Self::IndirectAddress,
+            encode::LOCATION_INDIRECT_INDEXED_ADDRESS => Self::IndirectIndexedAddress,
+            encode::LOCATION_INDEXED_INDIRECT_ADDRESS => Self::IndexedIndirectAddress,
+            encode::LOCATION_INDEXED_POINTER => Self::IndexedPointer,
+            encode::LOCATION_INDIRECT_POINTER => Self::IndirectPointer,
+            encode::LOCATION_INDIRECT_INDEXED_POINTER => Self::IndirectIndexedPointer,
+            encode::LOCATION_INDEXED_INDIRECT_POINTER => Self::IndexedIndirectPointer,
             _ => panic!("Illegal source value: {}", source),
         }
     }
 }
 
+// Bytes the instruction and its operands occupy, an extension prefix on its own is a 2 byte instruction
+pub fn instruction_length(instruction: u16) -> usize {
+    2 + operand_bytes(0x0000, instruction)
+}
+
+// Bytes occupied by an instruction behind an extension prefix, including the prefix
+pub fn extended_instruction_length(extension: u16, instruction: u16) -> usize {
+    4 + operand_bytes(extension, instruction)
+}
+
+fn operand_bytes(extension: u16, instruction: u16) -> usize {
+    let operation = Operation::get_operation_from_instruction(instruction);
+    let byte_mode = (instruction & encode::BYTE_MODE_BIT) != 0;
+    let (has_destination, has_source) = operation.operands();
+
+    let mut bytes = 0;
+
+    if has_source {
+        bytes += Location::get_source_from_instruction(instruction, extension).operand_bytes(true, byte_mode);
+    }
+
+    if has_destination {
+        bytes += Location::get_destination_from_instruction(instruction, extension).operand_bytes(false, byte_mode);
+    }
+
+    bytes
+}
+
+#[derive(Clone, Copy)]
+enum Register {
+    A,
+    B,
+    C,
+    D,
+    Idx,
+    Idy,
+}
+
+// A location after its operand bytes have been fetched and its effective address computed
+#[derive(Clone, Copy)]
+enum Operand {
+    Discard, // Immediate destination, writes go nowhere
+    Immediate(u16),
+    Register(Register),
+    Memory(usize),
+}
+
+// Summary of a single call to process, for debuggers that want to show what just happened
+#[derive(Clone, Copy, Debug)]
+pub struct StepInfo {
+    pub program_counter: u16, // Program counter before the step
+    pub operation: Option<&'static str>, // None if no instruction was executed
+    pub bytes: usize, // Bytes fetched from the instruction stream
+    pub interrupt: Option<Interrupt>, // Interrupt that was serviced, an IRQ that isn't listed here is still pending
+    pub stuck: bool, // The instruction left every register and flag as it found them and wrote no memory, so it will run again forever
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptEventKind {
+    Raised, // The line went active
+    Serviced, // The CPU jumped to the handler
+    Acknowledged, // Logged by the host when the source is cleared
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterruptEvent {
+    pub cycle: u64, // Value of cycles() for the step it happened in
+    pub interrupt: Interrupt,
+    pub kind: InterruptEventKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TraceEntry {
+    pub program_counter: u16,
+    pub instruction: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    pub index_x: u16,
+    pub index_y: u16,
+    pub status: u8,
+    pub a: u16,
+    pub b: u16,
+    pub c: u16,
+    pub d: u16,
+}
+
+impl Registers {
+    pub fn diff(&self, new: &Registers) -> RegistersDiff {
+        RegistersDiff::new(self, new)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub name: &'static str, // Register name as print_state labels it, or a flag's letter followed by F
+    pub old: u16,
+    pub new: u16,
+}
+
+// Fields that differ between two sets of registers, the status register is compared flag by flag
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegistersDiff {
+    pub changes: Vec<RegisterChange>,
+}
+
+impl RegistersDiff {
+    pub fn new(old: &Registers, new: &Registers) -> Self {
+        let registers = [
+            ("PC", old.program_counter, new.program_counter),
+            ("SP", old.stack_pointer, new.stack_pointer),
+            ("IDX", old.index_x, new.index_x),
+            ("IDY", old.index_y, new.index_y),
+            ("A", old.a, new.a),
+            ("B", old.b, new.b),
+            ("C", old.c, new.c),
+            ("D", old.d, new.d),
+        ];
+
+        let flags = [
+            ("SF", SIGN_FLAG),
+            ("ZF", ZERO_FLAG),
+            ("PF", PARITY_FLAG),
+            ("CF", CARRY_FLAG),
+            ("OF", OVERFLOW_FLAG),
+            ("IF", INTERRUPT_DISABLE_FLAG),
+        ];
+
+        let mut changes: Vec<RegisterChange> = registers.into_iter()
+            .filter(|(_, old, new)| old != new)
+            .map(|(name, old, new)| RegisterChange { name, old, new })
+            .collect();
+
+        for (name, mask) in flags {
+            if (old.status ^ new.status) & mask != 0 {
+                changes.push(RegisterChange {
+                    name,
+                    old: (old.status & mask != 0) as u16,
+                    new: (new.status & mask != 0) as u16,
+                });
+            }
+        }
+
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changed(&self) -> Vec<&'static str> {
+        self.changes.iter().map(|change| change.name).collect()
+    }
+
+    // Panics listing the actual changes unless exactly the named registers and flags changed, in any order
+    #[track_caller]
+    pub fn assert_changed(&self, expected: &[&str]) {
+        let mut changed = self.changed();
+        let mut expected = expected.to_vec();
+
+        changed.sort_unstable();
+        expected.sort_unstable();
+
+        assert!(changed == expected, "Expected changes to {:?} but got: {}", expected, self);
+    }
+}
+
+impl fmt::Display for RegistersDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        for (i, change) in self.changes.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{}: 0x{:04X} -> 0x{:04X}", change.name, change.old, change.new)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Executions of each operation while profiling
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileCounts {
+    operations: [u64; Operation::ALL.len()],
+}
+
+// Arrays only derive Default up to 32 elements
+impl Default for ProfileCounts {
+    fn default() -> Self {
+        Self {
+            operations: [0; Operation::ALL.len()],
+        }
+    }
+}
+
+impl ProfileCounts {
+    // Executions of the operation with this mnemonic, 0 for unknown mnemonics
+    pub fn count(&self, mnemonic: &str) -> u64 {
+        self.iter()
+            .find(|(operation, _)| operation.eq_ignore_ascii_case(mnemonic))
+            .map_or(0, |(_, count)| count)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.operations.iter().sum()
+    }
+
+    // Every operation's mnemonic and count in opcode order, including those never executed
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        Operation::ALL.iter().map(|operation| (operation.mnemonic(), self.operations[*operation as usize]))
+    }
+
+    fn record(&mut self, operation: Operation) {
+        self.operations[operation as usize] += 1;
+    }
+}
+
+// A push or pop the CPU halted on because it would have left the stack bounds, with the stack pointer before it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackFault {
+    Overflow { stack_pointer: u16 },
+    Underflow { stack_pointer: u16 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    OutOfRange { index: u8 },
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfRange { index } => write!(f, "Register {} is out of range", index),
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+// An instruction the CPU halted on because it has no defined operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalInstruction {
+    pub program_counter: u16,
+    pub instruction: u16,
+}
+
+// Full machine state for save states
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    pub enable: bool,
+    pub waiting_for_interrupt: bool,
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    pub index_x: u16,
+    pub index_y: u16,
+    pub status: u8,
+    pub a: u16,
+    pub b: u16,
+    pub c: u16,
+    pub d: u16,
+    pub memory: MemorySnapshot,
+}
+
+// Called with the CPU for each SYSCALL
+pub type SyscallHandler = Box<dyn FnMut(&mut CPU)>;
+
 pub struct CPU {
     pub enable: bool,
     pub waiting_for_interrupt: bool,
@@ -113,6 +727,55 @@ pub struct CPU {
     pub b: u16,
     pub c: u16,
     pub d: u16,
+    reset_vector: usize,
+    nmi_vector: usize,
+    irq_vector: usize,
+    fetched_bytes: usize,
+    cycles: u64,
+    trace: VecDeque<TraceEntry>,
+    trace_capacity: usize, // Tracing is disabled when 0
+    interrupt_log: VecDeque<InterruptEvent>,
+    interrupt_log_capacity: usize, // Logging is disabled when 0
+    interrupt_lines: (bool, Option<u8>), // NMI and IRQ of the previous step, NMI is serviced and logged as raised on its rising edge
+    on_halt: Option<Box<dyn FnMut()>>,
+    syscall_handler: Option<SyscallHandler>,
+    history: Option<History>,
+    profile: ProfileCounts,
+    profiling: bool,
+    trap_illegal_instructions: bool, // Undefined operations halt instead of executing as NOP
+    stack_top: Option<u16>, // Where reset puts the stack pointer, None for the end of the lowest RAM
+    illegal_instruction: Option<IllegalInstruction>,
+    execute_guard: bool, // Fetching an instruction from a non-executable mapping halts
+    execute_violation: Option<u16>,
+    stack_bounds: Option<(u16, u16)>, // Lowest address an entry may occupy and the stack pointer when the stack is empty
+    stack_fault: Option<StackFault>,
+    halt_when_stuck: bool,
+    register_window: Option<usize>, // Mapping index of the RegisterWindow shadowing PC and SP
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CPU {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CPU")
+            .field("enable", &self.enable)
+            .field("waiting_for_interrupt", &self.waiting_for_interrupt)
+            .field("program_counter", &format_args!("0x{:04X}", self.program_counter))
+            .field("stack_pointer", &format_args!("0x{:04X}", self.stack_pointer))
+            .field("index_x", &format_args!("0x{:04X}", self.index_x))
+            .field("index_y", &format_args!("0x{:04X}", self.index_y))
+            .field("status", &format_args!("0b{:08b}", self.status))
+            .field("a", &format_args!("0x{:04X}", self.a))
+            .field("b", &format_args!("0x{:04X}", self.b))
+            .field("c", &format_args!("0x{:04X}", self.c))
+            .field("d", &format_args!("0x{:04X}", self.d))
+            .field("memory_controller", &self.memory_controller)
+            .finish()
+    }
 }
 
 impl CPU {
@@ -130,15 +793,48 @@ impl CPU {
             b: 0x0000,
             c: 0x0000,
             d: 0x0000,
+            reset_vector: RESET_VECTOR,
+            nmi_vector: NMI_VECTOR,
+            irq_vector: IRQ_VECTOR,
+            fetched_bytes: 0,
+            cycles: 0,
+            trace: VecDeque::new(),
+            trace_capacity: 0,
+            interrupt_log: VecDeque::new(),
+            interrupt_log_capacity: 0,
+            interrupt_lines: (false, None),
+            on_halt: None,
+            syscall_handler: None,
+            history: None,
+            profile: ProfileCounts::default(),
+            profiling: false,
+            trap_illegal_instructions: false,
+            stack_top: None,
+            illegal_instruction: None,
+            execute_guard: false,
+            execute_violation: None,
+            stack_bounds: None,
+            stack_fault: None,
+            halt_when_stuck: false,
+            register_window: None,
         }
     }
 
+    // Cold reset, devices are reset too so RAM is cleared
     pub fn reset(&mut self) {
+        self.memory_controller.reset();
+        self.reset_warm();
+    }
+
+    // Re-vectors the CPU and clears its registers but leaves memory and devices as they are
+    pub fn reset_warm(&mut self) {
         self.enable = true;
         self.waiting_for_interrupt = false;
-        self.memory_controller.reset();
-        self.program_counter = self.memory_controller.read16(RESET_VECTOR);
-        self.stack_pointer = 0x0000;
+        self.illegal_instruction = None;
+        self.execute_violation = None;
+        self.stack_fault = None;
+        self.program_counter = self.memory_controller.read16(self.reset_vector);
+        self.stack_pointer = self.stack_top();
         self.index_x = 0x0000;
         self.index_y = 0x0000;
         self.status = 0b0000_0000;
@@ -146,1336 +842,2016 @@ impl CPU {
         self.b = 0x0000;
         self.c = 0x0000;
         self.d = 0x0000;
-    }
 
-    pub fn print_state(&self) {
-        println!("PC: 0x{:04X}", self.program_counter);
-        println!("SP: 0x{:04X}", self.stack_pointer);
-        println!("IDX: 0x{:04X}", self.index_x);
-        println!("IDY: 0x{:04X}", self.index_y);
-        println!("FLG: 0b{:08b}", self.status);
-        println!("A: 0x{:04X}", self.a);
-        println!("B: 0x{:04X}", self.b);
-        println!("C: 0x{:04X}", self.c);
-        println!("D: 0x{:04X}", self.d);
+        self.update_register_window();
     }
 
-    pub fn process(&mut self, nmi: bool, irq: Option<u8>) {
-        if !self.enable {
-            return;
+    // Keeps the RegisterWindow at this mapping index in step with the program counter and stack pointer, None detaches it
+    pub fn set_register_window(&mut self, mapping_index: Option<usize>) -> Result<(), MemoryError> {
+        if let Some(mapping_index) = mapping_index {
+            self.memory_controller.get_device_as::<RegisterWindow>(mapping_index)?;
         }
 
-        if self.waiting_for_interrupt {
-            if nmi {
-                self.waiting_for_interrupt = false;
+        self.register_window = mapping_index;
 
-                self.set_interrupt_disable_flag(true);
+        self.update_register_window();
 
-                self.program_counter = self.memory_controller.read16(NMI_VECTOR);
-            }
+        Ok(())
+    }
 
-            if let Some(irq_code) = irq {
-                self.waiting_for_interrupt = false;
+    // Does nothing if the window has since been unmapped
+    fn update_register_window(&mut self) {
+        let Some(mapping_index) = self.register_window else {
+            return;
+        };
 
-                if self.get_interrupt_disable_flag() {
-                    return;
-                }
+        let (program_counter, stack_pointer) = (self.program_counter, self.stack_pointer);
 
-                self.set_interrupt_disable_flag(true);
+        if let Ok(window) = self.memory_controller.get_device_as_mut::<RegisterWindow>(mapping_index) {
+            window.update(program_counter, stack_pointer);
+        }
+    }
 
-                self.program_counter = self.memory_controller.read16(self.memory_controller.read16(IRQ_VECTOR) as usize + (irq_code as usize * 2));
-            }
+    // The stack grows downwards from here, RAM ending at the top of memory gives 0x0000 as the first push wraps below it
+    pub fn stack_top(&self) -> u16 {
+        self.stack_top.unwrap_or_else(|| self.memory_controller.first_ram_end().map_or(0x0000, |end| end as u16))
+    }
+
+    // Takes effect on the next reset
+    pub fn set_stack_top(&mut self, stack_top: u16) {
+        self.stack_top = Some(stack_top);
+    }
+
+    // The stack pointer is decremented before a word is stored, so it always points at the most recent entry and [SP+$nn]
+    // reaches entries pushed earlier
+    pub fn push16(&mut self, value: u16) {
+        if let Some((low, _)) = self.stack_bounds
+            && self.stack_pointer.checked_sub(2).is_none_or(|stack_pointer| stack_pointer < low)
+        {
+            self.raise_stack_fault(StackFault::Overflow { stack_pointer: self.stack_pointer });
 
             return;
         }
 
-        let instruction = self.fetch16();
+        self.stack_pointer = self.stack_pointer.wrapping_sub(2);
 
-        let operation = Operation::get_operation_from_instruction(instruction);
-        let byte_mode = (instruction & 0x0040) != 0;
-        let lo_hi = (instruction & 0x0080) != 0;
-        let destination = Location::get_destination_from_instruction(instruction);
-        let source = Location::get_source_from_instruction(instruction);
+        self.memory_controller.write16(self.stack_pointer as usize, value);
+    }
 
-        match operation {
-            Operation::Mov => {
-                if byte_mode {
-                    self.execute_mov8(lo_hi, destination, source);
-                } else {
-                    self.execute_mov16(destination, source);
-                }
-            },
-            Operation::Adc => {
-                if byte_mode {
-                    self.execute_adc8(lo_hi, destination, source);
-                } else {
-                    self.execute_adc16(destination, source);
-                }
-            },
-            Operation::Sbc => {
-                if byte_mode {
-                    self.execute_sbc8(lo_hi, destination, source);
-                } else {
-                    self.execute_sbc16(destination, source);
-                }
-            },
-            Operation::Stp => {
-                self.enable = false;
-            },
-            Operation::Rst => {
-                self.reset();
-            },
-            Operation::Nop => (),
+    // An underflowing pop still returns the word at the stack pointer but leaves the pointer where it was
+    pub fn pop16(&mut self) -> u16 {
+        let value = self.memory_controller.read16(self.stack_pointer as usize);
+
+        if let Some((_, high)) = self.stack_bounds
+            && self.stack_pointer.checked_add(2).is_none_or(|stack_pointer| stack_pointer > high)
+        {
+            self.raise_stack_fault(StackFault::Underflow { stack_pointer: self.stack_pointer });
+
+            return value;
         }
+
+        self.stack_pointer = self.stack_pointer.wrapping_add(2);
+
+        value
     }
 
-    fn fetch16(&mut self) -> u16 {
-        let fetched_value = self.memory_controller.read16(self.program_counter as usize);
-        self.program_counter += 2;
-        fetched_value
+    // Pushes below low and pops above high halt the CPU instead of wrapping into whatever is next to the stack. High is the
+    // stack pointer of an empty stack, one past the topmost entry, so a stack of n words has high - low == 2 * n
+    pub fn set_stack_bounds(&mut self, low: u16, high: u16) {
+        assert!(low <= high, "Stack bounds 0x{:04X}..0x{:04X} are reversed", low, high);
+
+        self.stack_bounds = Some((low, high));
     }
 
-    fn fetch8(&mut self) -> u8 {
-        let fetched_value = self.memory_controller.read8(self.program_counter as usize);
-        self.program_counter += 1;
-        fetched_value
+    pub fn clear_stack_bounds(&mut self) {
+        self.stack_bounds = None;
     }
 
-    fn fetch_address(&mut self) -> usize {
-        self.fetch16() as usize
+    pub fn stack_bounds(&self) -> Option<(u16, u16)> {
+        self.stack_bounds
     }
 
-    fn fetch_indexed_address(&mut self) -> usize {
-        (self.fetch16() + self.index_x) as usize
+    // The push or pop the CPU halted on, until the next reset
+    pub fn stack_fault(&self) -> Option<StackFault> {
+        self.stack_fault
     }
 
-    fn fetch_indirect_address(&mut self) -> usize {
-        let indirect_address = self.fetch16() as usize;
-        self.memory_controller.read16(indirect_address) as usize
+    fn raise_stack_fault(&mut self, fault: StackFault) {
+        self.stack_fault = Some(fault);
+
+        self.halt();
     }
 
-    fn fetch_indirect_indexed_address(&mut self) -> usize {
-        let indirect_address = self.fetch16() as usize;
-        (self.memory_controller.read16(indirect_address) + self.index_x) as usize
+    // Halted by STP, an illegal instruction, the execute guard or a stack fault, only a reset starts it again
+    pub fn is_halted(&self) -> bool {
+        !self.enable
     }
 
-    fn fetch_indexed_indirect_address(&mut self) -> usize {
-        let indirect_address = (self.fetch16() + self.index_x) as usize;
-        self.memory_controller.read16(indirect_address) as usize
+    // Permissive emulation runs undefined operations as NOP, which hides a program that has run off into data
+    pub fn set_trap_illegal_instructions(&mut self, trap: bool) {
+        self.trap_illegal_instructions = trap;
     }
 
-    fn get_pointer_indexed_address(&mut self) -> usize {
-        (self.index_y + self.index_x) as usize
+    pub fn traps_illegal_instructions(&self) -> bool {
+        self.trap_illegal_instructions
     }
 
-    fn get_pointer_indirect_address(&mut self) -> usize {
-        let indirect_address = self.index_y as usize;
-        self.memory_controller.read16(indirect_address) as usize
+    // The instruction the CPU halted on, until the next reset
+    pub fn illegal_instruction(&self) -> Option<IllegalInstruction> {
+        self.illegal_instruction
     }
 
-    fn get_pointer_indirect_indexed_address(&mut self) -> usize {
-        let indirect_address = self.index_y as usize;
-        (self.memory_controller.read16(indirect_address) + self.index_x) as usize
+    // Catches runaway jumps into data and self-modifying code, mark mappings with MemoryController::set_executable
+    pub fn set_execute_guard(&mut self, guard: bool) {
+        self.execute_guard = guard;
     }
 
-    fn get_pointer_indexed_indirect_address(&mut self) -> usize {
-        let indirect_address = (self.index_y + self.index_x) as usize;
-        self.memory_controller.read16(indirect_address) as usize
+    pub fn has_execute_guard(&self) -> bool {
+        self.execute_guard
     }
 
-    fn get_sign_flag(&self) -> bool {
-        self.status & 0x80 != 0
+    // Where an instruction was fetched from a non-executable mapping, until the next reset
+    pub fn execute_violation(&self) -> Option<u16> {
+        self.execute_violation
     }
 
-    fn get_zero_flag(&self) -> bool {
-        self.status & 0x40 != 0
+    // Halts on a trivial self-loop such as a branch to itself, see StepInfo::stuck. A loop polling a device for input
+    // also looks stuck until the input changes, so leave this off for programs that wait that way
+    pub fn set_halt_when_stuck(&mut self, halt: bool) {
+        self.halt_when_stuck = halt;
     }
 
-    fn get_parity_flag(&self) -> bool {
-        self.status & 0x20 != 0
+    pub fn halts_when_stuck(&self) -> bool {
+        self.halt_when_stuck
     }
 
-    fn get_carry_flag(&self) -> bool {
-        self.status & 0x10 != 0
+    // Sleeping after WAI, any interrupt wakes it
+    pub fn is_waiting(&self) -> bool {
+        self.enable && self.waiting_for_interrupt
     }
 
-    fn get_overflow_flag(&self) -> bool {
-        self.status & 0x08 != 0
+    // Called once each time the CPU halts, so a front-end can break out of its loop
+    pub fn set_on_halt(&mut self, on_halt: impl FnMut() + 'static) {
+        self.on_halt = Some(Box::new(on_halt));
     }
 
-    fn get_interrupt_disable_flag(&self) -> bool {
-        self.status & 0x04 != 0
+    pub fn clear_on_halt(&mut self) {
+        self.on_halt = None;
     }
 
-    fn set_sign_flag(&mut self, flag: bool) {
-        if flag {
-            self.status |= 0x80;
-        } else {
-            self.status &= 0x7F
-        }
+    // Runs for each SYSCALL before the next instruction, arguments and results are passed in whichever registers the host
+    // and program agree on
+    pub fn set_syscall_handler(&mut self, handler: impl FnMut(&mut CPU) + 'static) {
+        self.syscall_handler = Some(Box::new(handler));
     }
 
-    fn set_zero_flag(&mut self, flag: bool) {
-        if flag {
-            self.status |= 0x40;
-        } else {
-            self.status &= 0xBF;
-        }
+    pub fn clear_syscall_handler(&mut self) {
+        self.syscall_handler = None;
     }
 
-    fn set_parity_flag(&mut self, flag: bool) {
-        if flag {
-            self.status |= 0x20;
-        } else {
-            self.status &= 0xDF;
-        }
+    // Total cycles executed since the CPU was created, including wait states, it is not cleared by a reset
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
 
-    fn set_carry_flag(&mut self, flag: bool) {
-        if flag {
-            self.status |= 0x10;
-        } else {
-            self.status &= 0xEF;
-        }
+    // Vectors are the locations the program counter is loaded from, not the handler addresses themselves
+    pub fn reset_vector(&self) -> usize {
+        self.reset_vector
     }
 
-    fn set_overflow_flag(&mut self, flag: bool) {
-        if flag {
-            self.status |= 0x08;
-        } else {
-            self.status &= 0xF7;
-        }
+    pub fn set_reset_vector(&mut self, vector: usize) {
+        self.reset_vector = vector;
     }
 
-    fn set_interrupt_disable_flag(&mut self, flag: bool) {
-        if flag {
-            self.status |= 0x04;
-        } else {
-            self.status &= 0xFB;
+    // Pokes the code into a mapped ROM at an offset within it and points the reset vector at the entry, the program starts on the next reset
+    pub fn load_program_at(&mut self, rom_index: usize, offset: usize, code: &[u8], entry: u16) -> Result<(), MemoryError> {
+        self.memory_controller.get_device_mut(rom_index)?.poke_bytes(offset, code)?;
+
+        let vector = self.memory_controller.endianness().to_bytes(entry);
+
+        self.memory_controller.poke_bytes(self.reset_vector, &vector)
+    }
+
+    // Starts execution somewhere other than the reset vector, a CPU waiting for an interrupt wakes up there
+    pub fn jump_to(&mut self, address: u16) {
+        self.program_counter = address;
+        self.waiting_for_interrupt = false;
+    }
+
+    pub fn nmi_vector(&self) -> usize {
+        self.nmi_vector
+    }
+
+    pub fn set_nmi_vector(&mut self, vector: usize) {
+        self.nmi_vector = vector;
+    }
+
+    pub fn irq_vector(&self) -> usize {
+        self.irq_vector
+    }
+
+    pub fn set_irq_vector(&mut self, vector: usize) {
+        self.irq_vector = vector;
+    }
+
+    // The IRQ vector holds the base of a table of 16-bit handler addresses, one entry per IRQ code.
+    // The entry address wraps within the 16-bit address space rather than running past it.
+    pub fn irq_handler_address(&self, code: u8) -> u16 {
+        let table_address = self.memory_controller.read16(self.irq_vector);
+        let entry_address = table_address.wrapping_add(code as u16 * 2);
+
+        self.memory_controller.read16(entry_address as usize)
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            index_x: self.index_x,
+            index_y: self.index_y,
+            status: self.status,
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            d: self.d,
         }
     }
 
-    fn set_flags_from_value16(&mut self, value: u16) {
-        self.set_sign_flag(value & 0x80 != 0);
-        self.set_zero_flag(value == 0);
-        self.set_parity_flag(value.count_ones() % 2 == 0);
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
     }
 
-    fn set_flags_from_value8(&mut self, value: u8) {
-        self.set_sign_flag(value & 0x80 != 0);
-        self.set_zero_flag(value == 0);
-        self.set_parity_flag(value.count_ones() % 2 == 0);
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.program_counter = value;
     }
 
-    fn set_al(&mut self, value: u8) {
-        self.a = self.a & 0xFF00 | value as u16;
+    pub fn stack_pointer(&self) -> u16 {
+        self.stack_pointer
     }
 
-    fn set_ah(&mut self, value: u8) {
-        self.a = self.a & 0x00FF | (value as u16) << 8;
+    pub fn set_stack_pointer(&mut self, value: u16) {
+        self.stack_pointer = value;
     }
 
-    fn set_bl(&mut self, value: u8) {
-        self.b = self.b & 0xFF00 | value as u16;
+    pub fn index_x(&self) -> u16 {
+        self.index_x
     }
 
-    fn set_bh(&mut self, value: u8) {
-        self.b = self.b & 0x00FF | (value as u16) << 8;
+    pub fn set_index_x(&mut self, value: u16) {
+        self.index_x = value;
     }
 
-    fn set_cl(&mut self, value: u8) {
-        self.c = self.c & 0xFF00 | value as u16;
+    pub fn index_y(&self) -> u16 {
+        self.index_y
     }
 
-    fn set_ch(&mut self, value: u8) {
-        self.c = self.c & 0x00FF | (value as u16) << 8;
+    pub fn set_index_y(&mut self, value: u16) {
+        self.index_y = value;
     }
 
-    fn set_dl(&mut self, value: u8) {
-        self.d = self.d & 0xFF00 | value as u16;
+    pub fn status(&self) -> u8 {
+        self.status
     }
 
-    fn set_dh(&mut self, value: u8) {
-        self.d = self.d & 0x00FF | (value as u16) << 8;
+    pub fn set_status(&mut self, value: u8) {
+        self.status = value;
     }
 
-    fn set_idxl(&mut self, value: u8) {
-        self.index_x = self.index_x & 0xFF00 | value as u16;
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        match flag {
+            Flag::Sign => self.get_sign_flag(),
+            Flag::Zero => self.get_zero_flag(),
+            Flag::Parity => self.get_parity_flag(),
+            Flag::Carry => self.get_carry_flag(),
+            Flag::Overflow => self.get_overflow_flag(),
+            Flag::InterruptDisable => self.get_interrupt_disable_flag(),
+        }
     }
 
-    fn set_idxh(&mut self, value: u8) {
-        self.index_x = self.index_x & 0x00FF | (value as u16) << 8;
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        match flag {
+            Flag::Sign => self.set_sign_flag(value),
+            Flag::Zero => self.set_zero_flag(value),
+            Flag::Parity => self.set_parity_flag(value),
+            Flag::Carry => self.set_carry_flag(value),
+            Flag::Overflow => self.set_overflow_flag(value),
+            Flag::InterruptDisable => self.set_interrupt_disable_flag(value),
+        }
     }
 
-    fn set_idyl(&mut self, value: u8) {
-        self.index_y = self.index_y & 0xFF00 | value as u16;
+    pub fn a(&self) -> u16 {
+        self.a
     }
 
-    fn set_idyh(&mut self, value: u8) {
-        self.index_y = self.index_y & 0x00FF | (value as u16) << 8;
+    pub fn set_a(&mut self, value: u16) {
+        self.a = value;
     }
 
-    fn add_with_carry16(&mut self, lhs: u16, rhs: u16, carry: bool) -> u16 {
-        let (result, carry_out) = lhs.carrying_add(rhs, carry);
+    pub fn b(&self) -> u16 {
+        self.b
+    }
 
-        self.set_flags_from_value16(result);
-        self.set_carry_flag(carry_out);
-        self.set_overflow_flag(lhs & 0x8000 == rhs & 0x8000 && lhs & 0x8000 != result & 0x8000);
+    pub fn set_b(&mut self, value: u16) {
+        self.b = value;
+    }
 
-        result
+    pub fn c(&self) -> u16 {
+        self.c
     }
 
-    fn add_with_carry8(&mut self, lhs: u8, rhs: u8, carry: bool) -> u8 {
-        let (result, carry_out) = lhs.carrying_add(rhs, carry);
+    pub fn set_c(&mut self, value: u16) {
+        self.c = value;
+    }
 
-        self.set_flags_from_value8(result);
-        self.set_carry_flag(carry_out);
-        self.set_overflow_flag(lhs & 0x80 == rhs & 0x80 && lhs & 0x80 != result & 0x80);
+    pub fn d(&self) -> u16 {
+        self.d
+    }
 
-        result
+    pub fn set_d(&mut self, value: u16) {
+        self.d = value;
     }
 
-    fn subtract_with_carry16(&mut self, lhs: u16, rhs: u16, carry: bool) -> u16 {
-        let (result, borrow) = lhs.borrowing_sub(rhs, !carry);
+    // Registers by number for table-driven code, in location nibble order: 0 to 3 are A to D, 4 is IDX and 5 is IDY
+    pub fn register(&self, n: u8) -> Option<u16> {
+        match n {
+            0 => Some(self.a),
+            1 => Some(self.b),
+            2 => Some(self.c),
+            3 => Some(self.d),
+            4 => Some(self.index_x),
+            5 => Some(self.index_y),
+            _ => None,
+        }
+    }
 
-        self.set_flags_from_value16(result);
-        self.set_carry_flag(!borrow);
-        self.set_overflow_flag(lhs & 0x8000 == rhs & 0x8000 && lhs & 0x8000 != result & 0x8000);
+    pub fn set_register(&mut self, n: u8, value: u16) -> Result<(), RegisterError> {
+        match n {
+            0 => self.a = value,
+            1 => self.b = value,
+            2 => self.c = value,
+            3 => self.d = value,
+            4 => self.index_x = value,
+            5 => self.index_y = value,
+            _ => return Err(RegisterError::OutOfRange { index: n }),
+        }
 
-        result
+        Ok(())
     }
 
-    fn subtract_with_carry8(&mut self, lhs: u8, rhs: u8, carry: bool) -> u8 {
-        let (result, borrow) = lhs.borrowing_sub(rhs, !carry);
+    // A checkpoint of the register file alone, lighter than a snapshot for rolling back a speculative step
+    pub fn save_registers(&self) -> Registers {
+        self.registers()
+    }
 
-        self.set_flags_from_value8(result);
-        self.set_carry_flag(!borrow);
-        self.set_overflow_flag(lhs & 0x80 == rhs & 0x80 && lhs & 0x80 != result & 0x80);
+    // Memory, and whether the CPU is halted or waiting, are left as they are
+    pub fn restore_registers(&mut self, registers: &Registers) {
+        self.program_counter = registers.program_counter;
+        self.stack_pointer = registers.stack_pointer;
+        self.index_x = registers.index_x;
+        self.index_y = registers.index_y;
+        self.status = registers.status;
+        self.a = registers.a;
+        self.b = registers.b;
+        self.c = registers.c;
+        self.d = registers.d;
+    }
 
-        result
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot_with_memory(self.memory_controller.snapshot())
     }
 
-    fn execute_mov16(&mut self, destination: Location, source: Location) {
-        let source_value = match source {
-            Location::Immediate => self.fetch16(),
-            Location::A => self.a,
-            Location::B => self.b,
-            Location::C => self.c,
-            Location::D => self.d,
-            Location::Idx => self.index_x,
-            Location::Idy => self.index_y,
-            Location::Address => {
-                let source_address = self.fetch_address();
-
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedAddress => {
-                let source_address = self.fetch_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectAddress => {
-                let source_address = self.fetch_indirect_address();
+    fn snapshot_with_memory(&self, memory: MemorySnapshot) -> Snapshot {
+        Snapshot {
+            enable: self.enable,
+            waiting_for_interrupt: self.waiting_for_interrupt,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            index_x: self.index_x,
+            index_y: self.index_y,
+            status: self.status,
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            memory,
+        }
+    }
 
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectIndexedAddress => {
-                let source_address = self.fetch_indirect_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedIndirectAddress => {
-                let source_address = self.fetch_indexed_indirect_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedPointer => {
-                let source_address = self.get_pointer_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectPointer => {
-                let source_address = self.get_pointer_indirect_address();
+    // The snapshot must have been taken with the same devices mapped
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), MemoryError> {
+        self.memory_controller.restore(&snapshot.memory)?;
+
+        self.enable = snapshot.enable;
+        self.waiting_for_interrupt = snapshot.waiting_for_interrupt;
+        self.program_counter = snapshot.program_counter;
+        self.stack_pointer = snapshot.stack_pointer;
+        self.index_x = snapshot.index_x;
+        self.index_y = snapshot.index_y;
+        self.status = snapshot.status;
+        self.a = snapshot.a;
+        self.b = snapshot.b;
+        self.c = snapshot.c;
+        self.d = snapshot.d;
+
+        Ok(())
+    }
 
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectIndexedPointer => {
-                let source_address = self.get_pointer_indirect_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedIndirectPointer => {
-                let source_address = self.get_pointer_indexed_indirect_address();
-                
-                self.memory_controller.read16(source_address)
-            },
+    #[cfg(feature = "std")]
+    pub fn print_state(&self) {
+        println!("PC: 0x{:04X}", self.program_counter);
+        println!("SP: 0x{:04X}", self.stack_pointer);
+        println!("IDX: 0x{:04X}", self.index_x);
+        println!("IDY: 0x{:04X}", self.index_y);
+        println!("FLG: {}", decode_status(self.status));
+        println!("A: 0x{:04X}", self.a);
+        println!("B: 0x{:04X}", self.b);
+        println!("C: 0x{:04X}", self.c);
+        println!("D: 0x{:04X}", self.d);
+    }
+
+    pub fn process(&mut self, nmi: bool, irq: Option<u8>) {
+        self.step_debug_with_interrupts(nmi, irq);
+    }
+
+    // Steps until the CPU halts, a program that waits for an interrupt or never stops never returns
+    pub fn run(&mut self) {
+        while self.enable {
+            self.step_debug();
+        }
+    }
+
+    // Like run, but the interrupt lines are polled from the closures before every step
+    pub fn run_with_interrupts(&mut self, mut nmi: impl FnMut() -> bool, mut irq: impl FnMut() -> Option<u8>) {
+        while self.enable {
+            let nmi = nmi();
+            let irq = irq();
+
+            self.step_debug_with_interrupts(nmi, irq);
+        }
+    }
+
+    // Steps with both interrupt lines low
+    pub fn step_debug(&mut self) -> StepInfo {
+        self.step_debug_with_interrupts(false, None)
+    }
+
+    pub fn step_debug_with_interrupts(&mut self, nmi: bool, irq: Option<u8>) -> StepInfo {
+        let info = self.step(nmi, irq);
+
+        self.update_register_window();
+
+        info
+    }
+
+    fn step(&mut self, nmi: bool, irq: Option<u8>) -> StepInfo {
+        let mut info = StepInfo {
+            program_counter: self.program_counter,
+            operation: None,
+            bytes: 0,
+            interrupt: None,
+            stuck: false,
         };
 
-        self.set_flags_from_value16(source_value);
+        if self.history.is_some() {
+            self.record_history(nmi, irq);
+        }
 
-        match destination {
-            Location::Immediate => (), // NOP
-            Location::A => {
-                self.a = source_value;
-            },
-            Location::B => {
-                self.b = source_value;
-            },
-            Location::C => {
-                self.c = source_value;
-            },
-            Location::D => {
-                self.d = source_value;
-            },
-            Location::Idx => {
-                self.index_x = source_value;
-            },
-            Location::Idy => {
-                self.index_y = source_value;
-            },
-            Location::Address => {
-                let destination_address = self.fetch_address();
+        if !self.enable {
+            return info;
+        }
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndexedAddress => {
-                let destination_address = self.fetch_indexed_address();
+        // Every step while enabled takes one cycle, including idle steps while waiting for an interrupt
+        self.cycles = self.cycles.wrapping_add(1);
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndirectAddress => {
-                let destination_address = self.fetch_indirect_address();
+        if self.interrupt_log_capacity != 0 {
+            self.log_raised_interrupts(nmi, irq);
+        }
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndirectIndexedAddress => {
-                let destination_address = self.fetch_indirect_indexed_address();
+        let (previous_nmi, _) = self.interrupt_lines;
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndexedIndirectAddress => {
-                let destination_address = self.fetch_indexed_indirect_address();
+        self.interrupt_lines = (nmi, irq);
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndexedPointer => {
-                let destination_address = self.get_pointer_indexed_address();
+        // Interrupts are checked between instructions, NMI takes priority and leaves any IRQ pending for a later step. NMI is
+        // edge-triggered, a line held high is only serviced on the step it goes high
+        if nmi && !previous_nmi {
+            self.waiting_for_interrupt = false;
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndirectPointer => {
-                let destination_address = self.get_pointer_indirect_address();
+            self.push_interrupt_frame();
+            self.set_interrupt_disable_flag(true);
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndirectIndexedPointer => {
-                let destination_address = self.get_pointer_indirect_indexed_address();
+            self.program_counter = self.memory_controller.read16(self.nmi_vector);
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
-            Location::IndexedIndirectPointer => {
-                let destination_address = self.get_pointer_indexed_indirect_address();
+            info.interrupt = Some(Interrupt::Nmi);
 
-                self.memory_controller.write16(destination_address, source_value);
-            },
+            if self.interrupt_log_capacity != 0 {
+                self.log_interrupt_event(Interrupt::Nmi, InterruptEventKind::Serviced);
+            }
+
+            return info;
         }
-    }
 
-    fn execute_mov8(&mut self, lo_hi: bool, destination: Location, source: Location) {
-        let source_value = match source {
-            Location::Immediate => self.fetch8(),
-            Location::A => {
-                if lo_hi {
-                    (self.a >> 8) as u8
-                } else {
-                    self.a as u8
+        if let Some(irq_code) = irq {
+            if !self.get_interrupt_disable_flag() {
+                self.waiting_for_interrupt = false;
+
+                self.push_interrupt_frame();
+                self.set_interrupt_disable_flag(true);
+
+                self.program_counter = self.irq_handler_address(irq_code);
+
+                info.interrupt = Some(Interrupt::Irq(irq_code));
+
+                if self.interrupt_log_capacity != 0 {
+                    self.log_interrupt_event(Interrupt::Irq(irq_code), InterruptEventKind::Serviced);
                 }
-            },
-            Location::B => {
-                if lo_hi {
-                    (self.b >> 8) as u8
+
+                return info;
+            }
+
+            // A masked IRQ still wakes a waiting CPU without being serviced
+            if self.waiting_for_interrupt {
+                self.waiting_for_interrupt = false;
+
+                return info;
+            }
+        }
+
+        if self.waiting_for_interrupt {
+            return info;
+        }
+
+        if self.execute_guard && !self.memory_controller.is_executable(self.program_counter as usize) {
+            self.execute_violation = Some(self.program_counter);
+
+            self.halt();
+
+            return info;
+        }
+
+        // The window shows the registers as they were at the start of the instruction while it executes
+        self.update_register_window();
+
+        let registers_before = self.registers();
+        let bus_writes_before = self.memory_controller.bus_writes();
+        let wait_states_before = self.memory_controller.wait_states();
+
+        self.fetched_bytes = 0;
+
+        let mut instruction = self.fetch16();
+        let mut extension = 0x0000;
+
+        // An extension prefix and the instruction it extends execute as one step
+        if let Operation::Ext = Operation::get_operation_from_instruction(instruction) {
+            extension = instruction;
+            instruction = self.fetch16();
+        }
+
+        if self.trace_capacity != 0 {
+            self.record_trace(info.program_counter, instruction);
+        }
+
+        let operation = Operation::get_operation_from_instruction(instruction);
+        let byte_mode = (instruction & encode::BYTE_MODE_BIT) != 0;
+        let lo_hi = (instruction & encode::LO_HI_BIT) != 0;
+        let destination = Location::get_destination_from_instruction(instruction, extension);
+        let source = Location::get_source_from_instruction(instruction, extension);
+
+        info.operation = Some(operation.mnemonic());
+
+        if self.profiling {
+            self.profile.record(operation);
+        }
+
+        match operation {
+            Operation::Mov => {
+                if byte_mode {
+                    self.execute_mov8(lo_hi, destination, source);
                 } else {
-                    self.b as u8
+                    self.execute_mov16(destination, source);
                 }
             },
-            Location::C => {
-                if lo_hi {
-                    (self.c >> 8) as u8
+            Operation::Adc => {
+                if byte_mode {
+                    self.execute_adc8(lo_hi, destination, source);
                 } else {
-                    self.c as u8
+                    self.execute_adc16(destination, source);
                 }
             },
-            Location::D => {
-                if lo_hi {
-                    (self.d >> 8) as u8
+            Operation::Sbc => {
+                if byte_mode {
+                    self.execute_sbc8(lo_hi, destination, source);
                 } else {
-                    self.d as u8
+                    self.execute_sbc16(destination, source);
                 }
             },
-            Location::Idx => {
-                if lo_hi {
-                    (self.index_x >> 8) as u8
+            Operation::Xchg => {
+                if byte_mode {
+                    self.execute_xchg8(lo_hi, destination, source);
                 } else {
-                    self.index_x as u8
+                    self.execute_xchg16(destination, source);
                 }
             },
-            Location::Idy => {
-                if lo_hi {
-                    (self.index_y >> 8) as u8
+            Operation::Bit => {
+                if byte_mode {
+                    self.execute_bit8(lo_hi, destination, source);
                 } else {
-                    self.index_y as u8
+                    self.execute_bit16(destination, source);
                 }
             },
-            Location::Address => {
-                let source_address = self.fetch_address();
-
-                self.memory_controller.read8(source_address)
+            Operation::Inx => {
+                self.index_x = self.index_x.wrapping_add(1);
+                self.set_index_flags(self.index_x);
             },
-            Location::IndexedAddress => {
-                let source_address = self.fetch_indexed_address();
-                
-                self.memory_controller.read8(source_address)
+            Operation::Dex => {
+                self.index_x = self.index_x.wrapping_sub(1);
+                self.set_index_flags(self.index_x);
             },
-            Location::IndirectAddress => {
-                let source_address = self.fetch_indirect_address();
-
-                self.memory_controller.read8(source_address)
+            Operation::Iny => {
+                self.index_y = self.index_y.wrapping_add(1);
+                self.set_index_flags(self.index_y);
             },
-            Location::IndirectIndexedAddress => {
-                let source_address = self.fetch_indirect_indexed_address();
-                
-                self.memory_controller.read8(source_address)
+            Operation::Dey => {
+                self.index_y = self.index_y.wrapping_sub(1);
+                self.set_index_flags(self.index_y);
             },
-            Location::IndexedIndirectAddress => {
-                let source_address = self.fetch_indexed_indirect_address();
-                
-                self.memory_controller.read8(source_address)
+            Operation::Swap => {
+                if byte_mode {
+                    self.execute_swap8(lo_hi, destination);
+                } else {
+                    self.execute_swap16(destination);
+                }
             },
-            Location::IndexedPointer => {
-                let source_address = self.get_pointer_indexed_address();
-                
-                self.memory_controller.read8(source_address)
+            Operation::Jtb => {
+                self.execute_jtb(destination, source);
             },
-            Location::IndirectPointer => {
-                let source_address = self.get_pointer_indirect_address();
-
-                self.memory_controller.read8(source_address)
+            Operation::Dbnz => {
+                if byte_mode {
+                    self.execute_dbnz8(lo_hi, destination, source);
+                } else {
+                    self.execute_dbnz16(destination, source);
+                }
             },
-            Location::IndirectIndexedPointer => {
-                let source_address = self.get_pointer_indirect_indexed_address();
-                
-                self.memory_controller.read8(source_address)
+            Operation::Swp => {
+                if byte_mode {
+                    self.execute_swp8(lo_hi, destination, source);
+                } else {
+                    self.execute_swp16(destination, source);
+                }
             },
-            Location::IndexedIndirectPointer => {
-                let source_address = self.get_pointer_indexed_indirect_address();
-                
-                self.memory_controller.read8(source_address)
+            Operation::Cmp => {
+                if byte_mode {
+                    self.execute_cmp8(lo_hi, destination, source);
+                } else {
+                    self.execute_cmp16(destination, source);
+                }
             },
-        };
-
-        self.set_flags_from_value8(source_value);
+            Operation::Adcl => {
+                self.execute_adcl(destination, source);
+            },
+            Operation::Sbcl => {
+                self.execute_sbcl(destination, source);
+            },
+            Operation::Bgt | Operation::Blt | Operation::Bge | Operation::Ble
+            | Operation::Bhi | Operation::Blo | Operation::Bhs | Operation::Bls => {
+                let taken = self.branch_condition(operation);
 
-        match destination {
-            Location::Immediate => (), // NOP
-            Location::A => {
-                if lo_hi {
-                    self.set_ah(source_value);
+                self.execute_branch(byte_mode, lo_hi, source, taken);
+            },
+            Operation::AddSat => {
+                if byte_mode {
+                    self.execute_addsat8(lo_hi, destination, source);
                 } else {
-                    self.set_al(source_value);
+                    self.execute_addsat16(destination, source);
                 }
             },
-            Location::B => {
-                if lo_hi {
-                    self.set_bh(source_value);
+            Operation::SubSat => {
+                if byte_mode {
+                    self.execute_subsat8(lo_hi, destination, source);
                 } else {
-                    self.set_bl(source_value);
+                    self.execute_subsat16(destination, source);
                 }
             },
-            Location::C => {
-                if lo_hi {
-                    self.set_ch(source_value);
+            Operation::Min => {
+                if byte_mode {
+                    self.execute_min8(lo_hi, destination, source);
                 } else {
-                    self.set_cl(source_value);
+                    self.execute_min16(destination, source);
                 }
             },
-            Location::D => {
-                if lo_hi {
-                    self.set_dh(source_value);
+            Operation::Max => {
+                if byte_mode {
+                    self.execute_max8(lo_hi, destination, source);
                 } else {
-                    self.set_dl(source_value);
+                    self.execute_max16(destination, source);
                 }
             },
-            Location::Idx => {
-                if lo_hi {
-                    self.set_idxh(source_value);
+            Operation::Lea => {
+                self.execute_lea(byte_mode, destination, source);
+            },
+            Operation::Clz => {
+                if byte_mode {
+                    self.execute_clz8(lo_hi, destination, source);
                 } else {
-                    self.set_idxl(source_value);
+                    self.execute_clz16(destination, source);
                 }
             },
-            Location::Idy => {
-                if lo_hi {
-                    self.set_idyh(source_value);
+            Operation::Popcnt => {
+                if byte_mode {
+                    self.execute_popcnt8(lo_hi, destination, source);
                 } else {
-                    self.set_idyl(source_value);
+                    self.execute_popcnt16(destination, source);
                 }
             },
-            Location::Address => {
-                let destination_address = self.fetch_address();
-
-                self.memory_controller.write8(destination_address, source_value);
+            Operation::Syscall => {
+                self.execute_syscall();
             },
-            Location::IndexedAddress => {
-                let destination_address = self.fetch_indexed_address();
+            Operation::MovIf => {
+                let condition = self.move_condition(instruction);
 
-                self.memory_controller.write8(destination_address, source_value);
+                if byte_mode {
+                    self.execute_movif8(lo_hi, destination, source, condition);
+                } else {
+                    self.execute_movif16(destination, source, condition);
+                }
             },
-            Location::IndirectAddress => {
-                let destination_address = self.fetch_indirect_address();
-
-                self.memory_controller.write8(destination_address, source_value);
+            Operation::Stp => {
+                self.halt();
             },
-            Location::IndirectIndexedAddress => {
-                let destination_address = self.fetch_indirect_indexed_address();
-
-                self.memory_controller.write8(destination_address, source_value);
+            Operation::Rst => {
+                self.reset();
             },
-            Location::IndexedIndirectAddress => {
-                let destination_address = self.fetch_indexed_indirect_address();
-
-                self.memory_controller.write8(destination_address, source_value);
+            Operation::Wai => {
+                self.waiting_for_interrupt = true;
             },
-            Location::IndexedPointer => {
-                let destination_address = self.get_pointer_indexed_address();
-
-                self.memory_controller.write8(destination_address, source_value);
+            Operation::Nop => (),
+            // Every opcode without an operation runs as NOP unless trapped
+            Operation::Illegal => {
+                if self.trap_illegal_instructions {
+                    self.illegal_instruction = Some(IllegalInstruction {
+                        program_counter: info.program_counter,
+                        instruction,
+                    });
+
+                    self.halt();
+                }
             },
-            Location::IndirectPointer => {
-                let destination_address = self.get_pointer_indirect_address();
+            Operation::Ext => (),
+        }
 
-                self.memory_controller.write8(destination_address, source_value);
-            },
-            Location::IndirectIndexedPointer => {
-                let destination_address = self.get_pointer_indirect_indexed_address();
+        info.bytes = self.fetched_bytes;
+        info.stuck = self.enable && self.registers() == registers_before && self.memory_controller.bus_writes() == bus_writes_before;
 
-                self.memory_controller.write8(destination_address, source_value);
-            },
-            Location::IndexedIndirectPointer => {
-                let destination_address = self.get_pointer_indexed_indirect_address();
+        // Slow devices stretch the instruction, including its fetch. Vector reads while servicing an interrupt aren't counted
+        let wait_states = self.memory_controller.wait_states().wrapping_sub(wait_states_before);
 
-                self.memory_controller.write8(destination_address, source_value);
-            },
+        self.cycles = self.cycles.wrapping_add(wait_states);
+
+        if info.stuck && self.halt_when_stuck {
+            self.halt();
         }
+
+        info
     }
 
-    fn execute_adc16(&mut self, destination: Location, source: Location) {
-        let source_value = match source {
-            Location::Immediate => self.fetch16(),
-            Location::A => self.a,
-            Location::B => self.b,
-            Location::C => self.c,
-            Location::D => self.d,
-            Location::Idx => self.index_x,
-            Location::Idy => self.index_y,
-            Location::Address => {
-                let source_address = self.fetch_address();
-
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedAddress => {
-                let source_address = self.fetch_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectAddress => {
-                let source_address = self.fetch_indirect_address();
+    fn halt(&mut self) {
+        self.enable = false;
 
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectIndexedAddress => {
-                let source_address = self.fetch_indirect_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedIndirectAddress => {
-                let source_address = self.fetch_indexed_indirect_address();
-                
-                self.memory_controller.read16(source_address)
+        if let Some(on_halt) = &mut self.on_halt {
+            on_halt();
+        }
+    }
+
+    // Keeps the last `capacity` executed instructions, discarding any previous trace
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = VecDeque::with_capacity(capacity);
+        self.trace_capacity = capacity;
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.trace = VecDeque::new();
+        self.trace_capacity = 0;
+    }
+
+    // Keeps the last `capacity` interrupt events, discarding any previous log. The CPU logs interrupts being raised and serviced,
+    // the host logs acknowledgements with log_interrupt_event as only it knows when a device's source is cleared
+    pub fn enable_interrupt_log(&mut self, capacity: usize) {
+        self.interrupt_log = VecDeque::with_capacity(capacity);
+        self.interrupt_log_capacity = capacity;
+    }
+
+    pub fn disable_interrupt_log(&mut self) {
+        self.interrupt_log = VecDeque::new();
+        self.interrupt_log_capacity = 0;
+    }
+
+    // Returns the logged events, oldest first
+    pub fn interrupt_log(&self) -> Vec<InterruptEvent> {
+        self.interrupt_log.iter().copied().collect()
+    }
+
+    // Stamped with the current cycle count, does nothing while logging is disabled
+    pub fn log_interrupt_event(&mut self, interrupt: Interrupt, kind: InterruptEventKind) {
+        if self.interrupt_log_capacity == 0 {
+            return;
+        }
+
+        if self.interrupt_log.len() == self.interrupt_log_capacity {
+            self.interrupt_log.pop_front();
+        }
+
+        self.interrupt_log.push_back(InterruptEvent {
+            cycle: self.cycles,
+            interrupt,
+            kind,
+        });
+    }
+
+    // The return address goes first so the handler finds the status on top, pushed before the interrupt disable flag is set
+    fn push_interrupt_frame(&mut self) {
+        self.push16(self.program_counter);
+        self.push16(self.status as u16);
+    }
+
+    fn log_raised_interrupts(&mut self, nmi: bool, irq: Option<u8>) {
+        let (previous_nmi, previous_irq) = self.interrupt_lines;
+
+        if nmi && !previous_nmi {
+            self.log_interrupt_event(Interrupt::Nmi, InterruptEventKind::Raised);
+        }
+
+        if let Some(irq_code) = irq
+            && previous_irq != irq
+        {
+            self.log_interrupt_event(Interrupt::Irq(irq_code), InterruptEventKind::Raised);
+        }
+    }
+
+    // Counts keep accumulating across enables until reset_profile
+    pub fn enable_profiling(&mut self) {
+        self.profiling = true;
+    }
+
+    pub fn disable_profiling(&mut self) {
+        self.profiling = false;
+    }
+
+    pub fn profile(&self) -> &ProfileCounts {
+        &self.profile
+    }
+
+    pub fn reset_profile(&mut self) {
+        self.profile = ProfileCounts::default();
+    }
+
+    // Returns the traced instructions, oldest first
+    pub fn trace(&self) -> Vec<TraceEntry> {
+        self.trace.iter().copied().collect()
+    }
+
+    fn record_trace(&mut self, program_counter: u16, instruction: u16) {
+        if self.trace.len() == self.trace_capacity {
+            self.trace.pop_front();
+        }
+
+        self.trace.push_back(TraceEntry {
+            program_counter,
+            instruction,
+        });
+    }
+
+    // Length of the instruction in memory at the address, following an extension prefix to the instruction it extends
+    pub fn instruction_length_at(&self, address: usize) -> usize {
+        let instruction = self.memory_controller.read16(address);
+
+        if let Operation::Ext = Operation::get_operation_from_instruction(instruction) {
+            return extended_instruction_length(instruction, self.memory_controller.read16((address + 2) % ADDRESS_SPACE));
+        }
+
+        instruction_length(instruction)
+    }
+
+    // Decodes consecutive instructions from the address, for a debugger's code view
+    pub fn disassemble_range(&self, start: usize, instructions: usize) -> Vec<(usize, String)> {
+        let mut address = start % ADDRESS_SPACE;
+
+        (0..instructions)
+            .map(|_| {
+                let (text, _) = self.disassemble(address);
+                let line = (address, text);
+
+                address = (address + self.instruction_length_at(address)) % ADDRESS_SPACE;
+
+                line
+            })
+            .collect()
+    }
+
+    // Returns the text of the instruction at the address and its length in bytes
+    pub fn disassemble(&self, address: usize) -> (String, usize) {
+        let mut instruction = self.memory_controller.read16(address);
+        let mut extension = 0x0000;
+        let mut length = 2;
+
+        if let Operation::Ext = Operation::get_operation_from_instruction(instruction) {
+            extension = instruction;
+            instruction = self.memory_controller.read16((address + length) % ADDRESS_SPACE);
+            length += 2;
+        }
+
+        let operation = Operation::get_operation_from_instruction(instruction);
+        let byte_mode = (instruction & encode::BYTE_MODE_BIT) != 0;
+        let lo_hi = (instruction & encode::LO_HI_BIT) != 0;
+        let destination = Location::get_destination_from_instruction(instruction, extension);
+        let source = Location::get_source_from_instruction(instruction, extension);
+
+        match operation {
+            Operation::Mov | Operation::Adc | Operation::Sbc | Operation::Xchg | Operation::Bit | Operation::Jtb | Operation::Dbnz
+            | Operation::Swp | Operation::Cmp | Operation::AddSat | Operation::SubSat | Operation::Min | Operation::Max
+            | Operation::Lea | Operation::Clz | Operation::Popcnt => {
+                // Source operands come before destination operands in the instruction stream
+                let source_text = self.disassemble_location(source, true, byte_mode, lo_hi, address, &mut length);
+                let destination_text = self.disassemble_location(destination, false, byte_mode, lo_hi, address, &mut length);
+
+                (format!("{} {}, {}", operation.mnemonic(), destination_text, source_text), length)
             },
-            Location::IndexedPointer => {
-                let source_address = self.get_pointer_indexed_address();
-                
-                self.memory_controller.read16(source_address)
+            Operation::MovIf => {
+                let source_text = self.disassemble_location(source, true, byte_mode, lo_hi, address, &mut length);
+                let destination_text = self.disassemble_location(destination, false, byte_mode, lo_hi, address, &mut length);
+
+                (format!("{} {}, {}", move_if_mnemonic(instruction), destination_text, source_text), length)
             },
-            Location::IndirectPointer => {
-                let source_address = self.get_pointer_indirect_address();
+            Operation::Swap => {
+                let destination_text = self.disassemble_location(destination, false, byte_mode, lo_hi, address, &mut length);
 
-                self.memory_controller.read16(source_address)
+                (format!("{} {}", operation.mnemonic(), destination_text), length)
             },
-            Location::IndirectIndexedPointer => {
-                let source_address = self.get_pointer_indirect_indexed_address();
-                
-                self.memory_controller.read16(source_address)
+            Operation::Adcl | Operation::Sbcl => {
+                // Anything other than a register pair makes the instruction a NOP
+                let destination_text = destination.register_pair_name().unwrap_or("-");
+                let source_text = source.register_pair_name().unwrap_or("-");
+
+                (format!("{} {}, {}", operation.mnemonic(), destination_text, source_text), length)
             },
-            Location::IndexedIndirectPointer => {
-                let source_address = self.get_pointer_indexed_indirect_address();
-                
-                self.memory_controller.read16(source_address)
+            Operation::Bgt | Operation::Blt | Operation::Bge | Operation::Ble
+            | Operation::Bhi | Operation::Blo | Operation::Bhs | Operation::Bls => {
+                let source_text = self.disassemble_location(source, true, byte_mode, lo_hi, address, &mut length);
+
+                (format!("{} {}", operation.mnemonic(), source_text), length)
             },
+            Operation::Inx | Operation::Dex | Operation::Iny | Operation::Dey | Operation::Syscall
+            | Operation::Stp | Operation::Rst | Operation::Wai | Operation::Ext | Operation::Nop | Operation::Illegal => (operation.mnemonic().to_string(), length),
+        }
+    }
+
+    fn disassemble_location(&self, location: Location, is_source: bool, byte_mode: bool, lo_hi: bool, instruction_address: usize, length: &mut usize) -> String {
+        let register_name = |name: &str| {
+            if !byte_mode {
+                name.to_string()
+            } else if lo_hi {
+                format!("{}H", name)
+            } else {
+                format!("{}L", name)
+            }
         };
 
-        match destination {
-            Location::Immediate => (), // NOP
-            Location::A => {
-                self.a = self.add_with_carry16(self.a, source_value, self.get_carry_flag());
-            },
-            Location::B => {
-                self.b = self.add_with_carry16(self.b, source_value, self.get_carry_flag());
+        let operand_address = (instruction_address + *length) % ADDRESS_SPACE;
+
+        let mut fetch_operand16 = || {
+            *length += 2;
+            self.memory_controller.read16(operand_address)
+        };
+
+        match location {
+            Location::Immediate => {
+                if !is_source {
+                    "-".to_string() // Writes to an immediate destination are discarded
+                } else if byte_mode {
+                    *length += 1;
+                    format!("#${:02X}", self.memory_controller.read8(operand_address))
+                } else {
+                    format!("#${:04X}", fetch_operand16())
+                }
             },
-            Location::C => {
-                self.c = self.add_with_carry16(self.c, source_value, self.get_carry_flag());
+            Location::A => register_name("A"),
+            Location::B => register_name("B"),
+            Location::C => register_name("C"),
+            Location::D => register_name("D"),
+            Location::Idx => register_name("IDX"),
+            Location::Idy => register_name("IDY"),
+            Location::Address => format!("[${:04X}]", fetch_operand16()),
+            Location::IndexedAddress => format!("[${:04X}+IDX]", fetch_operand16()),
+            Location::IndirectAddress => format!("[[${:04X}]]", fetch_operand16()),
+            Location::IndirectIndexedAddress => format!("[[${:04X}]+IDX]", fetch_operand16()),
+            Location::IndexedIndirectAddress => format!("[[${:04X}+IDX]]", fetch_operand16()),
+            Location::IndexedPointer => "[IDY+IDX]".to_string(),
+            Location::IndirectPointer => "[[IDY]]".to_string(),
+            Location::IndirectIndexedPointer => "[[IDY]+IDX]".to_string(),
+            Location::IndexedIndirectPointer => "[[IDY+IDX]]".to_string(),
+            Location::PostIncrementPointer => "[IDY+]".to_string(),
+            Location::PreDecrementPointer => "[-IDY]".to_string(),
+            Location::StackRelative => {
+                *length += 1;
+                format!("[SP+${:02X}]", self.memory_controller.read8(operand_address))
+            },
+            Location::ByteIndexedAddress => format!("[${:04X}+XL]", fetch_operand16()),
+            Location::ByteIndexedPointer => "[IDY+XL]".to_string(),
+            Location::ZeroPage => {
+                *length += 1;
+                format!("[${:02X}]", self.memory_controller.read8(operand_address))
+            },
+            Location::ProgramCounterRelative => {
+                let offset = fetch_operand16() as i16;
+
+                if offset < 0 {
+                    format!("[PC-${:04X}]", offset.unsigned_abs())
+                } else {
+                    format!("[PC+${:04X}]", offset)
+                }
             },
-            Location::D => {
-                self.d = self.add_with_carry16(self.d, source_value, self.get_carry_flag());
+        }
+    }
+
+    // The program counter wraps like the 16-bit register it is, after the vectors at the top of memory comes 0x0000
+    fn fetch16(&mut self) -> u16 {
+        // A word at the very top of memory is split, so its high byte comes from 0x0000. On a narrower bus the top of memory
+        // repeats below 0xFFFF
+        let top = self.memory_controller.address_space() - 1;
+
+        if self.program_counter as usize & top == top {
+            let bytes = [self.fetch8(), self.fetch8()];
+
+            return self.memory_controller.endianness().from_bytes(bytes);
+        }
+
+        let fetched_value = self.memory_controller.read16(self.program_counter as usize);
+        self.program_counter = self.program_counter.wrapping_add(2);
+        self.fetched_bytes += 2;
+        fetched_value
+    }
+
+    fn fetch8(&mut self) -> u8 {
+        let fetched_value = self.memory_controller.read8(self.program_counter as usize);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.fetched_bytes += 1;
+        fetched_value
+    }
+
+    fn fetch_address(&mut self) -> usize {
+        self.fetch16() as usize
+    }
+
+    fn fetch_indexed_address(&mut self) -> usize {
+        self.fetch16().wrapping_add(self.index_x) as usize
+    }
+
+    fn fetch_indirect_address(&mut self) -> usize {
+        let indirect_address = self.fetch16() as usize;
+        self.memory_controller.read16(indirect_address) as usize
+    }
+
+    fn fetch_indirect_indexed_address(&mut self) -> usize {
+        let indirect_address = self.fetch16() as usize;
+        self.memory_controller.read16(indirect_address).wrapping_add(self.index_x) as usize
+    }
+
+    fn fetch_indexed_indirect_address(&mut self) -> usize {
+        let indirect_address = self.fetch16().wrapping_add(self.index_x) as usize;
+        self.memory_controller.read16(indirect_address) as usize
+    }
+
+    // The offset is an unsigned byte, so frames are addressed upwards from the stack pointer
+    fn fetch_stack_relative_address(&mut self) -> usize {
+        self.stack_pointer.wrapping_add(self.fetch8() as u16) as usize
+    }
+
+    // The signed offset is relative to the program counter just after the offset itself is fetched, so for a destination it's the
+    // end of the instruction but for a source it's the start of the destination's operand bytes
+    fn fetch_program_counter_relative_address(&mut self) -> usize {
+        let offset = self.fetch16();
+
+        self.program_counter.wrapping_add(offset) as usize
+    }
+
+    // Only the low byte of IDX is added, so stale data in the high byte can't throw the offset off
+    fn fetch_byte_indexed_address(&mut self) -> usize {
+        self.fetch16().wrapping_add(self.index_x & 0x00FF) as usize
+    }
+
+    fn get_pointer_byte_indexed_address(&mut self) -> usize {
+        self.index_y.wrapping_add(self.index_x & 0x00FF) as usize
+    }
+
+    fn get_pointer_indexed_address(&mut self) -> usize {
+        self.index_y.wrapping_add(self.index_x) as usize
+    }
+
+    fn get_pointer_indirect_address(&mut self) -> usize {
+        let indirect_address = self.index_y as usize;
+        self.memory_controller.read16(indirect_address) as usize
+    }
+
+    fn get_pointer_indirect_indexed_address(&mut self) -> usize {
+        let indirect_address = self.index_y as usize;
+        self.memory_controller.read16(indirect_address).wrapping_add(self.index_x) as usize
+    }
+
+    fn get_pointer_indexed_indirect_address(&mut self) -> usize {
+        let indirect_address = self.index_y.wrapping_add(self.index_x) as usize;
+        self.memory_controller.read16(indirect_address) as usize
+    }
+
+    fn get_sign_flag(&self) -> bool {
+        self.status & SIGN_FLAG != 0
+    }
+
+    fn get_zero_flag(&self) -> bool {
+        self.status & ZERO_FLAG != 0
+    }
+
+    fn get_parity_flag(&self) -> bool {
+        self.status & PARITY_FLAG != 0
+    }
+
+    fn get_carry_flag(&self) -> bool {
+        self.status & CARRY_FLAG != 0
+    }
+
+    fn get_overflow_flag(&self) -> bool {
+        self.status & OVERFLOW_FLAG != 0
+    }
+
+    fn get_interrupt_disable_flag(&self) -> bool {
+        self.status & INTERRUPT_DISABLE_FLAG != 0
+    }
+
+    fn set_sign_flag(&mut self, flag: bool) {
+        if flag {
+            self.status |= SIGN_FLAG;
+        } else {
+            self.status &= !SIGN_FLAG;
+        }
+    }
+
+    fn set_zero_flag(&mut self, flag: bool) {
+        if flag {
+            self.status |= ZERO_FLAG;
+        } else {
+            self.status &= !ZERO_FLAG;
+        }
+    }
+
+    fn set_parity_flag(&mut self, flag: bool) {
+        if flag {
+            self.status |= PARITY_FLAG;
+        } else {
+            self.status &= !PARITY_FLAG;
+        }
+    }
+
+    fn set_carry_flag(&mut self, flag: bool) {
+        if flag {
+            self.status |= CARRY_FLAG;
+        } else {
+            self.status &= !CARRY_FLAG;
+        }
+    }
+
+    fn set_overflow_flag(&mut self, flag: bool) {
+        if flag {
+            self.status |= OVERFLOW_FLAG;
+        } else {
+            self.status &= !OVERFLOW_FLAG;
+        }
+    }
+
+    fn set_interrupt_disable_flag(&mut self, flag: bool) {
+        if flag {
+            self.status |= INTERRUPT_DISABLE_FLAG;
+        } else {
+            self.status &= !INTERRUPT_DISABLE_FLAG;
+        }
+    }
+
+    fn set_flags_from_value16(&mut self, value: u16) {
+        self.set_sign_flag(value & 0x8000 != 0);
+        self.set_zero_flag(value == 0);
+        self.set_parity_flag(value.count_ones().is_multiple_of(2));
+    }
+
+    fn set_flags_from_value8(&mut self, value: u8) {
+        self.set_sign_flag(value & 0x80 != 0);
+        self.set_zero_flag(value == 0);
+        self.set_parity_flag(value.count_ones().is_multiple_of(2));
+    }
+
+    // Stepping an index only touches sign and zero, so carry survives across a loop
+    fn set_index_flags(&mut self, value: u16) {
+        self.set_sign_flag(value & 0x8000 != 0);
+        self.set_zero_flag(value == 0);
+    }
+
+    fn read_register(&self, register: Register) -> u16 {
+        match register {
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::D => self.d,
+            Register::Idx => self.index_x,
+            Register::Idy => self.index_y,
+        }
+    }
+
+    fn write_register(&mut self, register: Register, value: u16) {
+        match register {
+            Register::A => self.a = value,
+            Register::B => self.b = value,
+            Register::C => self.c = value,
+            Register::D => self.d = value,
+            Register::Idx => self.index_x = value,
+            Register::Idy => self.index_y = value,
+        }
+    }
+
+    fn read_register_pair(&self, (high, low): (Register, Register)) -> u32 {
+        (self.read_register(high) as u32) << 16 | self.read_register(low) as u32
+    }
+
+    fn write_register_pair(&mut self, (high, low): (Register, Register), value: u32) {
+        self.write_register(high, (value >> 16) as u16);
+        self.write_register(low, value as u16);
+    }
+
+    fn set_flags_from_value32(&mut self, value: u32) {
+        self.set_sign_flag(value & 0x8000_0000 != 0);
+        self.set_zero_flag(value == 0);
+        self.set_parity_flag(value.count_ones().is_multiple_of(2));
+    }
+
+    fn add_with_carry16(&mut self, lhs: u16, rhs: u16, carry: bool) -> u16 {
+        let (result, carry_out) = lhs.carrying_add(rhs, carry);
+
+        self.set_flags_from_value16(result);
+        self.set_carry_flag(carry_out);
+        // Addition overflows when the operands' signs match and the result's sign differs from them, the carry in can't change that
+        self.set_overflow_flag(!(lhs ^ rhs) & (lhs ^ result) & 0x8000 != 0);
+
+        result
+    }
+
+    fn add_with_carry8(&mut self, lhs: u8, rhs: u8, carry: bool) -> u8 {
+        let (result, carry_out) = lhs.carrying_add(rhs, carry);
+
+        self.set_flags_from_value8(result);
+        self.set_carry_flag(carry_out);
+        self.set_overflow_flag(!(lhs ^ rhs) & (lhs ^ result) & 0x80 != 0);
+
+        result
+    }
+
+    fn subtract_with_carry16(&mut self, lhs: u16, rhs: u16, carry: bool) -> u16 {
+        let (result, borrow) = lhs.borrowing_sub(rhs, !carry);
+
+        self.set_flags_from_value16(result);
+        self.set_carry_flag(!borrow);
+        // Subtraction overflows when the operands' signs differ and the result's sign differs from the minuend's
+        self.set_overflow_flag((lhs ^ rhs) & (lhs ^ result) & 0x8000 != 0);
+
+        result
+    }
+
+    fn subtract_with_carry8(&mut self, lhs: u8, rhs: u8, carry: bool) -> u8 {
+        let (result, borrow) = lhs.borrowing_sub(rhs, !carry);
+
+        self.set_flags_from_value8(result);
+        self.set_carry_flag(!borrow);
+        self.set_overflow_flag((lhs ^ rhs) & (lhs ^ result) & 0x80 != 0);
+
+        result
+    }
+
+    // Fetches the location's operand bytes and computes its effective address, so operands must be resolved in instruction stream order
+    fn resolve_operand(&mut self, location: Location, is_source: bool, byte_mode: bool) -> Operand {
+        let width = if byte_mode { 1 } else { 2 };
+
+        match location {
+            Location::Immediate => {
+                if !is_source {
+                    Operand::Discard
+                } else if byte_mode {
+                    Operand::Immediate(self.fetch8() as u16)
+                } else {
+                    Operand::Immediate(self.fetch16())
+                }
             },
-            Location::Idx => {
-                self.index_x = self.add_with_carry16(self.index_x, source_value, self.get_carry_flag());
+            Location::A => Operand::Register(Register::A),
+            Location::B => Operand::Register(Register::B),
+            Location::C => Operand::Register(Register::C),
+            Location::D => Operand::Register(Register::D),
+            Location::Idx => Operand::Register(Register::Idx),
+            Location::Idy => Operand::Register(Register::Idy),
+            Location::Address => Operand::Memory(self.fetch_address()),
+            Location::IndexedAddress => Operand::Memory(self.fetch_indexed_address()),
+            Location::IndirectAddress => Operand::Memory(self.fetch_indirect_address()),
+            Location::IndirectIndexedAddress => Operand::Memory(self.fetch_indirect_indexed_address()),
+            Location::IndexedIndirectAddress => Operand::Memory(self.fetch_indexed_indirect_address()),
+            Location::IndexedPointer => Operand::Memory(self.get_pointer_indexed_address()),
+            Location::IndirectPointer => Operand::Memory(self.get_pointer_indirect_address()),
+            Location::IndirectIndexedPointer => Operand::Memory(self.get_pointer_indirect_indexed_address()),
+            Location::IndexedIndirectPointer => Operand::Memory(self.get_pointer_indexed_indirect_address()),
+            // Stepping the pointer by the access width never touches the flags
+            Location::PostIncrementPointer => {
+                let address = self.index_y as usize;
+
+                self.index_y = self.index_y.wrapping_add(width);
+
+                Operand::Memory(address)
+            },
+            Location::PreDecrementPointer => {
+                self.index_y = self.index_y.wrapping_sub(width);
+
+                Operand::Memory(self.index_y as usize)
+            },
+            Location::StackRelative => Operand::Memory(self.fetch_stack_relative_address()),
+            Location::ByteIndexedAddress => Operand::Memory(self.fetch_byte_indexed_address()),
+            Location::ByteIndexedPointer => Operand::Memory(self.get_pointer_byte_indexed_address()),
+            Location::ZeroPage => Operand::Memory(self.fetch8() as usize),
+            Location::ProgramCounterRelative => Operand::Memory(self.fetch_program_counter_relative_address()),
+        }
+    }
+
+    fn read_operand16(&self, operand: Operand) -> u16 {
+        match operand {
+            Operand::Discard => 0x0000,
+            Operand::Immediate(value) => value,
+            Operand::Register(register) => self.read_register(register),
+            Operand::Memory(address) => self.memory_controller.read16(address),
+        }
+    }
+
+    fn read_operand8(&self, operand: Operand, lo_hi: bool) -> u8 {
+        match operand {
+            Operand::Discard => 0x00,
+            Operand::Immediate(value) => value as u8,
+            Operand::Register(register) => {
+                if lo_hi {
+                    (self.read_register(register) >> 8) as u8
+                } else {
+                    self.read_register(register) as u8
+                }
             },
-            Location::Idy => {
-                self.index_y = self.add_with_carry16(self.index_y, source_value, self.get_carry_flag());
+            Operand::Memory(address) => self.memory_controller.read8(address),
+        }
+    }
+
+    fn write_operand16(&mut self, operand: Operand, value: u16) {
+        match operand {
+            Operand::Discard | Operand::Immediate(_) => (),
+            Operand::Register(register) => self.write_register(register, value),
+            Operand::Memory(address) => self.memory_controller.write16(address, value),
+        }
+    }
+
+    fn write_operand8(&mut self, operand: Operand, lo_hi: bool, value: u8) {
+        match operand {
+            Operand::Discard | Operand::Immediate(_) => (),
+            Operand::Register(register) => {
+                let register_value = self.read_register(register);
+
+                if lo_hi {
+                    self.write_register(register, register_value & 0x00FF | (value as u16) << 8);
+                } else {
+                    self.write_register(register, register_value & 0xFF00 | value as u16);
+                }
             },
-            Location::Address => {
-                let destination_address = self.fetch_address();
+            Operand::Memory(address) => self.memory_controller.write8(address, value),
+        }
+    }
+
+    fn execute_mov16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+
+        self.set_flags_from_value16(source_value);
+
+        let destination = self.resolve_operand(destination, false, false);
+
+        self.write_operand16(destination, source_value);
+    }
+
+    fn execute_mov8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+
+        self.set_flags_from_value8(source_value);
+
+        let destination = self.resolve_operand(destination, false, true);
+
+        self.write_operand8(destination, lo_hi, source_value);
+    }
+
+    fn execute_adc16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.add_with_carry16(self.read_operand16(destination), source_value, self.get_carry_flag());
+
+        self.write_operand16(destination, result);
+    }
+
+    fn execute_adc8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.add_with_carry8(self.read_operand8(destination, lo_hi), source_value, self.get_carry_flag());
+
+        self.write_operand8(destination, lo_hi, result);
+    }
+
+    fn execute_sbc16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.subtract_with_carry16(self.read_operand16(destination), source_value, self.get_carry_flag());
+
+        self.write_operand16(destination, result);
+    }
+
+    fn execute_sbc8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.subtract_with_carry8(self.read_operand8(destination, lo_hi), source_value, self.get_carry_flag());
+
+        self.write_operand8(destination, lo_hi, result);
+    }
+
+    // Flags are left unaffected
+    fn execute_xchg16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let destination = self.resolve_operand(destination, false, false);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let source_value = self.read_operand16(source);
+        let destination_value = self.read_operand16(destination);
+
+        self.write_operand16(destination, source_value);
+        self.write_operand16(source, destination_value);
+    }
+
+    fn execute_xchg8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let destination = self.resolve_operand(destination, false, true);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination_value = self.read_operand8(destination, lo_hi);
+
+        self.write_operand8(destination, lo_hi, source_value);
+        self.write_operand8(source, lo_hi, destination_value);
+    }
+
+    // Like the 6502's BIT, zero is set from the AND while sign and overflow are copied from the top two bits of the source
+    fn execute_bit16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.read_operand16(destination) & source_value;
+
+        self.set_zero_flag(result == 0);
+        self.set_sign_flag(source_value & 0x8000 != 0);
+        self.set_overflow_flag(source_value & 0x4000 != 0);
+    }
+
+    fn execute_bit8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.read_operand8(destination, lo_hi) & source_value;
+
+        self.set_zero_flag(result == 0);
+        self.set_sign_flag(source_value & 0x80 != 0);
+        self.set_overflow_flag(source_value & 0x40 != 0);
+    }
+
+    // Swaps the two bytes of a word, the source location is unused
+    fn execute_swap16(&mut self, destination: Location) {
+        let destination = self.resolve_operand(destination, false, false);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.read_operand16(destination).swap_bytes();
+
+        self.set_flags_from_value16(result);
+
+        self.write_operand16(destination, result);
+    }
+
+    // Swaps the two nibbles of a byte, the source location is unused
+    fn execute_swap8(&mut self, lo_hi: bool, destination: Location) {
+        let destination = self.resolve_operand(destination, false, true);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.read_operand8(destination, lo_hi).rotate_left(4);
+
+        self.set_flags_from_value8(result);
+
+        self.write_operand8(destination, lo_hi, result);
+    }
+
+    // Jumps through a table of 16-bit addresses, the destination is the table (a memory operand, or a register holding its address) and the source is the entry index
+    fn execute_jtb(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let index = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
+
+        let table = match destination {
+            Operand::Discard | Operand::Immediate(_) => return, // NOP
+            Operand::Register(register) => self.read_register(register),
+            Operand::Memory(address) => address as u16,
+        };
+
+        // The entry address wraps so an out-of-range index still reads inside the address space
+        let entry = table.wrapping_add(index.wrapping_mul(2));
+
+        self.program_counter = self.memory_controller.read16(entry as usize);
+    }
+
+    // Decrements the destination and branches by the signed source displacement, relative to the next instruction, unless it reached zero
+    fn execute_dbnz16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let displacement = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let result = self.read_operand16(destination).wrapping_sub(1);
+
+        self.set_flags_from_value16(result);
+
+        self.write_operand16(destination, result);
+
+        if result != 0 {
+            self.program_counter = self.program_counter.wrapping_add(displacement);
+        }
+    }
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+    fn execute_dbnz8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let displacement = self.read_operand8(source, lo_hi) as i8;
+        let destination = self.resolve_operand(destination, false, true);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedAddress => {
-                let destination_address = self.fetch_indexed_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        let result = self.read_operand8(destination, lo_hi).wrapping_sub(1);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectAddress => {
-                let destination_address = self.fetch_indirect_address();
+        self.set_flags_from_value8(result);
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        self.write_operand8(destination, lo_hi, result);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectIndexedAddress => {
-                let destination_address = self.fetch_indirect_indexed_address();
+        if result != 0 {
+            self.program_counter = self.program_counter.wrapping_add_signed(displacement as i16);
+        }
+    }
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+    // Exchanges like XCHG but sets the flags from the destination's old value, so a spinlock can swap a 1 into the lock and test
+    // whether it was free in one instruction. Interrupts are only taken between instructions, so nothing can intervene
+    fn execute_swp16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let destination = self.resolve_operand(destination, false, false);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedIndirectAddress => {
-                let destination_address = self.fetch_indexed_indirect_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        let source_value = self.read_operand16(source);
+        let destination_value = self.read_operand16(destination);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedPointer => {
-                let destination_address = self.get_pointer_indexed_address();
+        self.set_flags_from_value16(destination_value);
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        self.write_operand16(destination, source_value);
+        self.write_operand16(source, destination_value);
+    }
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectPointer => {
-                let destination_address = self.get_pointer_indirect_address();
+    fn execute_swp8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let destination = self.resolve_operand(destination, false, true);
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectIndexedPointer => {
-                let destination_address = self.get_pointer_indirect_indexed_address();
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination_value = self.read_operand8(destination, lo_hi);
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        self.set_flags_from_value8(destination_value);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedIndirectPointer => {
-                let destination_address = self.get_pointer_indexed_indirect_address();
+        self.write_operand8(destination, lo_hi, source_value);
+        self.write_operand8(source, lo_hi, destination_value);
+    }
 
-                let result = self.add_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+    // Sets the flags like SBC with the carry set but leaves the destination alone, so carry is set when destination >= source
+    fn execute_cmp16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
 
-                self.memory_controller.write16(destination_address, result);
-            },
+        if let Operand::Discard = destination {
+            return; // NOP
         }
+
+        self.subtract_with_carry16(self.read_operand16(destination), source_value, true);
     }
 
-    fn execute_adc8(&mut self, lo_hi: bool, destination: Location, source: Location) {
-        let source_value = match source {
-            Location::Immediate => self.fetch8(),
-            Location::A => {
-                if lo_hi {
-                    (self.a >> 8) as u8
-                } else {
-                    self.a as u8
-                }
-            },
-            Location::B => {
-                if lo_hi {
-                    (self.b >> 8) as u8
-                } else {
-                    self.b as u8
-                }
-            },
-            Location::C => {
-                if lo_hi {
-                    (self.c >> 8) as u8
-                } else {
-                    self.c as u8
-                }
-            },
-            Location::D => {
-                if lo_hi {
-                    (self.d >> 8) as u8
-                } else {
-                    self.d as u8
-                }
-            },
-            Location::Idx => {
-                if lo_hi {
-                    (self.index_x >> 8) as u8
-                } else {
-                    self.index_x as u8
-                }
-            },
-            Location::Idy => {
-                if lo_hi {
-                    (self.index_y >> 8) as u8
-                } else {
-                    self.index_y as u8
-                }
-            },
-            Location::Address => {
-                let source_address = self.fetch_address();
+    fn execute_cmp8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedAddress => {
-                let source_address = self.fetch_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectAddress => {
-                let source_address = self.fetch_indirect_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectIndexedAddress => {
-                let source_address = self.fetch_indirect_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedIndirectAddress => {
-                let source_address = self.fetch_indexed_indirect_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedPointer => {
-                let source_address = self.get_pointer_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectPointer => {
-                let source_address = self.get_pointer_indirect_address();
+        self.subtract_with_carry8(self.read_operand8(destination, lo_hi), source_value, true);
+    }
 
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectIndexedPointer => {
-                let source_address = self.get_pointer_indirect_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedIndirectPointer => {
-                let source_address = self.get_pointer_indexed_indirect_address();
-                
-                self.memory_controller.read8(source_address)
-            },
+    // Unsigned saturating arithmetic, the carry in is ignored and the carry out is set when the result was clamped
+    fn execute_addsat16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
+
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
+
+        let (result, clamped) = match self.read_operand16(destination).checked_add(source_value) {
+            Some(result) => (result, false),
+            None => (u16::MAX, true),
         };
 
-        match destination {
-            Location::Immediate => (), // NOP
-            Location::A => {
-                let result = self.add_with_carry8(self.a as u8, source_value, self.get_carry_flag());
+        self.set_carry_flag(clamped);
+        self.set_flags_from_value16(result);
 
-                if lo_hi {
-                    self.set_ah(result);
-                } else {
-                    self.set_al(result);
-                }
-            },
-            Location::B => {
-                let result = self.add_with_carry8(self.b as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_bh(result);
-                } else {
-                    self.set_bl(result);
-                }
-            },
-            Location::C => {
-                let result = self.add_with_carry8(self.c as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_ch(result);
-                } else {
-                    self.set_cl(result);
-                }
-            },
-            Location::D => {
-                let result = self.add_with_carry8(self.d as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_dh(result);
-                } else {
-                    self.set_dl(result);
-                }
-            },
-            Location::Idx => {
-                let result = self.add_with_carry8(self.index_x as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_idxh(result);
-                } else {
-                    self.set_idxl(result);
-                }
-            },
-            Location::Idy => {
-                let result = self.add_with_carry8(self.index_y as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_idyh(result);
-                } else {
-                    self.set_idyl(result);
-                }
-            },
-            Location::Address => {
-                let destination_address = self.fetch_address();
-                
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        self.write_operand16(destination, result);
+    }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedAddress => {
-                let destination_address = self.fetch_indexed_address();
+    fn execute_addsat8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectAddress => {
-                let destination_address = self.fetch_indirect_address();
+        let (result, clamped) = match self.read_operand8(destination, lo_hi).checked_add(source_value) {
+            Some(result) => (result, false),
+            None => (u8::MAX, true),
+        };
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        self.set_carry_flag(clamped);
+        self.set_flags_from_value8(result);
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectIndexedAddress => {
-                let destination_address = self.fetch_indirect_indexed_address();
+        self.write_operand8(destination, lo_hi, result);
+    }
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+    fn execute_subsat16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedIndirectAddress => {
-                let destination_address = self.fetch_indexed_indirect_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        let (result, clamped) = match self.read_operand16(destination).checked_sub(source_value) {
+            Some(result) => (result, false),
+            None => (u16::MIN, true),
+        };
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedPointer => {
-                let destination_address = self.get_pointer_indexed_address();
+        self.set_carry_flag(clamped);
+        self.set_flags_from_value16(result);
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        self.write_operand16(destination, result);
+    }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectPointer => {
-                let destination_address = self.get_pointer_indirect_address();
+    fn execute_subsat8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectIndexedPointer => {
-                let destination_address = self.get_pointer_indirect_indexed_address();
+        let (result, clamped) = match self.read_operand8(destination, lo_hi).checked_sub(source_value) {
+            Some(result) => (result, false),
+            None => (u8::MIN, true),
+        };
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        self.set_carry_flag(clamped);
+        self.set_flags_from_value8(result);
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedIndirectPointer => {
-                let destination_address = self.get_pointer_indexed_indirect_address();
+        self.write_operand8(destination, lo_hi, result);
+    }
 
-                let result = self.add_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+    // Unsigned, there's no signed form since word mode has no spare bit once byte mode has claimed lo_hi, so signed clamping
+    // still needs CMP and a signed branch
+    fn execute_min16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
 
-                self.memory_controller.write8(destination_address, result);
-            },
+        if let Operand::Discard = destination {
+            return; // NOP
         }
+
+        let result = self.read_operand16(destination).min(source_value);
+
+        self.set_flags_from_value16(result);
+
+        self.write_operand16(destination, result);
     }
 
-    fn execute_sbc16(&mut self, destination: Location, source: Location) {
-        let source_value = match source {
-            Location::Immediate => self.fetch16(),
-            Location::A => self.a,
-            Location::B => self.b,
-            Location::C => self.c,
-            Location::D => self.d,
-            Location::Idx => self.index_x,
-            Location::Idy => self.index_y,
-            Location::Address => {
-                let source_address = self.fetch_address();
-
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedAddress => {
-                let source_address = self.fetch_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectAddress => {
-                let source_address = self.fetch_indirect_address();
+    fn execute_min8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectIndexedAddress => {
-                let source_address = self.fetch_indirect_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedIndirectAddress => {
-                let source_address = self.fetch_indexed_indirect_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedPointer => {
-                let source_address = self.get_pointer_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectPointer => {
-                let source_address = self.get_pointer_indirect_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndirectIndexedPointer => {
-                let source_address = self.get_pointer_indirect_indexed_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-            Location::IndexedIndirectPointer => {
-                let source_address = self.get_pointer_indexed_indirect_address();
-                
-                self.memory_controller.read16(source_address)
-            },
-        };
+        let result = self.read_operand8(destination, lo_hi).min(source_value);
 
-        match destination {
-            Location::Immediate => (), // NOP
-            Location::A => {
-                self.a = self.subtract_with_carry16(self.a, source_value, self.get_carry_flag());
-            },
-            Location::B => {
-                self.b = self.subtract_with_carry16(self.b, source_value, self.get_carry_flag());
-            },
-            Location::C => {
-                self.c = self.subtract_with_carry16(self.c, source_value, self.get_carry_flag());
-            },
-            Location::D => {
-                self.d = self.subtract_with_carry16(self.d, source_value, self.get_carry_flag());
-            },
-            Location::Idx => {
-                self.index_x = self.subtract_with_carry16(self.index_x, source_value, self.get_carry_flag());
-            },
-            Location::Idy => {
-                self.index_y = self.subtract_with_carry16(self.index_y, source_value, self.get_carry_flag());
-            },
-            Location::Address => {
-                let destination_address = self.fetch_address();
+        self.set_flags_from_value8(result);
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        self.write_operand8(destination, lo_hi, result);
+    }
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedAddress => {
-                let destination_address = self.fetch_indexed_address();
+    fn execute_max16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectAddress => {
-                let destination_address = self.fetch_indirect_address();
+        let result = self.read_operand16(destination).max(source_value);
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        self.set_flags_from_value16(result);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectIndexedAddress => {
-                let destination_address = self.fetch_indirect_indexed_address();
+        self.write_operand16(destination, result);
+    }
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+    fn execute_max8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedIndirectAddress => {
-                let destination_address = self.fetch_indexed_indirect_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        let result = self.read_operand8(destination, lo_hi).max(source_value);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedPointer => {
-                let destination_address = self.get_pointer_indexed_address();
+        self.set_flags_from_value8(result);
+
+        self.write_operand8(destination, lo_hi, result);
+    }
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+    // Stores the address the source would access rather than its contents, the indirect modes still read their pointers.
+    // Always writes a word, byte mode only changes how [IDY+] and [-IDY] step. A source outside memory makes it a NOP
+    fn execute_lea(&mut self, byte_mode: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, byte_mode);
+        let destination = self.resolve_operand(destination, false, false);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectPointer => {
-                let destination_address = self.get_pointer_indirect_address();
+        if let Operand::Memory(address) = source {
+            self.write_operand16(destination, address as u16);
+        }
+    }
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+    // Zero is set when the source was zero, so the count is the full width. The other flags are left alone
+    fn execute_clz16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndirectIndexedPointer => {
-                let destination_address = self.get_pointer_indirect_indexed_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+        self.set_zero_flag(source_value == 0);
 
-                self.memory_controller.write16(destination_address, result);
-            },
-            Location::IndexedIndirectPointer => {
-                let destination_address = self.get_pointer_indexed_indirect_address();
+        self.write_operand16(destination, source_value.leading_zeros() as u16);
+    }
 
-                let result = self.subtract_with_carry16(self.memory_controller.read16(destination_address), source_value, self.get_carry_flag());
+    fn execute_clz8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                self.memory_controller.write16(destination_address, result);
-            },
+        if let Operand::Discard = destination {
+            return; // NOP
         }
+
+        self.set_zero_flag(source_value == 0);
+
+        self.write_operand8(destination, lo_hi, source_value.leading_zeros() as u8);
     }
 
-    fn execute_sbc8(&mut self, lo_hi: bool, destination: Location, source: Location) {
-        let source_value = match source {
-            Location::Immediate => self.fetch8(),
-            Location::A => {
-                if lo_hi {
-                    (self.a >> 8) as u8
-                } else {
-                    self.a as u8
-                }
-            },
-            Location::B => {
-                if lo_hi {
-                    (self.b >> 8) as u8
-                } else {
-                    self.b as u8
-                }
-            },
-            Location::C => {
-                if lo_hi {
-                    (self.c >> 8) as u8
-                } else {
-                    self.c as u8
-                }
-            },
-            Location::D => {
-                if lo_hi {
-                    (self.d >> 8) as u8
-                } else {
-                    self.d as u8
-                }
-            },
-            Location::Idx => {
-                if lo_hi {
-                    (self.index_x >> 8) as u8
-                } else {
-                    self.index_x as u8
-                }
-            },
-            Location::Idy => {
-                if lo_hi {
-                    (self.index_y >> 8) as u8
-                } else {
-                    self.index_y as u8
-                }
-            },
-            Location::Address => {
-                let source_address = self.fetch_address();
+    // Like CLZ only the zero flag changes, set when the source had no bits set
+    fn execute_popcnt16(&mut self, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
 
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedAddress => {
-                let source_address = self.fetch_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectAddress => {
-                let source_address = self.fetch_indirect_address();
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectIndexedAddress => {
-                let source_address = self.fetch_indirect_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedIndirectAddress => {
-                let source_address = self.fetch_indexed_indirect_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedPointer => {
-                let source_address = self.get_pointer_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectPointer => {
-                let source_address = self.get_pointer_indirect_address();
+        self.set_zero_flag(source_value == 0);
 
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndirectIndexedPointer => {
-                let source_address = self.get_pointer_indirect_indexed_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-            Location::IndexedIndirectPointer => {
-                let source_address = self.get_pointer_indexed_indirect_address();
-                
-                self.memory_controller.read8(source_address)
-            },
-        };
+        self.write_operand16(destination, source_value.count_ones() as u16);
+    }
 
-        match destination {
-            Location::Immediate => (), // NOP
-            Location::A => {
-                let result = self.subtract_with_carry8(self.a as u8, source_value, self.get_carry_flag());
+    fn execute_popcnt8(&mut self, lo_hi: bool, destination: Location, source: Location) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                if lo_hi {
-                    self.set_ah(result);
-                } else {
-                    self.set_al(result);
-                }
-            },
-            Location::B => {
-                let result = self.subtract_with_carry8(self.b as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_bh(result);
-                } else {
-                    self.set_bl(result);
-                }
-            },
-            Location::C => {
-                let result = self.subtract_with_carry8(self.c as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_ch(result);
-                } else {
-                    self.set_cl(result);
-                }
-            },
-            Location::D => {
-                let result = self.subtract_with_carry8(self.d as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_dh(result);
-                } else {
-                    self.set_dl(result);
-                }
-            },
-            Location::Idx => {
-                let result = self.subtract_with_carry8(self.index_x as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_idxh(result);
-                } else {
-                    self.set_idxl(result);
-                }
-            },
-            Location::Idy => {
-                let result = self.subtract_with_carry8(self.index_y as u8, source_value, self.get_carry_flag());
-                
-                if lo_hi {
-                    self.set_idyh(result);
-                } else {
-                    self.set_idyl(result);
-                }
-            },
-            Location::Address => {
-                let destination_address = self.fetch_address();
-                
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        if let Operand::Discard = destination {
+            return; // NOP
+        }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedAddress => {
-                let destination_address = self.fetch_indexed_address();
+        self.set_zero_flag(source_value == 0);
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        self.write_operand8(destination, lo_hi, source_value.count_ones() as u8);
+    }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectAddress => {
-                let destination_address = self.fetch_indirect_address();
+    // A NOP without a handler. The handler is taken out while it runs so it can borrow the CPU, and only put back if it
+    // didn't install another
+    fn execute_syscall(&mut self) {
+        let Some(mut handler) = self.syscall_handler.take() else {
+            return;
+        };
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        handler(self);
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectIndexedAddress => {
-                let destination_address = self.fetch_indirect_indexed_address();
+        if self.syscall_handler.is_none() {
+            self.syscall_handler = Some(handler);
+        }
+    }
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+    // The condition is in the low bits of the opcode, see encode::CONDITION_MASK
+    fn move_condition(&self, instruction: u16) -> bool {
+        let condition = instruction & encode::CONDITION_MASK;
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedIndirectAddress => {
-                let destination_address = self.fetch_indexed_indirect_address();
+        let flag = match condition & !encode::CONDITION_NEGATE {
+            encode::CONDITION_ZERO => self.get_zero_flag(),
+            encode::CONDITION_CARRY => self.get_carry_flag(),
+            encode::CONDITION_SIGN => self.get_sign_flag(),
+            _ => self.get_overflow_flag(),
+        };
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        flag != (condition & encode::CONDITION_NEGATE != 0)
+    }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedPointer => {
-                let destination_address = self.get_pointer_indexed_address();
+    // Both operands are still resolved when the condition fails, so their bytes are skipped and [IDY+] or [-IDY] still step.
+    // Unlike MOV the flags are left alone, so several moves can share one test
+    fn execute_movif16(&mut self, destination: Location, source: Location, condition: bool) {
+        let source = self.resolve_operand(source, true, false);
+        let source_value = self.read_operand16(source);
+        let destination = self.resolve_operand(destination, false, false);
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        if condition {
+            self.write_operand16(destination, source_value);
+        }
+    }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectPointer => {
-                let destination_address = self.get_pointer_indirect_address();
+    fn execute_movif8(&mut self, lo_hi: bool, destination: Location, source: Location, condition: bool) {
+        let source = self.resolve_operand(source, true, true);
+        let source_value = self.read_operand8(source, lo_hi);
+        let destination = self.resolve_operand(destination, false, true);
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        if condition {
+            self.write_operand8(destination, lo_hi, source_value);
+        }
+    }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndirectIndexedPointer => {
-                let destination_address = self.get_pointer_indirect_indexed_address();
+    // After CMP destination, source:
+    // - Signed: BGT !Z && S == O, BLT S != O, BGE S == O, BLE Z || S != O. The sign alone is wrong when the subtraction overflowed
+    // - Unsigned: BHI C && !Z, BLO !C, BHS C, BLS !C || Z
+    fn branch_condition(&self, operation: Operation) -> bool {
+        let sign = self.get_sign_flag();
+        let zero = self.get_zero_flag();
+        let carry = self.get_carry_flag();
+        let overflow = self.get_overflow_flag();
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        match operation {
+            Operation::Bgt => !zero && sign == overflow,
+            Operation::Blt => sign != overflow,
+            Operation::Bge => sign == overflow,
+            Operation::Ble => zero || sign != overflow,
+            Operation::Bhi => carry && !zero,
+            Operation::Blo => !carry,
+            Operation::Bhs => carry,
+            Operation::Bls => !carry || zero,
+            _ => unreachable!("{} is not a branch", operation.mnemonic()),
+        }
+    }
 
-                self.memory_controller.write8(destination_address, result);
-            },
-            Location::IndexedIndirectPointer => {
-                let destination_address = self.get_pointer_indexed_indirect_address();
+    // The source is a displacement from the next instruction, sign-extended in byte mode like DBNZ's
+    fn execute_branch(&mut self, byte_mode: bool, lo_hi: bool, source: Location, taken: bool) {
+        let source = self.resolve_operand(source, true, byte_mode);
 
-                let result = self.subtract_with_carry8(self.memory_controller.read8(destination_address), source_value, self.get_carry_flag());
+        let displacement = if byte_mode {
+            self.read_operand8(source, lo_hi) as i8 as i16
+        } else {
+            self.read_operand16(source) as i16
+        };
 
-                self.memory_controller.write8(destination_address, result);
-            },
+        if taken {
+            self.program_counter = self.program_counter.wrapping_add_signed(displacement);
         }
     }
-}
\ No newline at end of file
+
+    // The carry runs between the low and high words inside the instruction, flags come from the whole 32-bit result
+    fn execute_adcl(&mut self, destination: Location, source: Location) {
+        let (Some(destination), Some(source)) = (destination.register_pair(), source.register_pair()) else {
+            return; // NOP
+        };
+
+        let lhs = self.read_register_pair(destination);
+        let rhs = self.read_register_pair(source);
+        let (result, carry_out) = lhs.carrying_add(rhs, self.get_carry_flag());
+
+        self.set_flags_from_value32(result);
+        self.set_carry_flag(carry_out);
+        self.set_overflow_flag(!(lhs ^ rhs) & (lhs ^ result) & 0x8000_0000 != 0);
+
+        self.write_register_pair(destination, result);
+    }
+
+    fn execute_sbcl(&mut self, destination: Location, source: Location) {
+        let (Some(destination), Some(source)) = (destination.register_pair(), source.register_pair()) else {
+            return; // NOP
+        };
+
+        let lhs = self.read_register_pair(destination);
+        let rhs = self.read_register_pair(source);
+        let (result, borrow) = lhs.borrowing_sub(rhs, !self.get_carry_flag());
+
+        self.set_flags_from_value32(result);
+        self.set_carry_flag(!borrow);
+        self.set_overflow_flag((lhs ^ rhs) & (lhs ^ result) & 0x8000_0000 != 0);
+
+        self.write_register_pair(destination, result);
+    }
+}
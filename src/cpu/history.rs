@@ -0,0 +1,204 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+use core::mem;
+
+use crate::cpu::{CPU, Snapshot};
+use crate::memory::{MemoryError, PageJournal};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RewindError {
+    HistoryDisabled,
+    NotEnoughHistory { requested: u64, available: u64 },
+    Memory(MemoryError),
+}
+
+impl fmt::Display for RewindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::HistoryDisabled => write!(f, "History is not enabled"),
+            Self::NotEnoughHistory { requested, available } => write!(f, "Cannot rewind {} steps, only {} are recorded", requested, available),
+            Self::Memory(error) => write!(f, "Failed to restore memory: {}", error),
+        }
+    }
+}
+
+impl Error for RewindError {}
+
+impl From<MemoryError> for RewindError {
+    fn from(error: MemoryError) -> Self {
+        Self::Memory(error)
+    }
+}
+
+// Machine state at the start of a step, replaying the recorded interrupt lines from it reaches any later step
+struct Checkpoint {
+    step: u64,
+    cycles: u64,
+    state: Snapshot, // RAM is left out of the memory snapshot, it's rebuilt from the journals
+    journal: PageJournal, // RAM pages as they were here, for each page written before the next checkpoint
+    inputs: Vec<(bool, Option<u8>)>, // NMI and IRQ lines of each step since here
+}
+
+// Ring of checkpoints taken every `interval` steps, the oldest is dropped once there are `capacity` of them
+//
+// Each checkpoint costs the registers, whatever every device other than RAM returns from save_state (the whole image of a block
+// storage device, for one), 256 bytes for each RAM page written before the next checkpoint and 3 bytes per step of recorded
+// interrupt lines. A short interval makes stepping back cheap, as fewer steps are replayed, but takes more checkpoints and copies
+// pages that are written repeatedly once per checkpoint. A long interval is cheaper to keep but replays up to `interval - 1` steps
+// on every step back
+pub struct History {
+    interval: u64,
+    capacity: usize,
+    step: u64, // Steps taken since history was enabled
+    checkpoints: VecDeque<Checkpoint>,
+}
+
+impl History {
+    fn new(interval: u64, capacity: usize) -> Self {
+        assert!(interval > 0, "History interval should be non-zero");
+        assert!(capacity > 0, "History capacity should be non-zero");
+
+        Self {
+            interval,
+            capacity,
+            step: 0,
+            checkpoints: VecDeque::with_capacity(capacity),
+        }
+    }
+}
+
+impl CPU {
+    // Starts recording so steps can be undone with step_back, anything already recorded is discarded
+    pub fn enable_history(&mut self, interval: u64, capacity: usize) {
+        self.history = Some(History::new(interval, capacity));
+        self.memory_controller.enable_journal();
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+        self.memory_controller.disable_journal();
+    }
+
+    // How many steps back can be rewound
+    pub fn history_steps(&self) -> u64 {
+        match &self.history {
+            Some(history) => history.checkpoints.front().map_or(0, |checkpoint| history.step - checkpoint.step),
+            None => 0,
+        }
+    }
+
+    // Undoes the last step
+    pub fn step_back(&mut self) -> Result<(), RewindError> {
+        self.rewind(1)
+    }
+
+    // Restores the closest checkpoint at or before the target step and replays forward to it. Changes the host made between steps,
+    // such as setting registers or input sent to devices, aren't recorded and are lost or replayed differently
+    pub fn rewind(&mut self, steps: u64) -> Result<(), RewindError> {
+        let Some(mut history) = self.history.take() else {
+            return Err(RewindError::HistoryDisabled);
+        };
+
+        let restored = self.restore_checkpoint(&mut history, steps);
+
+        self.history = Some(history);
+
+        for (nmi, irq) in restored? {
+            self.step_debug_with_interrupts(nmi, irq);
+        }
+
+        Ok(())
+    }
+
+    // Returns the inputs to replay from the restored checkpoint to the target step
+    fn restore_checkpoint(&mut self, history: &mut History, steps: u64) -> Result<Vec<(bool, Option<u8>)>, RewindError> {
+        let available = history.checkpoints.front().map_or(0, |checkpoint| history.step - checkpoint.step);
+
+        if steps > available {
+            return Err(RewindError::NotEnoughHistory { requested: steps, available });
+        }
+
+        let target = history.step - steps;
+
+        // Pages written since the last checkpoint are undone first, then each journal back to the restored checkpoint
+        let journal = self.memory_controller.take_journal();
+
+        self.memory_controller.undo_journal(&journal)?;
+
+        while let Some(checkpoint) = history.checkpoints.back() {
+            self.memory_controller.undo_journal(&checkpoint.journal)?;
+
+            if checkpoint.step <= target {
+                break;
+            }
+
+            history.checkpoints.pop_back();
+        }
+
+        let Some(checkpoint) = history.checkpoints.back_mut() else {
+            unreachable!("The oldest checkpoint is at or before the target step");
+        };
+
+        self.restore(&checkpoint.state)?;
+        self.cycles = checkpoint.cycles;
+
+        // Undoing poked the pages back, which shouldn't count as writes after the checkpoint
+        self.memory_controller.take_journal();
+
+        checkpoint.journal = PageJournal::default();
+        history.step = checkpoint.step;
+
+        let mut inputs = mem::take(&mut checkpoint.inputs);
+
+        inputs.truncate((target - checkpoint.step) as usize);
+
+        Ok(inputs)
+    }
+
+    // Called at the start of every step while history is enabled
+    pub(super) fn record_history(&mut self, nmi: bool, irq: Option<u8>) {
+        let Some(history) = &self.history else {
+            return;
+        };
+
+        let due = history.checkpoints.back().is_none_or(|checkpoint| history.step - checkpoint.step >= history.interval);
+
+        if due {
+            let state = self.snapshot_with_memory(self.memory_controller.snapshot_devices());
+            let journal = self.memory_controller.take_journal();
+            let cycles = self.cycles;
+
+            let Some(history) = &mut self.history else {
+                return;
+            };
+
+            if let Some(previous) = history.checkpoints.back_mut() {
+                previous.journal = journal;
+            }
+
+            if history.checkpoints.len() == history.capacity {
+                history.checkpoints.pop_front();
+            }
+
+            history.checkpoints.push_back(Checkpoint {
+                step: history.step,
+                cycles,
+                state,
+                journal: PageJournal::default(),
+                inputs: Vec::with_capacity(history.interval as usize),
+            });
+        }
+
+        let Some(history) = &mut self.history else {
+            return;
+        };
+
+        if let Some(checkpoint) = history.checkpoints.back_mut() {
+            checkpoint.inputs.push((nmi, irq));
+        }
+
+        history.step += 1;
+    }
+}
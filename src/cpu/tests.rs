@@ -0,0 +1,2212 @@
+use alloc::rc::Rc;
+use core::cell::Cell;
+
+use super::*;
+use crate::assembler::assemble;
+use crate::devices::{Keyboard, REGISTER_WINDOW_PROGRAM_COUNTER, TIMER_CONTROL, TIMER_RELOAD, Timer};
+use crate::memory::{Endianness, MAP_BLOCK_SIZE, MAP_BLOCKS, MappedDevice, RAM, ROM};
+
+const RAM_CAPACITY: usize = 0x1000;
+const ROM_CAPACITY: usize = 0x8000;
+const ROM_FIRST_BLOCK: usize = 8;
+const ROM_FIRST_ADDRESS: u16 = 0x8000;
+
+// 4 KiB of RAM at 0x0000 and 32 KiB of ROM at 0x8000 with the code at the start of ROM, reset and ready to step
+fn machine(code: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+
+    cpu.memory_controller.map_device(0, 1, Box::new(RAM::new(RAM_CAPACITY))).unwrap();
+
+    let rom_index = cpu.memory_controller.map_device(ROM_FIRST_BLOCK, ROM_CAPACITY / MAP_BLOCK_SIZE, Box::new(ROM::new(ROM_CAPACITY))).unwrap();
+    let rom = cpu.memory_controller.get_device_mut(rom_index).unwrap();
+
+    rom.poke_bytes(0x0000, code).unwrap();
+    rom.poke_bytes(RESET_VECTOR - ROM_FIRST_ADDRESS as usize, &ROM_FIRST_ADDRESS.to_le_bytes()).unwrap();
+
+    cpu.reset();
+
+    cpu
+}
+
+// Steps the code on a fresh machine until it halts or `steps` steps have run
+fn run_program(code: &[u8], steps: usize) -> CPU {
+    let mut cpu = machine(code);
+
+    for _ in 0..steps {
+        if cpu.is_halted() {
+            break;
+        }
+
+        cpu.step_debug();
+    }
+
+    cpu
+}
+
+fn program(source: &str) -> Vec<u8> {
+    assemble(source).expect("Test program should assemble")
+}
+
+#[test]
+fn step_debug_reports_the_executed_instruction() {
+    // MOV AL, #$10 / MOV A, #$1234 / STP
+    let mut cpu = machine(&[0x40, 0x10, 0x10, 0x00, 0x10, 0x34, 0x12, 0x30, 0x00]);
+
+    let info = cpu.step_debug();
+
+    assert_eq!(info.program_counter, 0x8000);
+    assert_eq!(info.operation, Some("MOV"));
+    assert_eq!(info.bytes, 3);
+    assert_eq!(info.interrupt, None);
+
+    let info = cpu.step_debug();
+
+    assert_eq!(info.program_counter, 0x8003);
+    assert_eq!(info.bytes, 4);
+    assert_eq!(cpu.a(), 0x1234);
+}
+
+#[test]
+fn step_debug_reports_no_operation_while_halted() {
+    let mut cpu = machine(&[0x30, 0x00]); // STP
+
+    cpu.step_debug();
+
+    let info = cpu.step_debug();
+
+    assert!(cpu.is_halted());
+    assert_eq!(info.operation, None);
+    assert_eq!(info.bytes, 0);
+}
+
+#[test]
+fn disassemble_reads_back_each_instruction_and_its_length() {
+    // MOV A, #$1234 / ADC [[$1000]], B / MOV AL, #$80 / STP
+    let cpu = machine(&[0x00, 0x10, 0x34, 0x12, 0x01, 0x92, 0x00, 0x10, 0x40, 0x10, 0x80, 0x30, 0x00]);
+
+    assert_eq!(cpu.disassemble(0x8000), ("MOV A, #$1234".to_string(), 4));
+    assert_eq!(cpu.disassemble(0x8004), ("ADC [[$1000]], B".to_string(), 4));
+    assert_eq!(cpu.disassemble(0x8008), ("MOV AL, #$80".to_string(), 3));
+    assert_eq!(cpu.disassemble(0x800B), ("STP".to_string(), 2));
+}
+
+#[test]
+fn trace_keeps_the_most_recent_instructions() {
+    // ADC A, B six times / STP
+    let mut cpu = machine(&[0x01, 0x12, 0x01, 0x12, 0x01, 0x12, 0x01, 0x12, 0x01, 0x12, 0x01, 0x12, 0x30, 0x00]);
+
+    cpu.enable_trace(4);
+
+    for _ in 0..6 {
+        cpu.process(false, None);
+    }
+
+    let trace = cpu.trace();
+    let program_counters: Vec<u16> = trace.iter().map(|entry| entry.program_counter).collect();
+
+    assert_eq!(program_counters, [0x8004, 0x8006, 0x8008, 0x800A]);
+    assert_eq!(trace[0].instruction, cpu.memory_controller.read16(0x8004));
+
+    cpu.disable_trace();
+    cpu.process(false, None);
+
+    assert!(cpu.trace().is_empty());
+}
+
+#[test]
+fn restore_returns_to_the_snapshot() {
+    // MOV [$0010], #$1234 / ADC A, #1 / ADC A, #1 / MOV [$0010], #$5678 / STP
+    let mut cpu = machine(&[
+        0x00, 0x70, 0x34, 0x12, 0x10, 0x00,
+        0x01, 0x10, 0x01, 0x00,
+        0x01, 0x10, 0x01, 0x00,
+        0x00, 0x70, 0x78, 0x56, 0x10, 0x00,
+        0x30, 0x00,
+    ]);
+
+    cpu.process(false, None);
+    cpu.process(false, None);
+
+    let snapshot = cpu.snapshot();
+
+    cpu.process(false, None);
+    cpu.process(false, None);
+
+    assert_ne!(cpu.snapshot(), snapshot);
+
+    cpu.restore(&snapshot).unwrap();
+
+    assert_eq!(cpu.snapshot(), snapshot);
+    assert_eq!(cpu.a(), 1);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x1234);
+}
+
+#[test]
+fn restore_rejects_a_snapshot_of_other_devices() {
+    let snapshot = machine(&[]).snapshot();
+    let mut cpu = CPU::new();
+
+    assert!(cpu.restore(&snapshot).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshots_are_serializable() {
+    fn assert_serializable<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+
+    assert_serializable::<Snapshot>();
+}
+
+#[test]
+fn post_increment_steps_past_the_operand() {
+    // MOV IDY, #$0010 / MOV A, [IDY+] / MOV B, [IDY+] / MOV AL, [IDY+] / STP
+    let mut cpu = machine(&[0x00, 0x60, 0x10, 0x00, 0x3F, 0x01, 0x00, 0x10, 0x3F, 0x01, 0x00, 0x20, 0x3F, 0x01, 0x40, 0x10, 0x30, 0x00]);
+
+    cpu.memory_controller.write16(0x0010, 0x1111);
+    cpu.memory_controller.write16(0x0012, 0x2222);
+    cpu.memory_controller.write8(0x0014, 0x33);
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0x1133);
+    assert_eq!(cpu.b(), 0x2222);
+    assert_eq!(cpu.index_y(), 0x0015);
+    assert_eq!(cpu.disassemble(0x8004).0, "MOV A, [IDY+]");
+}
+
+#[test]
+fn pre_decrement_steps_back_before_the_operand() {
+    // MOV IDY, #$0014 / MOV [-IDY], AL / MOV [-IDY], A / STP
+    let mut cpu = machine(&[0x00, 0x60, 0x14, 0x00, 0x3F, 0x20, 0x40, 0x01, 0x3F, 0x20, 0x00, 0x01, 0x30, 0x00]);
+
+    cpu.set_a(0xBEEF);
+    cpu.run();
+
+    assert_eq!(cpu.index_y(), 0x0011);
+    assert_eq!(cpu.memory_controller.read16(0x0011), 0xBEEF);
+    assert_eq!(cpu.memory_controller.read8(0x0013), 0xEF);
+    assert_eq!(cpu.disassemble(0x8004).0, "MOV [-IDY], AL");
+}
+
+#[test]
+fn stack_relative_operands_are_offset_from_sp() {
+    // MOV A, #$BEEF / MOV [SP+$04], A / MOV B, [SP+$04] / STP
+    let mut cpu = machine(&[0x00, 0x10, 0xEF, 0xBE, 0x3F, 0x30, 0x00, 0x01, 0x04, 0x3F, 0x03, 0x00, 0x20, 0x04, 0x30, 0x00]);
+
+    cpu.set_stack_pointer(0x0100);
+    cpu.run();
+
+    assert_eq!(cpu.memory_controller.read16(0x0104), 0xBEEF);
+    assert_eq!(cpu.b(), 0xBEEF);
+    assert_eq!(cpu.stack_pointer(), 0x0100);
+    assert_eq!(cpu.disassemble(0x8004), ("MOV [SP+$04], A".to_string(), 5));
+}
+
+#[test]
+fn xchg_swaps_operands_without_touching_flags() {
+    // MOV A, #1 / MOV B, #2 / XCHG A, B / XCHG [$0010], A / STP
+    let mut cpu = machine(&[0x00, 0x10, 0x01, 0x00, 0x00, 0x20, 0x02, 0x00, 0x03, 0x12, 0x03, 0x71, 0x10, 0x00, 0x30, 0x00]);
+
+    cpu.memory_controller.write16(0x0010, 0x7777);
+
+    for _ in 0..3 {
+        cpu.process(false, None);
+    }
+
+    assert_eq!((cpu.a(), cpu.b()), (2, 1));
+
+    let status = cpu.status();
+
+    cpu.process(false, None);
+
+    assert_eq!((cpu.a(), cpu.memory_controller.read16(0x0010)), (0x7777, 2));
+    assert_eq!(cpu.status(), status);
+    assert_eq!(cpu.disassemble(0x8008).0, "XCHG A, B");
+}
+
+#[test]
+fn accessors_read_back_what_setters_wrote() {
+    let mut cpu = machine(&[]);
+
+    cpu.set_a(0x1111);
+    cpu.set_b(0x2222);
+    cpu.set_c(0x3333);
+    cpu.set_d(0x4444);
+    cpu.set_index_x(0x5555);
+    cpu.set_index_y(0x6666);
+    cpu.set_stack_pointer(0x7777);
+    cpu.set_program_counter(0x8888);
+    cpu.set_status(CARRY_FLAG);
+
+    assert_eq!((cpu.a(), cpu.b(), cpu.c(), cpu.d()), (0x1111, 0x2222, 0x3333, 0x4444));
+    assert_eq!((cpu.index_x(), cpu.index_y()), (0x5555, 0x6666));
+    assert_eq!((cpu.stack_pointer(), cpu.program_counter()), (0x7777, 0x8888));
+    assert_eq!(cpu.status(), CARRY_FLAG);
+    assert_eq!(
+        cpu.registers(),
+        Registers {
+            program_counter: 0x8888,
+            stack_pointer: 0x7777,
+            index_x: 0x5555,
+            index_y: 0x6666,
+            status: CARRY_FLAG,
+            a: 0x1111,
+            b: 0x2222,
+            c: 0x3333,
+            d: 0x4444,
+        }
+    );
+}
+
+#[test]
+fn flag_masks_are_distinct_bits() {
+    let mut seen = 0x00;
+
+    for flag in Flag::ALL {
+        assert_eq!(flag.mask().count_ones(), 1, "{flag:?}");
+        assert_eq!(seen & flag.mask(), 0x00, "{flag:?}");
+
+        seen |= flag.mask();
+    }
+}
+
+#[test]
+fn status_flags_sit_at_their_masks() {
+    let mut cpu = CPU::new();
+
+    cpu.set_status(SIGN_FLAG | OVERFLOW_FLAG);
+
+    assert!(cpu.get_flag(Flag::Sign));
+    assert!(cpu.get_flag(Flag::Overflow));
+    assert!(!cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn debug_shows_registers_and_mapped_ranges() {
+    let mut cpu = machine(&[]);
+
+    cpu.set_a(0xBEEF);
+
+    let debug = format!("{cpu:?}");
+
+    assert!(debug.contains("a: 0xBEEF"), "{debug}");
+    assert!(debug.contains("0x0000..0x1000 -> 0"), "{debug}");
+    assert!(debug.contains("0x8000..0x10000 -> 1"), "{debug}");
+}
+
+#[test]
+fn default_is_new() {
+    assert_eq!(CPU::default().registers(), CPU::new().registers());
+    assert_eq!(format!("{:?}", MemoryController::default()), format!("{:?}", MemoryController::new()));
+}
+
+#[test]
+fn reset_reads_the_configured_vector() {
+    let mut cpu = machine(&[]);
+
+    cpu.memory_controller.poke_bytes(0xF000, &[0x34, 0x92]).unwrap();
+    cpu.set_reset_vector(0xF000);
+    cpu.reset();
+
+    assert_eq!(cpu.reset_vector(), 0xF000);
+    assert_eq!(cpu.program_counter(), 0x9234);
+}
+
+#[test]
+fn nmi_jumps_through_the_configured_vector() {
+    let mut cpu = machine(&[0x3E, 0x00]); // NOP
+
+    cpu.memory_controller.poke_bytes(0xF000, &[0x00, 0x90]).unwrap();
+    cpu.set_nmi_vector(0xF000);
+    cpu.process(true, None);
+
+    assert_eq!(cpu.program_counter(), 0x9000);
+}
+
+#[test]
+fn nmi_pushes_the_return_address_and_status() {
+    let mut cpu = machine(&program("NOP\nNOP"));
+
+    cpu.memory_controller.poke_bytes(NMI_VECTOR, &[0x00, 0x90]).unwrap();
+    cpu.set_flag(Flag::Carry, true);
+    cpu.step_debug();
+
+    let status = cpu.status();
+    let stack_pointer = cpu.stack_pointer();
+
+    cpu.step_debug_with_interrupts(true, None);
+
+    assert_eq!(cpu.program_counter(), 0x9000);
+    assert_eq!(cpu.stack_pointer(), stack_pointer.wrapping_sub(4));
+    assert_eq!(cpu.pop16(), status as u16);
+    assert_eq!(cpu.pop16(), 0x8002);
+    assert!(cpu.get_flag(Flag::InterruptDisable));
+}
+
+#[test]
+fn held_nmi_is_only_serviced_on_its_rising_edge() {
+    let mut cpu = machine(&program("NOP"));
+
+    cpu.memory_controller.poke_bytes(NMI_VECTOR, &[0x00, 0x80]).unwrap();
+
+    assert_eq!(cpu.step_debug_with_interrupts(true, None).interrupt, Some(Interrupt::Nmi));
+    assert_eq!(cpu.step_debug_with_interrupts(true, None).interrupt, None);
+    assert_eq!(cpu.program_counter(), 0x8002);
+
+    cpu.step_debug();
+
+    assert_eq!(cpu.step_debug_with_interrupts(true, None).interrupt, Some(Interrupt::Nmi));
+}
+
+// The IRQ vector points at a table of handlers at 0x9000
+fn machine_with_irq_table(code: &[u8], handlers: &[u16]) -> CPU {
+    let mut cpu = machine(code);
+
+    cpu.memory_controller.poke_bytes(IRQ_VECTOR, &[0x00, 0x90]).unwrap();
+
+    for (i, handler) in handlers.iter().enumerate() {
+        cpu.memory_controller.poke_bytes(0x9000 + i * 2, &handler.to_le_bytes()).unwrap();
+    }
+
+    cpu
+}
+
+#[test]
+fn irq_codes_index_the_handler_table() {
+    let mut cpu = machine_with_irq_table(&[0x3E, 0x00], &[0xA000, 0xB000, 0xC000]); // NOP
+
+    assert_eq!(cpu.irq_handler_address(0), 0xA000);
+    assert_eq!(cpu.irq_handler_address(1), 0xB000);
+    assert_eq!(cpu.irq_handler_address(2), 0xC000);
+
+    cpu.process(false, Some(2));
+
+    assert_eq!(cpu.program_counter(), 0xC000);
+}
+
+#[test]
+fn irq_table_entries_wrap_at_the_top_of_memory() {
+    let mut cpu = machine(&[]);
+
+    cpu.set_irq_vector(0xF000);
+    cpu.memory_controller.poke_bytes(0xF000, &[0xFE, 0xFF]).unwrap();
+    cpu.memory_controller.write16(0x0000, 0x1234);
+
+    assert_eq!(cpu.irq_handler_address(1), 0x1234);
+}
+
+#[test]
+fn nmi_wins_over_a_simultaneous_irq() {
+    let mut cpu = machine_with_irq_table(&[0x3E, 0x00], &[0xA000]); // NOP
+
+    cpu.memory_controller.poke_bytes(NMI_VECTOR, &[0x00, 0xB0]).unwrap();
+
+    let info = cpu.step_debug_with_interrupts(true, Some(0));
+
+    assert_eq!(info.interrupt, Some(Interrupt::Nmi));
+    assert_eq!(cpu.program_counter(), 0xB000);
+}
+
+#[test]
+fn irq_preempts_a_running_program() {
+    // ADC A, #1 / ADC A, #1
+    let mut cpu = machine_with_irq_table(&[0x01, 0x10, 0x01, 0x00, 0x01, 0x10, 0x01, 0x00], &[0xA000]);
+
+    cpu.process(false, None);
+    cpu.process(false, Some(0));
+
+    assert_eq!(cpu.program_counter(), 0xA000);
+    assert_eq!(cpu.a(), 1);
+    assert!(cpu.get_flag(Flag::InterruptDisable));
+
+    // The status was pushed before the interrupt disable flag was set
+    assert_eq!(cpu.pop16() as u8 & Flag::InterruptDisable.mask(), 0);
+    assert_eq!(cpu.pop16(), 0x8004);
+}
+
+#[test]
+fn masked_irq_stays_pending() {
+    // ADC A, #1 / ADC A, #1
+    let mut cpu = machine_with_irq_table(&[0x01, 0x10, 0x01, 0x00, 0x01, 0x10, 0x01, 0x00], &[0xA000]);
+
+    cpu.set_flag(Flag::InterruptDisable, true);
+    cpu.process(false, Some(0));
+
+    assert_eq!(cpu.program_counter(), 0x8004);
+    assert_eq!(cpu.a(), 1);
+}
+
+#[test]
+fn wai_idles_until_an_interrupt() {
+    let mut cpu = machine(&[0x32, 0x00, 0x3E, 0x00]); // WAI / NOP
+
+    cpu.memory_controller.poke_bytes(NMI_VECTOR, &[0x00, 0x90]).unwrap();
+    cpu.process(false, None);
+
+    assert!(cpu.is_waiting());
+
+    cpu.process(false, None);
+
+    assert!(cpu.is_waiting());
+    assert_eq!(cpu.program_counter(), 0x8002);
+
+    cpu.process(true, None);
+
+    assert!(!cpu.is_waiting());
+    assert_eq!(cpu.program_counter(), 0x9000);
+}
+
+#[test]
+fn masked_irq_wakes_wai_without_being_serviced() {
+    // WAI / ADC A, #1
+    let mut cpu = machine_with_irq_table(&[0x32, 0x00, 0x01, 0x10, 0x01, 0x00], &[0xA000]);
+
+    cpu.set_flag(Flag::InterruptDisable, true);
+    cpu.process(false, None);
+    cpu.process(false, Some(0));
+
+    assert!(!cpu.is_waiting());
+
+    cpu.process(false, Some(0));
+
+    assert_eq!(cpu.a(), 1);
+}
+
+#[test]
+fn every_enabled_step_takes_a_cycle() {
+    let mut cpu = machine(&[0x3E, 0x00, 0x32, 0x00]); // NOP / WAI
+
+    for _ in 0..3 {
+        cpu.process(false, None);
+    }
+
+    assert_eq!(cpu.cycles(), 3);
+
+    cpu.halt();
+    cpu.process(false, None);
+
+    assert_eq!(cpu.cycles(), 3);
+}
+
+#[test]
+fn bit_sets_flags_without_storing() {
+    // BIT AL, [$0010] / BIT AL, [$0010]
+    let mut cpu = machine(&[0x44, 0x17, 0x10, 0x00, 0x44, 0x17, 0x10, 0x00]);
+
+    cpu.set_a(0x0001);
+    cpu.memory_controller.write8(0x0010, 0xC0);
+    cpu.process(false, None);
+
+    assert!(cpu.get_flag(Flag::Zero));
+    assert!(cpu.get_flag(Flag::Sign));
+    assert!(cpu.get_flag(Flag::Overflow));
+    assert_eq!(cpu.a(), 0x0001);
+    assert_eq!(cpu.memory_controller.read8(0x0010), 0xC0);
+
+    cpu.memory_controller.write8(0x0010, 0x01);
+    cpu.process(false, None);
+
+    assert!(!cpu.get_flag(Flag::Zero));
+    assert!(!cpu.get_flag(Flag::Sign));
+    assert!(!cpu.get_flag(Flag::Overflow));
+    assert_eq!(cpu.disassemble(0x8000).0, "BIT AL, [$0010]");
+}
+
+#[test]
+fn index_increments_and_decrements_wrap_and_keep_carry() {
+    // DEY / INY / INX / DEX / DEX
+    let mut cpu = machine(&[0x08, 0x00, 0x07, 0x00, 0x05, 0x00, 0x06, 0x00, 0x06, 0x00]);
+
+    cpu.set_flag(Flag::Carry, true);
+    cpu.process(false, None);
+
+    assert_eq!(cpu.index_y(), 0xFFFF);
+    assert!(cpu.get_flag(Flag::Sign));
+    assert!(cpu.get_flag(Flag::Carry));
+
+    cpu.process(false, None);
+
+    assert_eq!(cpu.index_y(), 0x0000);
+    assert!(cpu.get_flag(Flag::Zero));
+    assert!(cpu.get_flag(Flag::Carry));
+
+    for _ in 0..3 {
+        cpu.process(false, None);
+    }
+
+    assert_eq!(cpu.index_x(), 0xFFFF);
+    assert_eq!(cpu.disassemble(0x8000).0, "DEY");
+}
+
+#[test]
+fn swap_exchanges_bytes_or_nibbles() {
+    // SWAP A / SWAP BL / SWAP [$0010] / STP
+    let mut cpu = machine(&[0x09, 0x10, 0x49, 0x20, 0x09, 0x70, 0x10, 0x00, 0x30, 0x00]);
+
+    cpu.set_a(0x1234);
+    cpu.set_b(0x00AB);
+    cpu.set_flag(Flag::Carry, true);
+    cpu.memory_controller.write16(0x0010, 0xFF00);
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0x3412);
+    assert_eq!(cpu.b(), 0x00BA);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x00FF);
+    assert!(cpu.get_flag(Flag::Carry));
+    assert_eq!(cpu.disassemble(0x8002).0, "SWAP BL");
+    assert_eq!(cpu.disassemble(0x8004), ("SWAP [$0010]".to_string(), 4));
+}
+#[test]
+fn on_halt_runs_once_when_the_cpu_stops() {
+    // NOP / STP
+    let mut cpu = machine(&[0x3E, 0x00, 0x30, 0x00]);
+    let halts = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&halts);
+
+    cpu.set_on_halt(move || counter.set(counter.get() + 1));
+    cpu.process(false, None);
+
+    assert!(!cpu.is_halted());
+
+    for _ in 0..3 {
+        cpu.process(false, None);
+    }
+
+    assert!(cpu.is_halted());
+    assert!(!cpu.is_waiting());
+    assert_eq!(halts.get(), 1);
+}
+
+#[test]
+fn jtb_jumps_through_the_indexed_table_entry() {
+    // JTB [$9000], A
+    let mut cpu = machine(&[0x0A, 0x71, 0x00, 0x90]);
+
+    cpu.memory_controller.poke_bytes(0x9000, &[0x00, 0xA0, 0x00, 0xB0, 0x00, 0xC0]).unwrap();
+    cpu.set_a(2);
+    cpu.process(false, None);
+
+    assert_eq!(cpu.program_counter(), 0xC000);
+    assert_eq!(cpu.disassemble(0x8000).0, "JTB [$9000], A");
+}
+
+#[test]
+fn dbnz_loops_until_the_counter_reaches_zero() {
+    // MOV A, #3 / loop: ADC B, #1 / DBNZ A, loop / STP
+    let mut cpu = machine(&[0x00, 0x10, 0x03, 0x00, 0x01, 0x20, 0x01, 0x00, 0x0B, 0x10, 0xF8, 0xFF, 0x30, 0x00]);
+    cpu.run();
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.a(), 0);
+    assert_eq!(cpu.b(), 3);
+}
+
+#[test]
+fn dbnz_byte_mode_counts_the_low_byte() {
+    // MOV A, #$1202 / loop: ADC B, #1 / DBNZ AL, loop / STP
+    let mut cpu = machine(&[0x00, 0x10, 0x02, 0x12, 0x01, 0x20, 0x01, 0x00, 0x4B, 0x10, 0xF9, 0x30, 0x00]);
+    cpu.run();
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.a(), 0x1200);
+    assert_eq!(cpu.b(), 2);
+}
+
+#[test]
+fn byte_index_modes_only_add_the_low_byte_of_idx() {
+    // MOV A, [$0100+XL] / MOV B, [IDY+XL]
+    let mut cpu = machine(&[0x3F, 0x04, 0x00, 0x10, 0x00, 0x01, 0x3F, 0x05, 0x00, 0x20]);
+
+    cpu.memory_controller.write16(0x0112, 0xBEEF);
+    cpu.memory_controller.write16(0x0212, 0xCAFE);
+    cpu.set_index_x(0xFF12);
+    cpu.set_index_y(0x0200);
+    cpu.process(false, None);
+    cpu.process(false, None);
+
+    assert_eq!(cpu.a(), 0xBEEF);
+    assert_eq!(cpu.b(), 0xCAFE);
+    assert_eq!(cpu.disassemble(0x8000), ("MOV A, [$0100+XL]".to_string(), 6));
+    assert_eq!(cpu.disassemble(0x8006).0, "MOV B, [IDY+XL]");
+}
+
+#[test]
+fn zero_page_operands_take_one_address_byte() {
+    // MOV [$42], #$1234 / MOV A, [$42]
+    let mut cpu = machine(&[0x3F, 0x60, 0x00, 0x00, 0x34, 0x12, 0x42, 0x3F, 0x06, 0x00, 0x10, 0x42]);
+
+    let info = cpu.step_debug();
+
+    assert_eq!(info.bytes, 7);
+    assert_eq!(cpu.memory_controller.read16(0x0042), 0x1234);
+    assert_eq!(cpu.disassemble(0x8000), ("MOV [$42], #$1234".to_string(), 7));
+
+    cpu.process(false, None);
+
+    assert_eq!(cpu.a(), 0x1234);
+}
+
+const COUNTING_LOOP: &str = "MOV B, #$FFFF\nloop: ADC A, #1\nMOV [$0010], A\nMOV [$1000], A\nDBNZ B, loop";
+
+// The counting loop with a timer at 0x1000, so its reload register follows A
+fn counting_machine() -> CPU {
+    let mut cpu = machine(&program(COUNTING_LOOP));
+
+    cpu.memory_controller.map_device(1, 1, Box::new(Timer::new())).unwrap();
+
+    cpu
+}
+
+fn step(cpu: &mut CPU, steps: usize) {
+    for _ in 0..steps {
+        cpu.process(false, None);
+    }
+}
+
+#[test]
+fn rewind_matches_stepping_to_the_same_point() {
+    let mut rewound = counting_machine();
+
+    rewound.enable_history(4, 8);
+    step(&mut rewound, 20);
+    rewound.rewind(5).unwrap();
+
+    let mut stepped = counting_machine();
+
+    step(&mut stepped, 15);
+
+    assert_eq!(rewound.snapshot(), stepped.snapshot());
+    assert_eq!(rewound.cycles(), stepped.cycles());
+    assert_eq!(rewound.history_steps(), 15);
+}
+
+#[test]
+fn rewind_restores_device_registers() {
+    let mut cpu = counting_machine();
+
+    cpu.enable_history(3, 8);
+    step(&mut cpu, 13);
+
+    let reload = cpu.memory_controller.read16(0x1000 + TIMER_RELOAD);
+
+    step(&mut cpu, 8);
+
+    assert_ne!(cpu.memory_controller.read16(0x1000 + TIMER_RELOAD), reload);
+
+    cpu.rewind(8).unwrap();
+
+    assert_eq!(cpu.memory_controller.read16(0x1000 + TIMER_RELOAD), reload);
+    assert_eq!(cpu.memory_controller.read16(0x0010), reload);
+}
+
+#[test]
+fn rewind_is_limited_to_the_recorded_steps() {
+    let mut cpu = counting_machine();
+
+    assert_eq!(cpu.step_back(), Err(RewindError::HistoryDisabled));
+
+    cpu.enable_history(2, 2);
+    step(&mut cpu, 10);
+
+    // Two checkpoints two steps apart reach back to step 6
+    assert_eq!(cpu.history_steps(), 4);
+    assert_eq!(cpu.rewind(5), Err(RewindError::NotEnoughHistory { requested: 5, available: 4 }));
+
+    cpu.step_back().unwrap();
+
+    assert_eq!(cpu.history_steps(), 3);
+}
+
+#[test]
+fn operations_are_declared_in_opcode_order() {
+    for (i, operation) in Operation::ALL.iter().enumerate() {
+        assert_eq!(*operation as usize, i);
+    }
+
+    // Each operation first decodes from a higher opcode than the one before it, with ILLEGAL last
+    let mut first_opcodes = Vec::new();
+
+    for opcode in 0..=encode::OPERATION_MASK {
+        let operation = Operation::get_operation_from_instruction(opcode);
+
+        if !matches!(operation, Operation::Illegal) && !first_opcodes.contains(&(operation as usize)) {
+            first_opcodes.push(operation as usize);
+        }
+    }
+
+    first_opcodes.push(Operation::Illegal as usize);
+
+    assert_eq!(first_opcodes, (0..Operation::ALL.len()).collect::<Vec<_>>());
+}
+
+#[test]
+fn profile_counts_each_operation() {
+    let mut cpu = machine(&program("
+        MOV A, #$0003
+    loop:
+        DEX
+        ADC A, #$0001
+        MOVZ B, A
+        CMP A, #$0006
+        BLO loop
+        STP
+    "));
+
+    cpu.enable_profiling();
+    step(&mut cpu, 17);
+
+    let profile = cpu.profile();
+
+    assert_eq!(profile.count("MOV"), 1);
+    assert_eq!(profile.count("dex"), 3);
+    assert_eq!(profile.count("ADC"), 3);
+    assert_eq!(profile.count("MOVIF"), 3);
+    assert_eq!(profile.count("BLO"), 3);
+    assert_eq!(profile.count("STP"), 1);
+    assert_eq!(profile.count("FOO"), 0);
+    assert_eq!(profile.total(), 17);
+    assert_eq!(profile.iter().count(), Operation::ALL.len());
+    assert!(profile.iter().map(|(mnemonic, _)| mnemonic).eq(Operation::ALL.iter().map(Operation::mnemonic)));
+}
+
+#[test]
+fn profile_only_counts_while_enabled() {
+    let mut cpu = machine(&program("
+        NOP
+        NOP
+        NOP
+        NOP
+    "));
+
+    step(&mut cpu, 1);
+    cpu.enable_profiling();
+    step(&mut cpu, 2);
+    cpu.disable_profiling();
+    step(&mut cpu, 1);
+
+    assert_eq!(cpu.profile().count("NOP"), 2);
+
+    cpu.reset_profile();
+
+    assert_eq!(cpu.profile().total(), 0);
+}
+
+#[test]
+fn big_endian_reset_vector_is_read_high_byte_first() {
+    let mut cpu = CPU::new();
+
+    cpu.memory_controller.set_endianness(Endianness::Big);
+    cpu.memory_controller.map_device(0, 1, Box::new(RAM::new(RAM_CAPACITY))).unwrap();
+
+    let rom = cpu.memory_controller.map_device(ROM_FIRST_BLOCK, ROM_CAPACITY / MAP_BLOCK_SIZE, Box::new(ROM::new(ROM_CAPACITY))).unwrap();
+
+    cpu.load_program_at(rom, 0x0100, &program("STP"), 0x8100).unwrap();
+    cpu.reset();
+
+    assert_eq!(cpu.memory_controller.read8(cpu.reset_vector()), 0x81);
+    assert_eq!(cpu.program_counter(), 0x8100);
+}
+
+#[test]
+fn swp_exchanges_a_register_with_memory() {
+    let mut cpu = machine(&program("
+        MOV [$0010], #$1234
+        MOV A, #$0001
+        SWP A, [$0010]
+        STP
+    "));
+
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0x1234);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x0001);
+}
+
+#[test]
+fn swp_sets_flags_from_the_old_register_value() {
+    let mut cpu = machine(&program("
+        MOV [$0010], #$0001
+        SWP A, [$0010]
+        STP
+    "));
+
+    cpu.run();
+
+    // A held zero before the swap, so a spinlock can test the flags for whether it took the lock
+    assert!(cpu.get_flag(Flag::Zero));
+    assert_eq!(cpu.a(), 0x0001);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x0000);
+}
+
+#[test]
+fn swp_byte_mode_exchanges_one_byte() {
+    let mut cpu = machine(&program("
+        MOV [$0010], #$1234
+        MOV A, #$ABCD
+        SWP AL, [$0010]
+        STP
+    "));
+
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0xAB34);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x12CD);
+}
+
+#[test]
+fn diff_lists_only_what_an_instruction_changed() {
+    let mut cpu = machine(&program("MOV A, #$0000\nMOV B, #$0081\nSTP"));
+
+    cpu.set_a(0x1234);
+
+    let before = cpu.registers();
+
+    cpu.step_debug();
+
+    let diff = before.diff(&cpu.registers());
+
+    diff.assert_changed(&["PC", "A", "ZF", "PF"]);
+    assert!(diff.changes.contains(&RegisterChange { name: "A", old: 0x1234, new: 0x0000 }));
+    assert!(diff.changes.contains(&RegisterChange { name: "ZF", old: 0, new: 1 }));
+
+    let before = cpu.registers();
+
+    cpu.step_debug();
+
+    // Two bits set keeps the parity even, so only the zero flag moves
+    before.diff(&cpu.registers()).assert_changed(&["PC", "B", "ZF"]);
+    assert!(cpu.registers().diff(&cpu.registers()).is_empty());
+}
+
+#[test]
+fn diff_displays_old_and_new_values() {
+    let old = CPU::new().registers();
+    let new = Registers { a: 0x0042, ..old };
+
+    assert_eq!(old.diff(&new).to_string(), "A: 0x0000 -> 0x0042");
+    assert_eq!(old.diff(&old).to_string(), "no changes");
+}
+
+#[test]
+#[should_panic(expected = "Expected changes to [\"B\"]")]
+fn assert_changed_panics_on_other_changes() {
+    let old = CPU::new().registers();
+    let new = Registers { a: 0x0042, ..old };
+
+    old.diff(&new).assert_changed(&["B"]);
+}
+
+fn first_word(code: &[u8]) -> u16 {
+    u16::from_le_bytes([code[0], code[1]])
+}
+
+#[test]
+fn instruction_length_counts_operand_bytes() {
+    assert_eq!(instruction_length(first_word(&program("MOV A, #$1234"))), 4);
+    assert_eq!(instruction_length(first_word(&program("MOV A, B"))), 2);
+    assert_eq!(instruction_length(first_word(&program("MOV AL, #$12"))), 3);
+    assert_eq!(instruction_length(first_word(&program("MOV [$1000], #$1234"))), 6);
+    assert_eq!(instruction_length(first_word(&program("STP"))), 2);
+}
+
+#[test]
+fn instruction_length_matches_what_the_assembler_emits() {
+    let sources = [
+        "MOV A, [$42]",
+        "MOV [$0100+XL], AL",
+        "ADC A, [[$1234]+IDX]",
+        "loop: BGT loop",
+        "SWAP A",
+        "JTB [$9000], A",
+        "ADCL A:B, C:D",
+        "MOVNZ B, #$0001",
+        "MOV A, [IDY+]",
+        "MOV [SP+$04], #$1234",
+        "LEA A, [PC+$0010]",
+    ];
+
+    for source in sources {
+        let code = program(source);
+        let cpu = machine(&code);
+
+        assert_eq!(cpu.instruction_length_at(0x8000), code.len(), "{}", source);
+
+        if !matches!(Operation::get_operation_from_instruction(first_word(&code)), Operation::Ext) {
+            assert_eq!(instruction_length(first_word(&code)), code.len(), "{}", source);
+        }
+    }
+}
+
+#[test]
+fn warm_reset_keeps_memory_and_revectors() {
+    let mut cpu = machine(&program("MOV [$0010], #$1234\nMOV A, #$5678\nINX\nSTP"));
+
+    cpu.run();
+
+    cpu.reset_warm();
+
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.program_counter(), 0x8000);
+    assert_eq!(cpu.a(), 0x0000);
+    assert_eq!(cpu.index_x(), 0x0000);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x1234);
+
+    cpu.reset();
+
+    assert_eq!(cpu.program_counter(), 0x8000);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x0000);
+}
+
+// Every address is RAM, so code can run off the top of memory. Reset and ready for code to be poked in
+fn all_ram_machine() -> CPU {
+    let mut cpu = CPU::new();
+
+    cpu.memory_controller.map_device(0, MAP_BLOCKS, Box::new(RAM::new(ADDRESS_SPACE))).unwrap();
+    cpu.reset();
+
+    cpu
+}
+
+#[test]
+fn fetch_wraps_past_the_top_of_memory() {
+    let mut cpu = all_ram_machine();
+    let code = program("MOV A, #$1234");
+
+    cpu.memory_controller.poke_bytes(0xFFFE, &code[..2]).unwrap();
+    cpu.memory_controller.poke_bytes(0x0000, &code[2..]).unwrap();
+    cpu.jump_to(0xFFFE);
+    cpu.step_debug();
+
+    assert_eq!(cpu.a(), 0x1234);
+    assert_eq!(cpu.program_counter(), 0x0002);
+}
+
+#[test]
+fn instruction_word_split_across_the_top_of_memory() {
+    let mut cpu = all_ram_machine();
+    let code = program("MOV A, #$1234");
+
+    cpu.memory_controller.write8(0xFFFF, code[0]);
+    cpu.memory_controller.poke_bytes(0x0000, &code[1..]).unwrap();
+    cpu.jump_to(0xFFFF);
+
+    let info = cpu.step_debug();
+
+    assert_eq!(info.operation, Some("MOV"));
+    assert_eq!(info.bytes, 4);
+    assert_eq!(cpu.a(), 0x1234);
+    assert_eq!(cpu.program_counter(), 0x0003);
+}
+
+#[test]
+fn illegal_opcode_halts_when_trapped() {
+    let mut cpu = machine(&[0x33, 0x00, 0x33, 0x00]);
+
+    cpu.set_trap_illegal_instructions(true);
+
+    let info = cpu.step_debug();
+
+    assert_eq!(info.operation, Some("ILLEGAL"));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.illegal_instruction(), Some(IllegalInstruction { program_counter: 0x8000, instruction: 0x0033 }));
+
+    // Reset clears the status
+    cpu.reset();
+
+    assert_eq!(cpu.illegal_instruction(), None);
+}
+
+#[test]
+fn illegal_opcode_runs_as_nop_when_not_trapped() {
+    let mut cpu = machine(&[0x33, 0x00, 0x33, 0x00]);
+
+    assert!(!cpu.traps_illegal_instructions());
+
+    step(&mut cpu, 2);
+
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.illegal_instruction(), None);
+    assert_eq!(cpu.program_counter(), 0x8004);
+}
+
+#[test]
+fn stack_starts_at_the_end_of_ram() {
+    let mut cpu = machine(&program("STP"));
+
+    assert_eq!(cpu.stack_pointer(), RAM_CAPACITY as u16);
+
+    let reset_vector = cpu.memory_controller.read16(cpu.reset_vector());
+
+    cpu.push16(0x1234);
+
+    assert_eq!(cpu.stack_pointer(), 0x0FFE);
+    assert_eq!(cpu.memory_controller.read16(0x0FFE), 0x1234);
+    assert_eq!(cpu.memory_controller.read16(cpu.reset_vector()), reset_vector);
+    assert_eq!(cpu.pop16(), 0x1234);
+    assert_eq!(cpu.stack_pointer(), RAM_CAPACITY as u16);
+}
+
+#[test]
+fn set_stack_top_takes_effect_on_reset() {
+    let mut cpu = machine(&program("STP"));
+
+    cpu.set_stack_top(0x0800);
+
+    assert_eq!(cpu.stack_pointer(), RAM_CAPACITY as u16);
+
+    cpu.reset();
+
+    assert_eq!(cpu.stack_top(), 0x0800);
+    assert_eq!(cpu.stack_pointer(), 0x0800);
+}
+
+#[test]
+fn stack_top_without_ram_is_the_top_of_memory() {
+    let cpu = CPU::new();
+
+    // The first push wraps to 0xFFFE
+    assert_eq!(cpu.stack_top(), 0x0000);
+}
+
+#[test]
+fn disassemble_range_walks_consecutive_instructions() {
+    let cpu = machine(&program("MOV A, #$1234\nMOV AL, #$80\nMOV A, [IDY+]\nSTP"));
+
+    assert_eq!(
+        cpu.disassemble_range(0x8000, 3),
+        [
+            (0x8000, "MOV A, #$1234".to_string()),
+            (0x8004, "MOV AL, #$80".to_string()),
+            (0x8007, "MOV A, [IDY+]".to_string()),
+        ]
+    );
+    assert_eq!(cpu.disassemble_range(0x8007, 2)[1], (0x800B, "STP".to_string()));
+    assert!(cpu.disassemble_range(0x8000, 0).is_empty());
+}
+
+#[test]
+fn disassemble_range_wraps_at_the_top_of_memory() {
+    let mut cpu = all_ram_machine();
+
+    let code = program("MOV A, #$1234\nSTP");
+
+    cpu.memory_controller.poke_bytes(0xFFFC, &code[..4]).unwrap();
+    cpu.memory_controller.poke_bytes(0x0000, &code[4..]).unwrap();
+
+    let lines = cpu.disassemble_range(0xFFFC, 2);
+
+    assert_eq!(lines[0], (0xFFFC, "MOV A, #$1234".to_string()));
+    assert_eq!(lines[1], (0x0000, "STP".to_string()));
+}
+
+// Whether the branch is taken after comparing the first value with the second
+fn branch_taken(branch: &str, destination: u16, source: u16) -> bool {
+    let mut cpu = machine(&program(&format!("
+        MOV A, #${:04X}
+        CMP A, #${:04X}
+        {} taken
+        MOV B, #$0001
+        STP
+    taken:
+        MOV B, #$0002
+        STP
+    ", destination, source, branch)));
+
+    cpu.run();
+
+    cpu.b() == 0x0002
+}
+
+#[test]
+fn signed_and_unsigned_branches_disagree_on_the_sign_bit() {
+    // 0x8000 is -32768 signed but 32768 unsigned
+    assert!(branch_taken("BLT", 0x8000, 0x0001));
+    assert!(branch_taken("BLE", 0x8000, 0x0001));
+    assert!(!branch_taken("BGT", 0x8000, 0x0001));
+    assert!(!branch_taken("BGE", 0x8000, 0x0001));
+    assert!(branch_taken("BHI", 0x8000, 0x0001));
+    assert!(branch_taken("BHS", 0x8000, 0x0001));
+    assert!(!branch_taken("BLO", 0x8000, 0x0001));
+    assert!(!branch_taken("BLS", 0x8000, 0x0001));
+}
+
+#[test]
+fn branches_on_equal_values() {
+    for (branch, taken) in [("BGT", false), ("BLT", false), ("BGE", true), ("BLE", true), ("BHI", false), ("BLO", false), ("BHS", true), ("BLS", true)] {
+        assert_eq!(branch_taken(branch, 0x1234, 0x1234), taken, "{}", branch);
+    }
+}
+
+#[test]
+fn signed_branches_follow_overflow() {
+    // 0x7FFF - 0xFFFF overflows, 32767 is still greater than -1
+    assert!(branch_taken("BGT", 0x7FFF, 0xFFFF));
+    assert!(!branch_taken("BLT", 0x7FFF, 0xFFFF));
+    assert!(branch_taken("BLO", 0x7FFF, 0xFFFF));
+}
+
+// Writes a STP into RAM at 0x0100 and jumps to it through a one-entry table
+const JUMP_TO_RAM: &str = "
+    MOV [$0100], #$0030
+    MOV [$0200], #$0100
+    JTB [$0200], A
+";
+
+#[test]
+fn execute_guard_halts_on_a_fetch_from_non_executable_ram() {
+    let mut cpu = machine(&program(JUMP_TO_RAM));
+
+    cpu.memory_controller.set_executable(0, false).unwrap();
+    cpu.set_execute_guard(true);
+    step(&mut cpu, 4);
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.execute_violation(), Some(0x0100));
+    assert_eq!(cpu.program_counter(), 0x0100);
+
+    cpu.reset();
+
+    assert_eq!(cpu.execute_violation(), None);
+}
+
+#[test]
+fn non_executable_ram_runs_without_the_guard() {
+    let mut cpu = machine(&program(JUMP_TO_RAM));
+
+    cpu.memory_controller.set_executable(0, false).unwrap();
+
+    assert!(!cpu.has_execute_guard());
+    assert!(!cpu.memory_controller.is_executable(0x0100));
+    assert!(cpu.memory_controller.is_executable(0x8000));
+
+    step(&mut cpu, 4);
+
+    // The STP in RAM ran
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.execute_violation(), None);
+    assert_eq!(cpu.program_counter(), 0x0102);
+}
+
+#[test]
+fn load_program_at_points_reset_at_the_entry() {
+    let mut cpu = CPU::new();
+
+    let rom = cpu.memory_controller.map_device(ROM_FIRST_BLOCK, ROM_CAPACITY / MAP_BLOCK_SIZE, Box::new(ROM::new(ROM_CAPACITY))).unwrap();
+
+    cpu.load_program_at(rom, 0x0200, &program("MOV A, #$1234\nSTP"), 0x8200).unwrap();
+
+    assert_eq!(cpu.memory_controller.read8(cpu.reset_vector()), 0x00);
+    assert_eq!(cpu.memory_controller.read8(cpu.reset_vector() + 1), 0x82);
+
+    cpu.reset();
+
+    assert_eq!(cpu.program_counter(), 0x8200);
+
+    step(&mut cpu, 2);
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.a(), 0x1234);
+}
+
+#[test]
+fn load_program_at_rejects_a_bad_rom() {
+    let mut cpu = CPU::new();
+
+    let rom = cpu.memory_controller.map_device(ROM_FIRST_BLOCK, ROM_CAPACITY / MAP_BLOCK_SIZE, Box::new(ROM::new(ROM_CAPACITY))).unwrap();
+
+    assert_eq!(cpu.load_program_at(rom + 1, 0, &[0x30, 0x00], 0x8000), Err(MemoryError::IndexOutOfBounds { index: rom + 1 }));
+    assert_eq!(
+        cpu.load_program_at(rom, ROM_CAPACITY - 1, &[0x30, 0x00], 0x8000),
+        Err(MemoryError::RangeOutOfBounds { address: ROM_CAPACITY - 1, count: 2, size: ROM_CAPACITY })
+    );
+}
+
+// Runs ADCL or SBCL on A:B and C:D with the carry flag as given, returning the machine after it
+fn pair_arithmetic(operation: &str, lhs: u32, rhs: u32, carry: bool) -> CPU {
+    let mut cpu = machine(&program(&format!("{} A:B, C:D\nSTP", operation)));
+
+    cpu.set_a((lhs >> 16) as u16);
+    cpu.set_b(lhs as u16);
+    cpu.set_c((rhs >> 16) as u16);
+    cpu.set_d(rhs as u16);
+    cpu.set_flag(Flag::Carry, carry);
+    cpu.step_debug();
+
+    cpu
+}
+
+fn pair(cpu: &CPU) -> u32 {
+    (cpu.a() as u32) << 16 | cpu.b() as u32
+}
+
+#[test]
+fn adcl_carries_between_the_words() {
+    let cpu = pair_arithmetic("ADCL", 0x0001_FFFF, 0x0000_0001, false);
+
+    assert_eq!(pair(&cpu), 0x0002_0000);
+    assert_eq!((cpu.c(), cpu.d()), (0x0000, 0x0001));
+    assert!(!cpu.get_flag(Flag::Carry));
+    assert!(!cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn adcl_sets_flags_from_the_32_bit_result() {
+    let cpu = pair_arithmetic("ADCL", 0xFFFF_FFFF, 0x0000_0000, true);
+
+    assert_eq!(pair(&cpu), 0x0000_0000);
+    assert!(cpu.get_flag(Flag::Carry));
+    assert!(cpu.get_flag(Flag::Zero));
+    assert!(!cpu.get_flag(Flag::Overflow));
+
+    let cpu = pair_arithmetic("ADCL", 0x7FFF_FFFF, 0x0000_0001, false);
+
+    assert_eq!(pair(&cpu), 0x8000_0000);
+    assert!(cpu.get_flag(Flag::Overflow));
+    assert!(cpu.get_flag(Flag::Sign));
+    assert!(!cpu.get_flag(Flag::Carry));
+}
+
+#[test]
+fn sbcl_borrows_between_the_words() {
+    let cpu = pair_arithmetic("SBCL", 0x0002_0000, 0x0000_0001, true);
+
+    assert_eq!(pair(&cpu), 0x0001_FFFF);
+    assert!(cpu.get_flag(Flag::Carry));
+
+    // A clear carry borrows one more, and going below zero clears it
+    let cpu = pair_arithmetic("SBCL", 0x0000_0001, 0x0000_0001, false);
+
+    assert_eq!(pair(&cpu), 0xFFFF_FFFF);
+    assert!(!cpu.get_flag(Flag::Carry));
+    assert!(cpu.get_flag(Flag::Sign));
+
+    let cpu = pair_arithmetic("SBCL", 0x8000_0000, 0x0000_0001, true);
+
+    assert_eq!(pair(&cpu), 0x7FFF_FFFF);
+    assert!(cpu.get_flag(Flag::Overflow));
+}
+
+#[test]
+fn branch_to_itself_is_stuck() {
+    let mut cpu = machine(&program("loop: BGE loop"));
+
+    cpu.set_flag(Flag::Zero, true);
+
+    assert!(cpu.step_debug().stuck);
+    assert!(!cpu.is_halted());
+
+    cpu.set_halt_when_stuck(true);
+
+    assert!(cpu.step_debug().stuck);
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.program_counter(), 0x8000);
+}
+
+#[test]
+fn loop_counting_in_memory_is_not_stuck() {
+    let code = program("
+        MOV [$0100], #5
+    loop:
+        DBNZ [$0100], loop
+        STP
+    ");
+    let mut cpu = machine(&code);
+
+    cpu.set_halt_when_stuck(true);
+
+    for _ in 0..6 {
+        assert!(!cpu.step_debug().stuck);
+    }
+
+    assert_eq!(cpu.memory_controller.read16(0x0100), 0x0000);
+
+    cpu.step_debug();
+
+    // Halted by the STP, after the counter ran out
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.program_counter() as usize, ROM_FIRST_ADDRESS as usize + code.len());
+}
+
+#[test]
+fn bus_writes_count_every_write() {
+    let mut cpu = machine(&program("STP"));
+    let writes = cpu.memory_controller.bus_writes();
+
+    cpu.memory_controller.write8(0x0010, 0x12);
+    cpu.memory_controller.write16(0x5000, 0x3456);
+    cpu.push16(0x7890);
+
+    assert_eq!(cpu.memory_controller.bus_writes() - writes, 3);
+}
+
+#[test]
+fn set_flag_sets_only_its_status_bit() {
+    let mut cpu = CPU::new();
+
+    cpu.set_flag(Flag::Carry, true);
+
+    assert!(cpu.get_flag(Flag::Carry));
+    assert_eq!(cpu.status(), CARRY_FLAG);
+
+    for flag in Flag::ALL {
+        cpu.set_flag(flag, true);
+    }
+
+    cpu.set_flag(Flag::Carry, false);
+
+    assert!(!cpu.get_flag(Flag::Carry));
+    assert_eq!(cpu.status(), SIGN_FLAG | ZERO_FLAG | PARITY_FLAG | OVERFLOW_FLAG | INTERRUPT_DISABLE_FLAG);
+}
+
+#[test]
+fn decode_status_letters_set_flags() {
+    assert_eq!(decode_status(SIGN_FLAG | CARRY_FLAG), "S--C----");
+    assert_eq!(decode_status(0x00), "--------");
+    assert_eq!(decode_status(ZERO_FLAG | PARITY_FLAG | OVERFLOW_FLAG | INTERRUPT_DISABLE_FLAG), "-ZP-OI--");
+
+    // Unused bits stay blank even when set
+    assert_eq!(decode_status(0xFF), "SZPCOI--");
+}
+
+#[test]
+fn nop_only_advances_the_program_counter() {
+    let code = program("NOP");
+
+    assert_eq!(code, encode::OPCODE_NOP.to_le_bytes());
+
+    let mut cpu = machine(&code);
+
+    cpu.set_trap_illegal_instructions(true);
+    cpu.set_a(0x1234);
+    cpu.set_status(CARRY_FLAG | ZERO_FLAG);
+
+    let before = cpu.registers();
+    let info = cpu.step_debug();
+
+    before.diff(&cpu.registers()).assert_changed(&["PC"]);
+    assert_eq!(cpu.program_counter(), 0x8002);
+    assert_eq!(info.operation, Some("NOP"));
+    assert!(!cpu.is_halted());
+}
+
+const PC_RELATIVE_LOAD: &str = "
+    MOV A, [PC+data]
+    STP
+data:
+    DW $1234
+";
+
+#[test]
+fn pc_relative_operands_reach_data_wherever_the_code_is_loaded() {
+    for offset in [0x0000, 0x0100, 0x1234] {
+        let mut cpu = CPU::new();
+
+        let rom = cpu.memory_controller.map_device(ROM_FIRST_BLOCK, ROM_CAPACITY / MAP_BLOCK_SIZE, Box::new(ROM::new(ROM_CAPACITY))).unwrap();
+
+        cpu.load_program_at(rom, offset, &program(PC_RELATIVE_LOAD), ROM_FIRST_ADDRESS + offset as u16).unwrap();
+        cpu.reset();
+        step(&mut cpu, 2);
+
+        assert_eq!(cpu.a(), 0x1234, "loaded at 0x{:04X}", offset);
+    }
+}
+
+#[test]
+fn pc_relative_offsets_count_from_after_the_operand() {
+    // The EXT prefix, instruction and offset put the reference point at 0x8006, just before the STP
+    let mut cpu = machine(&program("MOV A, [PC+$0002]\nSTP\nDW $5678"));
+
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0x5678);
+
+    // Backwards to the word before the instruction
+    let mut cpu = machine(&program("DW $ABCD\nMOV A, [PC-$0008]\nSTP"));
+
+    cpu.jump_to(0x8002);
+    step(&mut cpu, 2);
+
+    assert_eq!(cpu.a(), 0xABCD);
+    assert!(cpu.is_halted());
+}
+
+#[test]
+fn decoder_reads_back_what_encode_instruction_built() {
+    let instruction = encode::encode_instruction(encode::OPCODE_MOV, false, false, encode::LOCATION_A, encode::LOCATION_IMMEDIATE);
+
+    assert!(matches!(Operation::get_operation_from_instruction(instruction), Operation::Mov));
+    assert!(matches!(Location::get_destination_from_instruction(instruction, 0x0000), Location::A));
+    assert!(matches!(Location::get_source_from_instruction(instruction, 0x0000), Location::Immediate));
+
+    let mut code = instruction.to_le_bytes().to_vec();
+
+    code.extend(0x1234_u16.to_le_bytes());
+
+    assert_eq!(code, program("MOV A, #$1234"));
+    assert_eq!(run_program(&code, 1).a(), 0x1234);
+
+    let extension = encode::encode_extension(encode::EXTENDED_ZERO_PAGE, 0x0);
+
+    assert!(matches!(Location::get_destination_from_instruction(instruction, extension), Location::ZeroPage));
+    assert!(matches!(Location::get_source_from_instruction(instruction, extension), Location::Immediate));
+}
+
+#[test]
+fn run_returns_once_the_program_halts() {
+    let mut cpu = machine(&program("MOV A, #3\nloop: DBNZ A, loop\nMOV B, #$BEEF\nSTP"));
+
+    cpu.run();
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.b(), 0xBEEF);
+    assert_eq!(cpu.cycles(), 6);
+}
+
+#[test]
+fn run_with_interrupts_polls_before_every_step() {
+    let code = program("
+    loop:
+        INX
+        DBNZ A, loop
+        ORG $0100
+        MOV B, #$BEEF
+        STP
+    ");
+    let mut cpu = machine_with_irq_table(&code, &[0x8100]);
+    let mut polls = 0;
+
+    cpu.run_with_interrupts(|| false, || {
+        polls += 1;
+
+        (polls == 5).then_some(0)
+    });
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.b(), 0xBEEF);
+    assert_eq!(cpu.index_x(), 2);
+    assert_eq!(polls, 7);
+}
+
+// (lhs, rhs, carry in, result, overflow, carry out) covering each pair of operand signs
+const ADD_OVERFLOW_CASES16: [(u16, u16, bool, u16, bool, bool); 12] = [
+    (0x1000, 0x2000, false, 0x3000, false, false), // + + no overflow
+    (0x7FFF, 0x0001, false, 0x8000, true, false),  // + + overflows into negative
+    (0x7FFF, 0x0000, true, 0x8000, true, false),   // + + overflows on the carry in alone
+    (0x7FFF, 0x8000, false, 0xFFFF, false, false), // + - can't overflow
+    (0x0001, 0xFFFF, true, 0x0001, false, true),   // + - with carry
+    (0x8000, 0x7FFF, true, 0x0000, false, true),   // - + can't overflow
+    (0xFFFF, 0x0001, false, 0x0000, false, true),  // - + wraps to zero
+    (0xFFFF, 0xFFFF, false, 0xFFFE, false, true),  // - - no overflow
+    (0x8000, 0x8000, false, 0x0000, true, true),   // - - overflows into positive
+    (0x8000, 0xFFFF, false, 0x7FFF, true, true),   // - - overflows into positive
+    (0x8000, 0xFFFF, true, 0x8000, false, true),   // - - the carry in keeps it negative
+    (0x4000, 0x4000, true, 0x8001, true, false),   // + + overflows with carry
+];
+
+const ADD_OVERFLOW_CASES8: [(u8, u8, bool, u8, bool, bool); 12] = [
+    (0x10, 0x20, false, 0x30, false, false),
+    (0x7F, 0x01, false, 0x80, true, false),
+    (0x7F, 0x00, true, 0x80, true, false),
+    (0x7F, 0x80, false, 0xFF, false, false),
+    (0x01, 0xFF, true, 0x01, false, true),
+    (0x80, 0x7F, true, 0x00, false, true),
+    (0xFF, 0x01, false, 0x00, false, true),
+    (0xFF, 0xFF, false, 0xFE, false, true),
+    (0x80, 0x80, false, 0x00, true, true),
+    (0x80, 0xFF, false, 0x7F, true, true),
+    (0x80, 0xFF, true, 0x80, false, true),
+    (0x40, 0x40, true, 0x81, true, false),
+];
+
+#[test]
+fn add_with_carry16_overflow_matrix() {
+    let mut cpu = CPU::new();
+
+    for (lhs, rhs, carry, result, overflow, carry_out) in ADD_OVERFLOW_CASES16 {
+        assert_eq!(cpu.add_with_carry16(lhs, rhs, carry), result, "{lhs:04X} + {rhs:04X} + {carry}");
+        assert_eq!(cpu.get_flag(Flag::Overflow), overflow, "overflow of {lhs:04X} + {rhs:04X} + {carry}");
+        assert_eq!(cpu.get_flag(Flag::Carry), carry_out, "carry of {lhs:04X} + {rhs:04X} + {carry}");
+    }
+}
+
+#[test]
+fn add_with_carry8_overflow_matrix() {
+    let mut cpu = CPU::new();
+
+    for (lhs, rhs, carry, result, overflow, carry_out) in ADD_OVERFLOW_CASES8 {
+        assert_eq!(cpu.add_with_carry8(lhs, rhs, carry), result, "{lhs:02X} + {rhs:02X} + {carry}");
+        assert_eq!(cpu.get_flag(Flag::Overflow), overflow, "overflow of {lhs:02X} + {rhs:02X} + {carry}");
+        assert_eq!(cpu.get_flag(Flag::Carry), carry_out, "carry of {lhs:02X} + {rhs:02X} + {carry}");
+    }
+}
+
+// Every byte-mode sum, checked against signed arithmetic
+#[test]
+fn add_with_carry8_overflow_matches_signed_range() {
+    let mut cpu = CPU::new();
+
+    for lhs in 0..=u8::MAX {
+        for rhs in 0..=u8::MAX {
+            for carry in [false, true] {
+                cpu.add_with_carry8(lhs, rhs, carry);
+
+                let sum = lhs as i8 as i16 + rhs as i8 as i16 + carry as i16;
+
+                assert_eq!(cpu.get_flag(Flag::Overflow), i8::try_from(sum).is_err(), "{lhs:02X} + {rhs:02X} + {carry}");
+            }
+        }
+    }
+}
+
+#[test]
+fn interrupt_log_stamps_each_device_interrupt() {
+    let mut cpu = machine_with_irq_table(&program("NOP\nNOP\nNOP\nNOP\nNOP\nNOP"), &[0x8000, 0x8000]);
+    let mut timer = Timer::new();
+    let mut keyboard = Keyboard::new();
+
+    timer.write16(TIMER_RELOAD, 2);
+    timer.write8(TIMER_CONTROL, 0x01);
+    cpu.enable_interrupt_log(16);
+
+    for cycle in 1..=5 {
+        timer.tick();
+
+        if cycle == 4 {
+            keyboard.enqueue(0x1C);
+        }
+
+        let irq = if timer.interrupt_pending() {
+            Some(0)
+        } else {
+            keyboard.interrupt_pending().then_some(1)
+        };
+
+        match cpu.step_debug_with_interrupts(false, irq).interrupt {
+            Some(Interrupt::Irq(0)) => {
+                timer.acknowledge();
+                timer.write8(TIMER_CONTROL, 0x00);
+                cpu.log_interrupt_event(Interrupt::Irq(0), InterruptEventKind::Acknowledged);
+            },
+            Some(Interrupt::Irq(1)) => {
+                keyboard.acknowledge();
+                cpu.log_interrupt_event(Interrupt::Irq(1), InterruptEventKind::Acknowledged);
+            },
+            _ => (),
+        }
+
+        // Stands in for the handler returning
+        cpu.set_flag(Flag::InterruptDisable, false);
+    }
+
+    let event = |cycle, code, kind| InterruptEvent { cycle, interrupt: Interrupt::Irq(code), kind };
+
+    assert_eq!(
+        cpu.interrupt_log(),
+        [
+            event(2, 0, InterruptEventKind::Raised),
+            event(2, 0, InterruptEventKind::Serviced),
+            event(2, 0, InterruptEventKind::Acknowledged),
+            event(4, 1, InterruptEventKind::Raised),
+            event(4, 1, InterruptEventKind::Serviced),
+            event(4, 1, InterruptEventKind::Acknowledged),
+        ]
+    );
+}
+
+#[test]
+fn interrupt_log_keeps_the_most_recent_events() {
+    let mut cpu = machine(&program("NOP"));
+
+    cpu.log_interrupt_event(Interrupt::Nmi, InterruptEventKind::Raised);
+
+    assert!(cpu.interrupt_log().is_empty());
+
+    cpu.enable_interrupt_log(2);
+
+    for code in 0..3 {
+        cpu.log_interrupt_event(Interrupt::Irq(code), InterruptEventKind::Raised);
+    }
+
+    let interrupts: Vec<_> = cpu.interrupt_log().iter().map(|event| event.interrupt).collect();
+
+    assert_eq!(interrupts, [Interrupt::Irq(1), Interrupt::Irq(2)]);
+
+    cpu.disable_interrupt_log();
+
+    assert!(cpu.interrupt_log().is_empty());
+}
+
+#[test]
+fn jump_to_starts_the_second_routine() {
+    let mut cpu = machine(&program("
+        MOV A, #$1111
+        STP
+    second:
+        MOV B, #$2222
+        STP
+    "));
+
+    cpu.jump_to(0x8006);
+    step(&mut cpu, 2);
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.a(), 0x0000);
+    assert_eq!(cpu.b(), 0x2222);
+}
+
+#[test]
+fn jump_to_wakes_a_waiting_cpu() {
+    let mut cpu = machine(&program("WAI\nMOV A, #$1234\nSTP"));
+
+    cpu.step_debug();
+
+    assert!(cpu.is_waiting());
+
+    cpu.jump_to(0x8002);
+
+    assert!(!cpu.is_waiting());
+
+    step(&mut cpu, 2);
+
+    assert_eq!(cpu.a(), 0x1234);
+}
+
+#[test]
+fn addsat_clamps_at_the_top_and_sets_carry() {
+    let cpu = run_program(&program("MOV A, #$FFFF\nADDSAT A, #$0001\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0xFFFF);
+    assert!(cpu.get_flag(Flag::Carry));
+
+    let cpu = run_program(&program("MOV A, #$1000\nADDSAT A, #$0234\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0x1234);
+    assert!(!cpu.get_flag(Flag::Carry));
+}
+
+#[test]
+fn subsat_clamps_at_zero_and_sets_carry() {
+    let cpu = run_program(&program("SUBSAT A, #$0001\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0x0000);
+    assert!(cpu.get_flag(Flag::Carry));
+    assert!(cpu.get_flag(Flag::Zero));
+
+    let cpu = run_program(&program("MOV A, #$1234\nSUBSAT A, #$0234\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0x1000);
+    assert!(!cpu.get_flag(Flag::Carry));
+}
+
+#[test]
+fn saturating_byte_mode_clamps_to_the_byte_range() {
+    let cpu = run_program(&program("
+        MOV A, #$12F0
+        ADDSAT AL, #$20
+        MOV [$0010], #$0005
+        SUBSAT.B [$0010], #$10
+        STP
+    "), 10);
+
+    assert_eq!(cpu.a(), 0x12FF);
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0x0000);
+    assert!(cpu.get_flag(Flag::Carry));
+}
+
+#[test]
+fn min_and_max_keep_the_smaller_and_larger() {
+    let mut cpu = machine(&program("
+        MOV A, #$0010
+        MAX A, #$00F0
+        MOV B, #$0010
+        MIN B, #$00F0
+        STP
+    "));
+
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0x00F0);
+    assert_eq!(cpu.b(), 0x0010);
+}
+
+#[test]
+fn min_and_max_compare_unsigned() {
+    let mut cpu = machine(&program("
+        MOV A, #$8000
+        MAX A, #$0001
+        MOV B, #$8000
+        MIN B, #$0001
+        MOV C, #$1234
+        MIN CL, #$80
+        STP
+    "));
+
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0x8000);
+    assert_eq!(cpu.b(), 0x0001);
+    assert_eq!(cpu.c(), 0x1234);
+    assert!(!cpu.get_flag(Flag::Sign));
+}
+
+#[test]
+fn min_sets_flags_from_the_result() {
+    let mut cpu = machine(&program("MOV A, #$FFFF\nMIN A, #$0000\nSTP"));
+
+    cpu.run();
+
+    assert_eq!(cpu.a(), 0x0000);
+    assert!(cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn movz_moves_only_while_zero_is_set() {
+    let mut cpu = machine(&program("MOVZ A, #$1234\nMOVZ B, #$5678\nSTP"));
+
+    cpu.set_flag(Flag::Zero, true);
+    cpu.step_debug();
+
+    assert_eq!(cpu.a(), 0x1234);
+
+    cpu.set_flag(Flag::Zero, false);
+    cpu.step_debug();
+
+    assert_eq!(cpu.b(), 0x0000);
+}
+
+#[test]
+fn movif_leaves_the_flags_alone() {
+    let mut cpu = machine(&program("MOVNC A, #$0000\nMOVC B, #$8000\nSTP"));
+
+    cpu.set_status(SIGN_FLAG);
+
+    let before = cpu.registers();
+
+    step(&mut cpu, 2);
+
+    // A MOV of zero would have set the zero flag
+    before.diff(&cpu.registers()).assert_changed(&["PC"]);
+
+    cpu.reset();
+    cpu.set_status(CARRY_FLAG);
+    step(&mut cpu, 2);
+
+    assert_eq!(cpu.b(), 0x8000);
+    assert_eq!(cpu.status(), CARRY_FLAG);
+}
+
+#[test]
+fn movif_conditions_and_their_negations() {
+    let cases = [
+        ("MOVZ", Flag::Zero),
+        ("MOVC", Flag::Carry),
+        ("MOVS", Flag::Sign),
+        ("MOVO", Flag::Overflow),
+    ];
+
+    for (mnemonic, flag) in cases {
+        for set in [false, true] {
+            let mut cpu = machine(&program(&format!("{} A, #$0001\n{} B, #$0001\nSTP", mnemonic, mnemonic.replacen("MOV", "MOVN", 1))));
+
+            cpu.set_flag(flag, set);
+            step(&mut cpu, 2);
+
+            assert_eq!((cpu.a(), cpu.b()), (set as u16, !set as u16), "{} with the flag {}", mnemonic, set);
+        }
+    }
+}
+
+#[test]
+fn movif_byte_mode_moves_one_byte() {
+    let mut cpu = machine(&program("MOV A, #$1234\nMOVZ AH, #$AB\nSTP"));
+
+    cpu.set_flag(Flag::Zero, true);
+    cpu.step_debug();
+    cpu.set_flag(Flag::Zero, true);
+    cpu.step_debug();
+
+    assert_eq!(cpu.a(), 0xAB34);
+    assert_eq!(cpu.disassemble(0x8004).0, "MOVZ AH, #$AB");
+}
+
+#[test]
+fn wait_states_of_operand_accesses_add_cycles() {
+    let mut cpu = machine(&program("MOV A, [$0010]\nMOV A, [$2010]\nMOV [$2010], A\nSTP"));
+
+    let slow = cpu.memory_controller.map_device(2, 1, Box::new(RAM::new(MAP_BLOCK_SIZE))).unwrap();
+
+    cpu.memory_controller.set_wait_states(slow, 3).unwrap();
+
+    let mut cycles = cpu.cycles();
+    let mut cycles_per_step = Vec::new();
+
+    for _ in 0..3 {
+        cpu.step_debug();
+        cycles_per_step.push(cpu.cycles() - cycles);
+        cycles = cpu.cycles();
+    }
+
+    assert_eq!(cycles_per_step, [1, 4, 4]);
+}
+
+#[test]
+fn wait_states_of_instruction_fetches_add_cycles() {
+    let mut cpu = machine(&program("MOV A, #$1234\nNOP\nSTP"));
+
+    cpu.memory_controller.set_wait_states(1, 2).unwrap();
+
+    let cycles = cpu.cycles();
+
+    cpu.step_debug();
+
+    // The instruction word and its immediate are two reads of ROM
+    assert_eq!(cpu.cycles() - cycles, 5);
+
+    cpu.step_debug();
+
+    assert_eq!(cpu.cycles() - cycles, 8);
+    assert_eq!(cpu.memory_controller.set_wait_states(5, 1), Err(MemoryError::IndexOutOfBounds { index: 5 }));
+}
+
+#[test]
+fn restore_registers_rolls_back_every_field() {
+    let mut cpu = machine(&program("MOV [$0010], #$BEEF\nSTP"));
+
+    cpu.set_a(0x1111);
+    cpu.set_index_y(0x2222);
+    cpu.set_status(CARRY_FLAG);
+
+    let saved = cpu.save_registers();
+
+    cpu.step_debug();
+    cpu.set_a(0xAAAA);
+    cpu.set_b(0xBBBB);
+    cpu.set_c(0xCCCC);
+    cpu.set_d(0xDDDD);
+    cpu.set_index_x(0x3333);
+    cpu.set_index_y(0x4444);
+    cpu.set_stack_pointer(0x0800);
+    cpu.set_status(ZERO_FLAG | SIGN_FLAG);
+    cpu.restore_registers(&saved);
+
+    assert_eq!(cpu.registers(), saved);
+    assert!(cpu.registers().diff(&saved).is_empty());
+
+    // Memory keeps what the rolled back step wrote
+    assert_eq!(cpu.memory_controller.read16(0x0010), 0xBEEF);
+}
+
+#[test]
+fn run_program_leaves_the_result_in_the_accumulator() {
+    let cpu = run_program(&program("MOV A, #$0030\nADC A, #$0012\nSTP"), 10);
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.a(), 0x0042);
+}
+
+#[test]
+fn run_program_stops_after_the_step_limit() {
+    let cpu = run_program(&program("loop: INX\nDBNZ A, loop"), 3);
+
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.index_x(), 2);
+}
+
+#[test]
+fn adc_adds_the_carry_in() {
+    let cpu = run_program(&program("MOV A, #$FFFF\nADC A, #$0001\nMOV B, #$0001\nADC B, #$0001\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0x0000);
+    assert_eq!(cpu.b(), 0x0003);
+    assert!(!cpu.get_flag(Flag::Carry));
+}
+
+#[test]
+fn adc_byte_mode_leaves_the_other_half_alone() {
+    let cpu = run_program(&program("MOV AL, #$80\nMOV BL, #$08\nADC AL, BL\nMOV AH, #$12\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0x1288);
+}
+
+#[test]
+fn sbc_borrows_when_the_carry_is_clear() {
+    let cpu = run_program(&program("MOV A, #$0010\nSBC A, #$0001\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0x000E);
+    assert!(cpu.get_flag(Flag::Carry));
+}
+
+#[test]
+fn sbc_below_zero_clears_the_carry() {
+    let cpu = run_program(&program("MOV A, #$0000\nCMP A, #0\nSBC A, #$0001\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0xFFFF);
+    assert!(!cpu.get_flag(Flag::Carry));
+    assert!(cpu.get_flag(Flag::Sign));
+}
+
+#[test]
+fn register_window_shadows_the_program_counter_and_stack_pointer() {
+    let mut cpu = machine(&program("NOP\nMOV A, [$1000]\nMOV B, [$1002]\nSTP"));
+
+    let window = cpu.memory_controller.map_device(1, 1, Box::new(RegisterWindow::new())).unwrap();
+
+    cpu.set_register_window(Some(window)).unwrap();
+
+    assert_eq!(cpu.memory_controller.read16(0x1000 + REGISTER_WINDOW_PROGRAM_COUNTER), cpu.program_counter());
+
+    cpu.step_debug();
+
+    assert_eq!(cpu.memory_controller.read16(0x1000 + REGISTER_WINDOW_PROGRAM_COUNTER), cpu.program_counter());
+
+    step(&mut cpu, 3);
+
+    // The program sees its own instruction's address
+    assert_eq!(cpu.a(), 0x8002);
+    assert_eq!(cpu.b(), cpu.stack_pointer());
+
+    // Writes through the bus are ignored
+    cpu.memory_controller.write16(0x1000, 0x1234);
+
+    assert_eq!(cpu.memory_controller.read16(0x1000), cpu.program_counter());
+}
+
+#[test]
+fn register_window_must_be_a_register_window() {
+    let mut cpu = machine(&program("STP"));
+
+    assert_eq!(
+        cpu.set_register_window(Some(0)),
+        Err(MemoryError::WrongDeviceType { index: 0, expected: core::any::type_name::<RegisterWindow>() })
+    );
+
+    cpu.set_register_window(None).unwrap();
+}
+
+#[test]
+fn lea_stores_the_address_not_its_contents() {
+    let cpu = run_program(&program("
+        MOV [$0120], #$BEEF
+        MOV IDX, #$0020
+        LEA IDX, [$0100+IDX]
+        STP
+    "), 10);
+
+    assert_eq!(cpu.index_x(), 0x0120);
+    assert_eq!(cpu.memory_controller.read16(0x0120), 0xBEEF);
+}
+
+#[test]
+fn lea_follows_pointers_of_indirect_modes() {
+    let cpu = run_program(&program("
+        MOV [$0200], #$0300
+        LEA A, [[$0200]]
+        MOV IDY, #$0400
+        LEA B, [IDY+]
+        STP
+    "), 10);
+
+    assert_eq!(cpu.a(), 0x0300);
+
+    // Post-increment still steps the pointer
+    assert_eq!(cpu.b(), 0x0400);
+    assert_eq!(cpu.index_y(), 0x0402);
+}
+
+#[test]
+fn lea_of_a_register_or_immediate_does_nothing() {
+    let mut cpu = machine(&program("LEA A, B\nLEA A, #$1234\nSTP"));
+
+    cpu.set_a(0x1111);
+    cpu.set_b(0x2222);
+
+    let before = cpu.registers();
+
+    step(&mut cpu, 2);
+
+    before.diff(&cpu.registers()).assert_changed(&["PC"]);
+}
+
+#[test]
+fn clz_counts_leading_zero_bits() {
+    let cpu = run_program(&program("
+        MOV B, #$0100
+        CLZ A, B
+        CLZ C, #$0000
+        STP
+    "), 10);
+
+    assert_eq!(cpu.a(), 7);
+    assert_eq!(cpu.c(), 16);
+    assert!(cpu.get_flag(Flag::Zero));
+
+    let cpu = run_program(&program("CLZ A, #$8000\nSTP"), 10);
+
+    assert_eq!(cpu.a(), 0);
+    assert!(!cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn clz_byte_mode_counts_within_the_byte() {
+    let cpu = run_program(&program("
+        MOV A, #$FF00
+        MOV B, #$0001
+        CLZ AL, BL
+        MOV C, #$1200
+        CLZ CL, #$00
+        STP
+    "), 10);
+
+    assert_eq!(cpu.a(), 0xFF07);
+    assert_eq!(cpu.c(), 0x1208);
+    assert!(cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn popcnt_counts_set_bits() {
+    let cpu = run_program(&program("
+        POPCNT A, #$00FF
+        POPCNT B, #$FFFF
+        POPCNT C, #$0000
+        STP
+    "), 10);
+
+    assert_eq!(cpu.a(), 8);
+    assert_eq!(cpu.b(), 16);
+    assert_eq!(cpu.c(), 0);
+    assert!(cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn popcnt_byte_mode_counts_one_byte() {
+    let cpu = run_program(&program("
+        MOV A, #$FF00
+        MOV B, #$F0A5
+        POPCNT AL, BL
+        STP
+    "), 10);
+
+    assert_eq!(cpu.a(), 0xFF04);
+    assert!(!cpu.get_flag(Flag::Zero));
+}
+
+#[test]
+fn syscall_runs_the_host_handler_with_the_cpu() {
+    let mut cpu = machine(&program("MOV A, #$0041\nSYSCALL\nSYSCALL\nSTP"));
+    let calls = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&calls);
+
+    cpu.set_syscall_handler(move |cpu| {
+        counter.set(counter.get() + 1);
+        cpu.set_a(cpu.a() + 1);
+    });
+    step(&mut cpu, 3);
+
+    assert_eq!(calls.get(), 2);
+    assert_eq!(cpu.a(), 0x0043);
+}
+
+#[test]
+fn syscall_without_a_handler_does_nothing() {
+    let mut cpu = machine(&program("SYSCALL\nSTP"));
+
+    cpu.set_syscall_handler(|cpu| cpu.set_a(0xFFFF));
+    cpu.clear_syscall_handler();
+
+    let before = cpu.registers();
+
+    cpu.step_debug();
+
+    before.diff(&cpu.registers()).assert_changed(&["PC"]);
+}
+
+#[test]
+fn syscall_handler_can_replace_itself() {
+    let mut cpu = machine(&program("SYSCALL\nSYSCALL\nSTP"));
+
+    cpu.set_syscall_handler(|cpu| {
+        cpu.set_a(1);
+        cpu.set_syscall_handler(|cpu| cpu.set_b(2));
+    });
+    step(&mut cpu, 2);
+
+    assert_eq!((cpu.a(), cpu.b()), (1, 2));
+}
+
+#[test]
+fn third_push_on_a_two_entry_stack_overflows() {
+    let mut cpu = machine(&program("STP"));
+
+    cpu.set_stack_pointer(0x0800);
+    cpu.set_stack_bounds(0x07FC, 0x0800);
+    cpu.push16(0x1111);
+    cpu.push16(0x2222);
+
+    assert_eq!(cpu.stack_fault(), None);
+
+    cpu.push16(0x3333);
+
+    assert_eq!(cpu.stack_fault(), Some(StackFault::Overflow { stack_pointer: 0x07FC }));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.stack_pointer(), 0x07FC);
+    assert_eq!(cpu.memory_controller.read16(0x07FA), 0x0000);
+}
+
+#[test]
+fn pop_of_an_empty_stack_underflows() {
+    let mut cpu = machine(&program("STP"));
+
+    cpu.set_stack_pointer(0x0800);
+    cpu.set_stack_bounds(0x07FC, 0x0800);
+    cpu.push16(0x1111);
+
+    assert_eq!(cpu.pop16(), 0x1111);
+    assert_eq!(cpu.stack_fault(), None);
+
+    cpu.pop16();
+
+    assert_eq!(cpu.stack_fault(), Some(StackFault::Underflow { stack_pointer: 0x0800 }));
+    assert_eq!(cpu.stack_pointer(), 0x0800);
+
+    cpu.reset();
+
+    assert_eq!(cpu.stack_fault(), None);
+    assert_eq!(cpu.stack_bounds(), Some((0x07FC, 0x0800)));
+}
+
+#[test]
+fn unbounded_stack_wraps() {
+    let mut cpu = machine(&program("STP"));
+
+    cpu.clear_stack_bounds();
+    cpu.set_stack_pointer(0x0000);
+    cpu.push16(0x1234);
+
+    assert_eq!(cpu.stack_pointer(), 0xFFFE);
+    assert_eq!(cpu.stack_fault(), None);
+}
+
+#[test]
+#[should_panic(expected = "Stack bounds 0x0800..0x0700 are reversed")]
+fn reversed_stack_bounds_panic() {
+    CPU::new().set_stack_bounds(0x0800, 0x0700);
+}
+
+#[test]
+fn registers_by_number_follow_location_order() {
+    let mut cpu = CPU::new();
+
+    cpu.set_c(0x1234);
+
+    assert_eq!(cpu.register(2), Some(0x1234));
+    assert_eq!(cpu.set_register(5, 0xBEEF), Ok(()));
+    assert_eq!(cpu.index_y(), 0xBEEF);
+}
+
+#[test]
+fn out_of_range_register_numbers_are_rejected() {
+    let mut cpu = CPU::new();
+
+    assert_eq!(cpu.register(6), None);
+    assert_eq!(cpu.set_register(6, 0x1234), Err(RegisterError::OutOfRange { index: 6 }));
+    assert_eq!(cpu.registers(), CPU::new().registers());
+}
@@ -0,0 +1,29 @@
+mod banked_rom;
+mod block_storage;
+#[cfg(feature = "std")]
+mod battery_ram;
+mod framebuffer;
+mod gpio;
+mod interrupt_controller;
+mod keyboard;
+mod register_window;
+mod rng;
+mod rtc;
+mod timer;
+mod tone;
+mod uart;
+
+pub use banked_rom::*;
+pub use block_storage::*;
+#[cfg(feature = "std")]
+pub use battery_ram::*;
+pub use framebuffer::*;
+pub use gpio::*;
+pub use interrupt_controller::*;
+pub use keyboard::*;
+pub use register_window::*;
+pub use rng::*;
+pub use rtc::*;
+pub use timer::*;
+pub use tone::*;
+pub use uart::*;
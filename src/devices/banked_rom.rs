@@ -0,0 +1,170 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Writing to this offset selects the visible bank, reads are unaffected
+pub const BANKED_ROM_SELECT: usize = 0x0;
+
+// ROM image larger than its mapped window, viewed one window-sized bank at a time
+pub struct BankedRom {
+    memory: Box<[u8]>,
+    window_size: usize,
+    bank: usize,
+}
+
+impl BankedRom {
+    // The image is padded with zeroes to a whole number of banks
+    pub fn new(window_size: usize, image: &[u8]) -> Self {
+        let banks = image.len().div_ceil(window_size).max(1);
+
+        let mut memory = vec![0x00_u8; banks * window_size];
+        memory[..image.len()].copy_from_slice(image);
+
+        Self {
+            memory: memory.into_boxed_slice(),
+            window_size,
+            bank: 0,
+        }
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    pub fn bank_count(&self) -> usize {
+        self.memory.len() / self.window_size
+    }
+
+    // Out-of-range banks wrap around
+    pub fn select_bank(&mut self, bank: usize) {
+        self.bank = bank % self.bank_count();
+    }
+
+    fn translate(&self, address: usize) -> usize {
+        self.bank * self.window_size + address
+    }
+}
+
+impl MappedDevice for BankedRom {
+    // Spans are limited to the visible bank
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        check_range(address, count, self.window_size)?;
+
+        let translated_address = self.translate(address);
+
+        Ok(&self.memory[translated_address..translated_address + count])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), self.window_size)?;
+
+        let translated_address = self.translate(address);
+
+        self.memory[translated_address..translated_address + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    fn peek8(&self, address: usize) -> Option<u8> {
+        if address >= self.window_size {
+            return None;
+        }
+        Some(self.memory[self.translate(address)])
+    }
+
+    fn size(&self) -> usize {
+        self.window_size
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        if address >= self.window_size {
+            return 0x00;
+        }
+        self.memory[self.translate(address)]
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        if address == BANKED_ROM_SELECT {
+            self.select_bank(value as usize);
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        if address == BANKED_ROM_SELECT {
+            self.select_bank(value as usize);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.bank = 0;
+    }
+
+    // The image is read-only, only the selected bank changes
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some((self.bank as u64).to_le_bytes().to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        self.select_bank(u64::from_le_bytes(fixed_state(state)?) as usize);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writing_the_select_register_switches_banks() {
+        let mut image = vec![0x00_u8; 0x300];
+
+        image[0x000] = 0x11;
+        image[0x100] = 0xAB;
+        image[0x101] = 0xCD;
+
+        let mut rom = BankedRom::new(0x100, &image);
+
+        assert_eq!(rom.bank_count(), 3);
+        assert_eq!(rom.read8(0x00), 0x11);
+
+        rom.write8(BANKED_ROM_SELECT, 1);
+
+        assert_eq!(rom.bank(), 1);
+        assert_eq!(rom.read16(0x00), 0xCDAB);
+
+        // Wraps around to bank 0
+        rom.write8(BANKED_ROM_SELECT, 3);
+
+        assert_eq!(rom.read8(0x00), 0x11);
+    }
+
+    #[test]
+    fn image_is_padded_to_whole_banks() {
+        let rom = BankedRom::new(0x100, &[0x01; 0x101]);
+
+        assert_eq!(rom.bank_count(), 2);
+        assert_eq!(rom.peek_bytes(0xFF, 1).unwrap(), &[0x01]);
+        assert!(rom.peek_bytes(0xFF, 2).is_err());
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut rom = BankedRom::new(0x100, &[0x00; 0x300]);
+
+        rom.select_bank(2);
+
+        let state = rom.save_state().unwrap();
+
+        rom.reset();
+        rom.load_state(&state).unwrap();
+
+        assert_eq!(rom.bank(), 2);
+    }
+}
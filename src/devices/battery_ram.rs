@@ -0,0 +1,160 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::memory::{MappedDevice, MappedRegion, MemoryError};
+
+// Non-volatile RAM backed by a file, contents survive resets and are written back on drop
+pub struct BatteryRam {
+    region: MappedRegion,
+    path: PathBuf,
+}
+
+impl BatteryRam {
+    // A missing file starts zeroed, a file of the wrong size is truncated or zero-padded
+    pub fn new(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut region = MappedRegion::new(capacity, true);
+
+        match fs::read(&path) {
+            Ok(contents) => {
+                let length = contents.len().min(capacity);
+
+                region.poke_bytes(0, &contents[..length]).expect("Contents should fit in the region");
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => return Err(error),
+        }
+
+        Ok(Self {
+            region,
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn persist(&self) -> io::Result<()> {
+        let contents = self.region.peek_bytes(0, self.region.size()).expect("Whole region should be in range");
+
+        fs::write(&self.path, contents)
+    }
+}
+
+impl Drop for BatteryRam {
+    fn drop(&mut self) {
+        // Errors can't be reported from drop, call persist to handle them
+        let _ = self.persist();
+    }
+}
+
+impl MappedDevice for BatteryRam {
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        self.region.peek_bytes(address, count)
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        self.region.poke_bytes(address, bytes)
+    }
+
+    fn size(&self) -> usize {
+        self.region.size()
+    }
+
+    fn peek8(&self, address: usize) -> Option<u8> {
+        self.region.peek8(address)
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        self.region.read8(address)
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.region.read16(address)
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        self.region.write8(address, value);
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.region.write16(address, value);
+    }
+
+    // Contents are kept across a reset and flushed instead
+    fn reset(&mut self) {
+        let _ = self.persist();
+    }
+
+    // Only an explicit clear wipes the contents, they're written back on the next persist
+    fn clear(&mut self) {
+        self.region.clear();
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.region.save_state()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        self.region.load_state(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    // Unique per test so they can run in parallel
+    fn temporary_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("vm-battery-ram-{}-{}.bin", name, process::id()));
+
+        let _ = fs::remove_file(&path);
+
+        path
+    }
+
+    #[test]
+    fn contents_survive_reset_and_reload() {
+        let path = temporary_path("reload");
+        let mut battery_ram = BatteryRam::new(&path, 16).unwrap();
+
+        assert_eq!(battery_ram.read8(3), 0x00);
+
+        battery_ram.write8(3, 0x42);
+        battery_ram.write16(14, 0xBEEF);
+        battery_ram.reset();
+
+        assert_eq!(battery_ram.read8(3), 0x42);
+
+        drop(battery_ram);
+
+        let battery_ram = BatteryRam::new(&path, 16).unwrap();
+
+        assert_eq!(battery_ram.read8(3), 0x42);
+        assert_eq!(battery_ram.read16(14), 0xBEEF);
+
+        drop(battery_ram);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn clear_wipes_the_persisted_contents() {
+        let path = temporary_path("clear");
+        let mut battery_ram = BatteryRam::new(&path, 16).unwrap();
+
+        battery_ram.write8(0, 0x42);
+        battery_ram.persist().unwrap();
+        battery_ram.clear();
+        battery_ram.persist().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), [0x00; 16]);
+
+        drop(battery_ram);
+        fs::remove_file(&path).unwrap();
+    }
+}
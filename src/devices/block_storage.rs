@@ -0,0 +1,400 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::memory::{MappedDevice, MemoryController, MemoryError, check_range};
+
+pub const BLOCK_STORAGE_SECTOR_SIZE: usize = 0x100;
+
+// Register offsets, the staged sector sits below them and 16-bit registers are little-endian
+pub const BLOCK_STORAGE_DATA: usize = 0x000; // The staging buffer, one sector long
+pub const BLOCK_STORAGE_SECTOR: usize = 0x100; // Sector the next command reads or writes
+pub const BLOCK_STORAGE_BUFFER: usize = 0x102; // Bus address for LOAD and STORE
+pub const BLOCK_STORAGE_COMMAND: usize = 0x104; // Writing starts a command
+pub const BLOCK_STORAGE_STATUS: usize = 0x105; // Bit 0 is busy, bit 1 is set when the last command's sector was out of range
+pub const BLOCK_STORAGE_SIZE: usize = 0x106;
+
+// Commands
+pub const BLOCK_STORAGE_COMMAND_READ: u8 = 0x01; // Sector into the staging buffer
+pub const BLOCK_STORAGE_COMMAND_WRITE: u8 = 0x02; // Staging buffer into the sector
+pub const BLOCK_STORAGE_COMMAND_LOAD: u8 = 0x03; // READ, then the staging buffer into memory at the buffer address
+pub const BLOCK_STORAGE_COMMAND_STORE: u8 = 0x04; // Memory at the buffer address into the staging buffer, then WRITE
+
+pub const BLOCK_STORAGE_STATUS_BUSY: u8 = 0x01;
+pub const BLOCK_STORAGE_STATUS_ERROR: u8 = 0x02;
+
+// Sector, buffer, pending command and error flag at the start of the saved state
+const BLOCK_STORAGE_STATE_REGISTERS: usize = 6;
+
+// A disk image read and written a sector at a time. Devices can't reach the bus, so LOAD and STORE stay busy until the host
+// calls service to do the memory side of the copy, the way a DMA controller would
+pub struct BlockStorage {
+    image: Box<[u8]>,
+    staging: [u8; BLOCK_STORAGE_SECTOR_SIZE],
+    sector: u16,
+    buffer: u16,
+    pending: Option<u8>, // A LOAD or STORE waiting on the host
+    error: bool,
+}
+
+impl BlockStorage {
+    // The image is padded with zeroes to a whole number of sectors
+    pub fn new(image: &[u8]) -> Self {
+        let sectors = image.len().div_ceil(BLOCK_STORAGE_SECTOR_SIZE);
+
+        let mut memory = vec![0x00_u8; sectors * BLOCK_STORAGE_SECTOR_SIZE];
+        memory[..image.len()].copy_from_slice(image);
+
+        Self {
+            image: memory.into_boxed_slice(),
+            staging: [0x00; BLOCK_STORAGE_SECTOR_SIZE],
+            sector: 0x0000,
+            buffer: 0x0000,
+            pending: None,
+            error: false,
+        }
+    }
+
+    pub fn sector_count(&self) -> usize {
+        self.image.len() / BLOCK_STORAGE_SECTOR_SIZE
+    }
+
+    // For the host to save written sectors back
+    pub fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    pub fn staging(&self) -> &[u8] {
+        &self.staging
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    // Finishes a pending LOAD or STORE through the controller, the device must be a BlockStorage
+    pub fn service(memory: &mut MemoryController, mapping_index: usize) -> Result<(), MemoryError> {
+        let storage = memory.get_device_as::<Self>(mapping_index)?;
+
+        let Some(command) = storage.pending else {
+            return Ok(());
+        };
+        let buffer = storage.buffer as usize;
+
+        if command == BLOCK_STORAGE_COMMAND_LOAD {
+            let staging = storage.staging;
+
+            for (i, byte) in staging.iter().enumerate() {
+                memory.write8(buffer + i, *byte);
+            }
+
+            memory.get_device_as_mut::<Self>(mapping_index)?.pending = None;
+        } else {
+            let mut staging = [0x00; BLOCK_STORAGE_SECTOR_SIZE];
+
+            for (i, byte) in staging.iter_mut().enumerate() {
+                *byte = memory.read8(buffer + i);
+            }
+
+            let storage = memory.get_device_as_mut::<Self>(mapping_index)?;
+
+            storage.staging = staging;
+            storage.pending = None;
+            storage.write_sector();
+        }
+
+        Ok(())
+    }
+
+    fn sector_range(&self) -> Option<core::ops::Range<usize>> {
+        let start = self.sector as usize * BLOCK_STORAGE_SECTOR_SIZE;
+
+        (start < self.image.len()).then(|| start..start + BLOCK_STORAGE_SECTOR_SIZE)
+    }
+
+    fn read_sector(&mut self) {
+        let range = self.sector_range();
+
+        self.error = range.is_none();
+
+        if let Some(range) = range {
+            self.staging.copy_from_slice(&self.image[range]);
+        }
+    }
+
+    fn write_sector(&mut self) {
+        let range = self.sector_range();
+
+        self.error = range.is_none();
+
+        if let Some(range) = range {
+            self.image[range].copy_from_slice(&self.staging);
+        }
+    }
+
+    // Commands are ignored while one is pending, unknown commands do nothing
+    fn command(&mut self, command: u8) {
+        if self.pending.is_some() {
+            return;
+        }
+
+        match command {
+            BLOCK_STORAGE_COMMAND_READ => self.read_sector(),
+            BLOCK_STORAGE_COMMAND_WRITE => self.write_sector(),
+            BLOCK_STORAGE_COMMAND_LOAD => {
+                self.read_sector();
+
+                if !self.error {
+                    self.pending = Some(command);
+                }
+            },
+            BLOCK_STORAGE_COMMAND_STORE => {
+                // Checked up front so an out-of-range STORE fails without waiting on the host
+                self.error = self.sector_range().is_none();
+
+                if !self.error {
+                    self.pending = Some(command);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0x00;
+
+        if self.pending.is_some() {
+            status |= BLOCK_STORAGE_STATUS_BUSY;
+        }
+        if self.error {
+            status |= BLOCK_STORAGE_STATUS_ERROR;
+        }
+
+        status
+    }
+}
+
+impl MappedDevice for BlockStorage {
+    // Only the staging buffer has backing memory to peek
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        check_range(address, count, BLOCK_STORAGE_SECTOR_SIZE)?;
+
+        Ok(&self.staging[address..address + count])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), BLOCK_STORAGE_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        BLOCK_STORAGE_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            BLOCK_STORAGE_DATA..BLOCK_STORAGE_SECTOR => self.staging[address],
+            BLOCK_STORAGE_SECTOR => self.sector as u8,
+            0x101 => (self.sector >> 8) as u8,
+            BLOCK_STORAGE_BUFFER => self.buffer as u8,
+            0x103 => (self.buffer >> 8) as u8,
+            BLOCK_STORAGE_STATUS => self.status(),
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        match address {
+            BLOCK_STORAGE_DATA..BLOCK_STORAGE_SECTOR => {
+                self.staging[address] = value;
+            },
+            BLOCK_STORAGE_SECTOR => {
+                self.sector = self.sector & 0xFF00 | value as u16;
+            },
+            0x101 => {
+                self.sector = self.sector & 0x00FF | (value as u16) << 8;
+            },
+            BLOCK_STORAGE_BUFFER => {
+                self.buffer = self.buffer & 0xFF00 | value as u16;
+            },
+            0x103 => {
+                self.buffer = self.buffer & 0x00FF | (value as u16) << 8;
+            },
+            BLOCK_STORAGE_COMMAND => self.command(value),
+            _ => (),
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.write8(address, value as u8);
+        self.write8(address + 1, (value >> 8) as u8);
+    }
+
+    // The image is the disk and survives, only the controller's state is cleared
+    fn reset(&mut self) {
+        self.staging = [0x00; BLOCK_STORAGE_SECTOR_SIZE];
+        self.sector = 0x0000;
+        self.buffer = 0x0000;
+        self.pending = None;
+        self.error = false;
+    }
+
+    // The registers, the staging buffer, then the image, as writes change the disk too
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let mut state = Vec::with_capacity(BLOCK_STORAGE_STATE_REGISTERS + BLOCK_STORAGE_SECTOR_SIZE + self.image.len());
+
+        state.extend(self.sector.to_le_bytes());
+        state.extend(self.buffer.to_le_bytes());
+        state.push(self.pending.unwrap_or(0x00));
+        state.push(self.error as u8);
+        state.extend(self.staging);
+        state.extend(self.image.iter());
+
+        Some(state)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        let expected = BLOCK_STORAGE_STATE_REGISTERS + BLOCK_STORAGE_SECTOR_SIZE + self.image.len();
+
+        if state.len() != expected {
+            return Err(MemoryError::SizeMismatch { expected, actual: state.len() });
+        }
+
+        let (registers, rest) = state.split_at(BLOCK_STORAGE_STATE_REGISTERS);
+        let (staging, image) = rest.split_at(BLOCK_STORAGE_SECTOR_SIZE);
+
+        self.sector = u16::from_le_bytes([registers[0], registers[1]]);
+        self.buffer = u16::from_le_bytes([registers[2], registers[3]]);
+        self.pending = (registers[4] != 0x00).then_some(registers[4]);
+        self.error = registers[5] != 0x00;
+        self.staging.copy_from_slice(staging);
+        self.image.copy_from_slice(image);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::RAM;
+
+    // Four sectors, each filled with its own number
+    fn storage() -> BlockStorage {
+        let image: Vec<u8> = (0..4).flat_map(|sector| [sector; BLOCK_STORAGE_SECTOR_SIZE]).collect();
+
+        BlockStorage::new(&image)
+    }
+
+    #[test]
+    fn read_stages_the_sector() {
+        let mut storage = storage();
+
+        storage.write16(BLOCK_STORAGE_SECTOR, 2);
+        storage.write8(BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_READ);
+
+        assert_eq!(storage.staging(), [0x02; BLOCK_STORAGE_SECTOR_SIZE]);
+        assert_eq!(storage.read8(BLOCK_STORAGE_DATA + 0xFF), 0x02);
+        assert_eq!(storage.read8(BLOCK_STORAGE_STATUS), 0x00);
+    }
+
+    #[test]
+    fn write_flushes_the_staging_buffer() {
+        let mut storage = storage();
+
+        storage.poke_bytes(BLOCK_STORAGE_DATA, &[0xAB; BLOCK_STORAGE_SECTOR_SIZE]).unwrap();
+        storage.write16(BLOCK_STORAGE_SECTOR, 1);
+        storage.write8(BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_WRITE);
+
+        assert_eq!(storage.image()[BLOCK_STORAGE_SECTOR_SIZE..2 * BLOCK_STORAGE_SECTOR_SIZE], [0xAB; BLOCK_STORAGE_SECTOR_SIZE]);
+        assert_eq!(storage.image()[0], 0x00);
+        assert_eq!(storage.image()[2 * BLOCK_STORAGE_SECTOR_SIZE], 0x02);
+    }
+
+    #[test]
+    fn sectors_past_the_image_set_the_error_bit() {
+        let mut storage = storage();
+
+        assert_eq!(storage.sector_count(), 4);
+
+        storage.write16(BLOCK_STORAGE_SECTOR, 4);
+        storage.write8(BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_READ);
+
+        assert_eq!(storage.read8(BLOCK_STORAGE_STATUS), BLOCK_STORAGE_STATUS_ERROR);
+
+        storage.write8(BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_STORE);
+
+        assert!(!storage.is_busy());
+        assert_eq!(storage.read8(BLOCK_STORAGE_STATUS), BLOCK_STORAGE_STATUS_ERROR);
+    }
+
+    #[test]
+    fn load_and_store_wait_for_the_host() {
+        let mut memory = MemoryController::new();
+
+        memory.map_device(0, 1, Box::new(RAM::new(0x1000))).unwrap();
+
+        let index = memory.map_device(1, 1, Box::new(storage())).unwrap();
+
+        memory.write16(0x1000 + BLOCK_STORAGE_SECTOR, 3);
+        memory.write16(0x1000 + BLOCK_STORAGE_BUFFER, 0x0200);
+        memory.write8(0x1000 + BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_LOAD);
+
+        assert_eq!(memory.read8(0x1000 + BLOCK_STORAGE_STATUS), BLOCK_STORAGE_STATUS_BUSY);
+        assert_eq!(memory.read8(0x0200), 0x00);
+
+        // Commands are ignored while one is pending
+        memory.write8(0x1000 + BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_STORE);
+        BlockStorage::service(&mut memory, index).unwrap();
+
+        assert_eq!(memory.read8(0x1000 + BLOCK_STORAGE_STATUS), 0x00);
+        assert_eq!(memory.peek_range(0x0200, BLOCK_STORAGE_SECTOR_SIZE), [0x03; BLOCK_STORAGE_SECTOR_SIZE]);
+
+        memory.fill_range(0x0300, BLOCK_STORAGE_SECTOR_SIZE, 0x5A);
+        memory.write16(0x1000 + BLOCK_STORAGE_SECTOR, 0);
+        memory.write16(0x1000 + BLOCK_STORAGE_BUFFER, 0x0300);
+        memory.write8(0x1000 + BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_STORE);
+        BlockStorage::service(&mut memory, index).unwrap();
+
+        let storage = memory.get_device_as::<BlockStorage>(index).unwrap();
+
+        assert!(!storage.is_busy());
+        assert_eq!(storage.image()[..BLOCK_STORAGE_SECTOR_SIZE], [0x5A; BLOCK_STORAGE_SECTOR_SIZE]);
+        assert_eq!(
+            BlockStorage::service(&mut memory, 0),
+            Err(MemoryError::WrongDeviceType { index: 0, expected: core::any::type_name::<BlockStorage>() })
+        );
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut storage = BlockStorage::new(&[0x00; BLOCK_STORAGE_SECTOR_SIZE * 2]);
+
+        storage.write8(BLOCK_STORAGE_DATA, 0x42);
+        storage.write16(BLOCK_STORAGE_SECTOR, 1);
+        storage.write8(BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_WRITE);
+        storage.write16(BLOCK_STORAGE_BUFFER, 0x1234);
+
+        let state = storage.save_state().unwrap();
+
+        storage.reset();
+        storage.write16(BLOCK_STORAGE_SECTOR, 0);
+        storage.write8(BLOCK_STORAGE_COMMAND, BLOCK_STORAGE_COMMAND_WRITE);
+        storage.load_state(&state).unwrap();
+
+        assert_eq!(storage.read16(BLOCK_STORAGE_SECTOR), 1);
+        assert_eq!(storage.read16(BLOCK_STORAGE_BUFFER), 0x1234);
+        assert_eq!(storage.staging()[0], 0x42);
+        assert_eq!(storage.image()[0], 0x00);
+        assert_eq!(storage.image()[BLOCK_STORAGE_SECTOR_SIZE], 0x42);
+    }
+}
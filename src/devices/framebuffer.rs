@@ -0,0 +1,133 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::memory::{MappedDevice, MemoryError, check_range};
+
+// One byte per pixel, stored row by row
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Box<[u8]>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0x00_u8; width * height].into_boxed_slice(),
+        }
+    }
+
+    // Returns (width, height)
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+impl MappedDevice for Framebuffer {
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        check_range(address, count, self.pixels.len())?;
+
+        Ok(&self.pixels[address..address + count])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), self.pixels.len())?;
+
+        self.pixels[address..address + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn peek8(&self, address: usize) -> Option<u8> {
+        self.pixels.get(address).copied()
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        if address >= self.pixels.len() {
+            return 0x00;
+        }
+        self.pixels[address]
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        if address >= self.pixels.len() {
+            return;
+        }
+        self.pixels[address] = value;
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.write8(address, value as u8);
+        self.write8(address + 1, (value >> 8) as u8);
+    }
+
+    fn reset(&mut self) {
+        self.clear();
+    }
+
+    fn clear(&mut self) {
+        self.pixels.fill(0x00);
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.pixels.to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        if state.len() != self.pixels.len() {
+            return Err(MemoryError::SizeMismatch { expected: self.pixels.len(), actual: state.len() });
+        }
+
+        self.pixels.copy_from_slice(state);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_land_on_pixels_row_by_row() {
+        let mut framebuffer = Framebuffer::new(4, 2);
+
+        framebuffer.write16(0x0, 0x0201);
+        framebuffer.write8(0x7, 0x09);
+        framebuffer.write8(0x8, 0x09); // Past the last pixel
+
+        assert_eq!(framebuffer.dimensions(), (4, 2));
+        assert_eq!(framebuffer.pixels(), &[0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09]);
+        assert_eq!(framebuffer.read8(0x8), 0x00);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut framebuffer = Framebuffer::new(2, 2);
+
+        framebuffer.poke_bytes(0, &[0x01, 0x02, 0x03, 0x04]).unwrap();
+
+        let state = framebuffer.save_state().unwrap();
+
+        framebuffer.reset();
+        framebuffer.load_state(&state).unwrap();
+
+        assert_eq!(framebuffer.pixels(), &[0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(framebuffer.load_state(&[0x00]), Err(MemoryError::SizeMismatch { expected: 4, actual: 1 }));
+    }
+}
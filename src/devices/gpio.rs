@@ -0,0 +1,186 @@
+use alloc::vec::Vec;
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Register offsets
+pub const GPIO_DATA: usize = 0x0; // Output bits read back what was written, input bits read the level the host set
+pub const GPIO_DIRECTION: usize = 0x1; // One bit per pin, set for output
+pub const GPIO_SIZE: usize = 0x2;
+
+pub const GPIO_PINS: u8 = 8;
+
+// Eight general-purpose pins, each an input driven by the host or an output driven by the program
+pub struct Gpio {
+    output: u8, // Latched by writes to the data register, only driven on output pins
+    input: u8, // Levels set by the host, only seen on input pins
+    direction: u8,
+}
+
+impl Gpio {
+    pub fn new() -> Self {
+        Self {
+            output: 0x00,
+            input: 0x00,
+            direction: 0x00,
+        }
+    }
+
+    pub fn set_input_pin(&mut self, pin: u8, level: bool) {
+        assert!(pin < GPIO_PINS, "GPIO pin {} is out of range", pin);
+
+        if level {
+            self.input |= 1 << pin;
+        } else {
+            self.input &= !(1 << pin);
+        }
+    }
+
+    // Pins configured as inputs always read low
+    pub fn read_output_pin(&self, pin: u8) -> bool {
+        assert!(pin < GPIO_PINS, "GPIO pin {} is out of range", pin);
+
+        self.output & self.direction & (1 << pin) != 0
+    }
+
+    pub fn direction(&self) -> u8 {
+        self.direction
+    }
+
+    fn data(&self) -> u8 {
+        (self.output & self.direction) | (self.input & !self.direction)
+    }
+}
+
+impl Default for Gpio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MappedDevice for Gpio {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), GPIO_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        GPIO_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            GPIO_DATA => self.data(),
+            GPIO_DIRECTION => self.direction,
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        match address {
+            GPIO_DATA => self.output = value,
+            GPIO_DIRECTION => self.direction = value,
+            _ => (),
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.write8(address, value as u8);
+        self.write8(address + 1, (value >> 8) as u8);
+    }
+
+    // Every pin goes back to being an input, host-set input levels are external and kept
+    fn reset(&mut self) {
+        self.output = 0x00;
+        self.direction = 0x00;
+    }
+
+    // Input levels are included so a restored machine sees the pins as they were
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some([self.output, self.input, self.direction].to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        [self.output, self.input, self.direction] = fixed_state(state)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_pins_report_the_written_pattern_and_input_pins_the_host_level() {
+        let mut gpio = Gpio::new();
+
+        gpio.write8(GPIO_DIRECTION, 0x0F);
+        gpio.write8(GPIO_DATA, 0b1010_0101);
+        gpio.set_input_pin(6, true);
+
+        assert!(gpio.read_output_pin(0));
+        assert!(!gpio.read_output_pin(1));
+        assert!(gpio.read_output_pin(2));
+        assert!(!gpio.read_output_pin(3));
+
+        // Bits written to input pins aren't driven, they read the host's levels instead
+        assert!(!gpio.read_output_pin(5));
+        assert_eq!(gpio.read8(GPIO_DATA), 0b0100_0101);
+
+        gpio.set_input_pin(6, false);
+
+        assert_eq!(gpio.read8(GPIO_DATA), 0b0000_0101);
+    }
+
+    #[test]
+    fn reset_makes_every_pin_an_input_and_keeps_host_levels() {
+        let mut gpio = Gpio::new();
+
+        gpio.write8(GPIO_DIRECTION, 0xFF);
+        gpio.write8(GPIO_DATA, 0xFF);
+        gpio.set_input_pin(1, true);
+        gpio.reset();
+
+        assert_eq!(gpio.direction(), 0x00);
+        assert!(!gpio.read_output_pin(0));
+        assert_eq!(gpio.read8(GPIO_DATA), 0b0000_0010);
+    }
+
+    #[test]
+    #[should_panic(expected = "GPIO pin 8 is out of range")]
+    fn pins_past_the_last_panic() {
+        Gpio::new().set_input_pin(GPIO_PINS, true);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut gpio = Gpio::new();
+
+        gpio.write8(GPIO_DIRECTION, 0x0F);
+        gpio.write8(GPIO_DATA, 0x05);
+        gpio.set_input_pin(7, true);
+
+        let state = gpio.save_state().unwrap();
+
+        gpio.reset();
+        gpio.set_input_pin(7, false);
+        gpio.load_state(&state).unwrap();
+
+        assert_eq!(gpio.read8(GPIO_DATA), 0x85);
+        assert_eq!(gpio.direction(), 0x0F);
+    }
+}
@@ -0,0 +1,196 @@
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Register offsets
+pub const INTERRUPT_CONTROLLER_PENDING: usize = 0x0; // One bit per source, set while the source is raised
+pub const INTERRUPT_CONTROLLER_ENABLE: usize = 0x1; // One bit per source, masked sources never produce an IRQ
+pub const INTERRUPT_CONTROLLER_ACKNOWLEDGE: usize = 0x2; // Reading returns the serviced source and clears it, 0xFF if none
+pub const INTERRUPT_CONTROLLER_SIZE: usize = 0x3;
+
+pub const INTERRUPT_CONTROLLER_SOURCES: u8 = 8;
+
+const NO_SOURCE: u8 = 0xFF;
+
+// Arbitrates between IRQ sources, source 0 has the highest priority and each source's number is its IRQ code
+pub struct InterruptController {
+    pending: Cell<u8>,
+    enabled: u8,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self {
+            pending: Cell::new(0x00),
+            enabled: 0x00,
+        }
+    }
+
+    pub fn raise(&mut self, source: u8) {
+        assert!(source < INTERRUPT_CONTROLLER_SOURCES, "Interrupt source {} is out of range", source);
+
+        self.pending.set(self.pending.get() | 1 << source);
+    }
+
+    pub fn lower(&mut self, source: u8) {
+        assert!(source < INTERRUPT_CONTROLLER_SOURCES, "Interrupt source {} is out of range", source);
+
+        self.pending.set(self.pending.get() & !(1 << source));
+    }
+
+    pub fn pending(&self) -> u8 {
+        self.pending.get()
+    }
+
+    pub fn enabled(&self) -> u8 {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: u8) {
+        self.enabled = enabled;
+    }
+
+    // The code to pass to the CPU's process, the highest-priority pending source that is enabled
+    pub fn irq(&self) -> Option<u8> {
+        let active = self.pending.get() & self.enabled;
+
+        if active == 0 {
+            return None;
+        }
+
+        Some(active.trailing_zeros() as u8)
+    }
+}
+
+impl Default for InterruptController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MappedDevice for InterruptController {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), INTERRUPT_CONTROLLER_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        INTERRUPT_CONTROLLER_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            INTERRUPT_CONTROLLER_PENDING => self.pending.get(),
+            INTERRUPT_CONTROLLER_ENABLE => self.enabled,
+            INTERRUPT_CONTROLLER_ACKNOWLEDGE => match self.irq() {
+                Some(source) => {
+                    self.pending.set(self.pending.get() & !(1 << source));
+
+                    source
+                },
+                None => NO_SOURCE,
+            },
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        if address == INTERRUPT_CONTROLLER_ENABLE {
+            self.enabled = value;
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.write8(address, value as u8);
+        self.write8(address + 1, (value >> 8) as u8);
+    }
+
+    fn reset(&mut self) {
+        self.pending.set(0x00);
+        self.enabled = 0x00;
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some([self.pending.get(), self.enabled].to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        let [pending, enabled] = fixed_state(state)?;
+
+        self.pending.set(pending);
+        self.enabled = enabled;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowest_source_has_priority() {
+        let mut controller = InterruptController::new();
+
+        controller.write8(INTERRUPT_CONTROLLER_ENABLE, 0xFF);
+        controller.raise(5);
+        controller.raise(2);
+
+        assert_eq!(controller.irq(), Some(2));
+        assert_eq!(controller.read8(INTERRUPT_CONTROLLER_PENDING), 0b0010_0100);
+        assert_eq!(controller.read8(INTERRUPT_CONTROLLER_ACKNOWLEDGE), 2);
+        assert_eq!(controller.read8(INTERRUPT_CONTROLLER_ACKNOWLEDGE), 5);
+        assert_eq!(controller.read8(INTERRUPT_CONTROLLER_ACKNOWLEDGE), NO_SOURCE);
+        assert_eq!(controller.irq(), None);
+    }
+
+    #[test]
+    fn masked_sources_stay_pending_without_an_irq() {
+        let mut controller = InterruptController::new();
+
+        controller.set_enabled(0b0000_0001);
+        controller.raise(3);
+
+        assert_eq!(controller.irq(), None);
+        assert_eq!(controller.pending(), 0b0000_1000);
+
+        controller.set_enabled(0b0000_1000);
+
+        assert_eq!(controller.irq(), Some(3));
+
+        controller.lower(3);
+
+        assert_eq!(controller.irq(), None);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut controller = InterruptController::new();
+
+        controller.set_enabled(0b0000_0110);
+        controller.raise(2);
+
+        let state = controller.save_state().unwrap();
+
+        controller.reset();
+        controller.load_state(&state).unwrap();
+
+        assert_eq!(controller.enabled(), 0b0000_0110);
+        assert_eq!(controller.irq(), Some(2));
+    }
+}
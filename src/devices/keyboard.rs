@@ -0,0 +1,171 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::memory::{MappedDevice, MemoryError, check_range};
+
+// Register offsets
+pub const KEYBOARD_DATA: usize = 0x0; // Reading pops the oldest scancode, 0x00 if none are queued
+pub const KEYBOARD_STATUS: usize = 0x1; // Bit 0 is key available
+pub const KEYBOARD_SIZE: usize = 0x2;
+
+pub const KEYBOARD_QUEUE_CAPACITY: usize = 16;
+
+pub struct Keyboard {
+    queue: RefCell<VecDeque<u8>>,
+    pending: bool,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self {
+            queue: RefCell::new(VecDeque::with_capacity(KEYBOARD_QUEUE_CAPACITY)),
+            pending: false,
+        }
+    }
+
+    // Returns false if the queue is full and the scancode was dropped
+    pub fn enqueue(&mut self, scancode: u8) -> bool {
+        let queue = self.queue.get_mut();
+
+        if queue.len() == KEYBOARD_QUEUE_CAPACITY {
+            return false;
+        }
+
+        queue.push_back(scancode);
+        self.pending = true;
+
+        true
+    }
+
+    // Set whenever a scancode is enqueued
+    pub fn interrupt_pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MappedDevice for Keyboard {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), KEYBOARD_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        KEYBOARD_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            KEYBOARD_DATA => self.queue.borrow_mut().pop_front().unwrap_or(0x00),
+            KEYBOARD_STATUS => !self.queue.borrow().is_empty() as u8,
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, _: usize, _: u8) {}
+
+    fn write16(&mut self, _: usize, _: u16) {}
+
+    fn reset(&mut self) {
+        self.queue.get_mut().clear();
+        self.pending = false;
+    }
+
+    // The pending flag, then the queued scancodes oldest first
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let mut state = Vec::with_capacity(1 + KEYBOARD_QUEUE_CAPACITY);
+
+        state.push(self.pending as u8);
+        state.extend(self.queue.borrow().iter());
+
+        Some(state)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        let Some((pending, scancodes)) = state.split_first().filter(|(_, scancodes)| scancodes.len() <= KEYBOARD_QUEUE_CAPACITY) else {
+            return Err(MemoryError::SizeMismatch { expected: 1 + KEYBOARD_QUEUE_CAPACITY, actual: state.len() });
+        };
+
+        self.pending = *pending != 0;
+        *self.queue.get_mut() = scancodes.iter().copied().collect();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scancodes_are_read_in_order() {
+        let mut keyboard = Keyboard::new();
+
+        assert_eq!(keyboard.read8(KEYBOARD_STATUS), 0x00);
+
+        keyboard.enqueue(0x1C);
+        keyboard.enqueue(0x32);
+
+        assert!(keyboard.interrupt_pending());
+        assert_eq!(keyboard.read8(KEYBOARD_STATUS), 0x01);
+        assert_eq!(keyboard.read8(KEYBOARD_DATA), 0x1C);
+        assert_eq!(keyboard.read8(KEYBOARD_DATA), 0x32);
+        assert_eq!(keyboard.read8(KEYBOARD_DATA), 0x00);
+        assert_eq!(keyboard.read8(KEYBOARD_STATUS), 0x00);
+
+        keyboard.acknowledge();
+
+        assert!(!keyboard.interrupt_pending());
+    }
+
+    #[test]
+    fn full_queue_drops_scancodes() {
+        let mut keyboard = Keyboard::new();
+
+        for scancode in 0..KEYBOARD_QUEUE_CAPACITY as u8 {
+            assert!(keyboard.enqueue(scancode));
+        }
+
+        assert!(!keyboard.enqueue(0xFF));
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut keyboard = Keyboard::new();
+
+        keyboard.enqueue(0x1C);
+
+        let state = keyboard.save_state().unwrap();
+
+        keyboard.reset();
+        keyboard.load_state(&state).unwrap();
+
+        assert!(keyboard.interrupt_pending());
+        assert_eq!(keyboard.read8(KEYBOARD_DATA), 0x1C);
+        assert!(keyboard.load_state(&[]).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use alloc::vec::Vec;
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Register offsets, 16-bit registers are little-endian
+pub const REGISTER_WINDOW_PROGRAM_COUNTER: usize = 0x0;
+pub const REGISTER_WINDOW_STACK_POINTER: usize = 0x2;
+pub const REGISTER_WINDOW_SIZE: usize = 0x4;
+
+// Read-only shadows of the CPU's program counter and stack pointer. The CPU owns the real registers, so it copies them in
+// before each instruction and after each step once the window is attached with CPU::set_register_window
+pub struct RegisterWindow {
+    program_counter: u16,
+    stack_pointer: u16,
+}
+
+impl RegisterWindow {
+    pub fn new() -> Self {
+        Self {
+            program_counter: 0x0000,
+            stack_pointer: 0x0000,
+        }
+    }
+
+    pub fn update(&mut self, program_counter: u16, stack_pointer: u16) {
+        self.program_counter = program_counter;
+        self.stack_pointer = stack_pointer;
+    }
+}
+
+impl Default for RegisterWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MappedDevice for RegisterWindow {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    // The shadows only change through update
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), REGISTER_WINDOW_SIZE)
+    }
+
+    fn size(&self) -> usize {
+        REGISTER_WINDOW_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            REGISTER_WINDOW_PROGRAM_COUNTER => self.program_counter as u8,
+            0x1 => (self.program_counter >> 8) as u8,
+            REGISTER_WINDOW_STACK_POINTER => self.stack_pointer as u8,
+            0x3 => (self.stack_pointer >> 8) as u8,
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, _: usize, _: u8) {}
+
+    fn write16(&mut self, _: usize, _: u16) {}
+
+    fn reset(&mut self) {
+        self.program_counter = 0x0000;
+        self.stack_pointer = 0x0000;
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let [program_counter_low, program_counter_high] = self.program_counter.to_le_bytes();
+        let [stack_pointer_low, stack_pointer_high] = self.stack_pointer.to_le_bytes();
+
+        Some([program_counter_low, program_counter_high, stack_pointer_low, stack_pointer_high].to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        let [program_counter_low, program_counter_high, stack_pointer_low, stack_pointer_high] = fixed_state(state)?;
+
+        self.program_counter = u16::from_le_bytes([program_counter_low, program_counter_high]);
+        self.stack_pointer = u16::from_le_bytes([stack_pointer_low, stack_pointer_high]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips() {
+        let mut window = RegisterWindow::new();
+
+        window.update(0x8000, 0x1000);
+
+        let state = window.save_state().unwrap();
+
+        window.reset();
+        window.load_state(&state).unwrap();
+
+        assert_eq!(window.read16(REGISTER_WINDOW_PROGRAM_COUNTER), 0x8000);
+        assert_eq!(window.read16(REGISTER_WINDOW_STACK_POINTER), 0x1000);
+    }
+}
@@ -0,0 +1,175 @@
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Register offsets
+pub const RNG_DATA: usize = 0x0; // 16-bit, reading yields the next value and writing reseeds
+pub const RNG_SIZE: usize = 0x2;
+
+// Xorshift gets stuck at zero, so a zero seed is replaced with this
+const ZERO_SEED_REPLACEMENT: u16 = 0xACE1;
+
+// Deterministic 16-bit xorshift generator
+pub struct Rng {
+    seed: u16,
+    state: Cell<u16>,
+}
+
+impl Rng {
+    pub fn new(seed: u16) -> Self {
+        let seed = Self::fix_seed(seed);
+
+        Self {
+            seed,
+            state: Cell::new(seed),
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u16) {
+        self.state.set(Self::fix_seed(seed));
+    }
+
+    fn fix_seed(seed: u16) -> u16 {
+        if seed == 0 {
+            ZERO_SEED_REPLACEMENT
+        } else {
+            seed
+        }
+    }
+
+    fn next(&self) -> u16 {
+        let mut value = self.state.get();
+
+        value ^= value << 7;
+        value ^= value >> 9;
+        value ^= value << 8;
+
+        self.state.set(value);
+
+        value
+    }
+}
+
+impl MappedDevice for Rng {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), RNG_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        RNG_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            RNG_DATA => self.next() as u8,
+            0x1 => (self.next() >> 8) as u8,
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        match address {
+            RNG_DATA => self.next(),
+            _ => self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8,
+        }
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        match address {
+            RNG_DATA => self.reseed(value as u16),
+            0x1 => self.reseed((value as u16) << 8),
+            _ => (),
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        match address {
+            RNG_DATA => self.reseed(value),
+            _ => {
+                self.write8(address, value as u8);
+                self.write8(address + 1, (value >> 8) as u8);
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.set(self.seed);
+    }
+
+    // The seed reset returns to is fixed at construction, only the sequence's position is state
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.state.get().to_le_bytes().to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        self.state.set(u16::from_le_bytes(fixed_state(state)?));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_the_same_sequence() {
+        let mut rng = Rng::new(0x0001);
+
+        rng.write16(RNG_DATA, 0x1234);
+        let first: Vec<u16> = (0..8).map(|_| rng.read16(RNG_DATA)).collect();
+
+        rng.write16(RNG_DATA, 0x1234);
+        let second: Vec<u16> = (0..8).map(|_| rng.read16(RNG_DATA)).collect();
+
+        assert_eq!(first, second);
+        assert_ne!(first[0], first[1]);
+    }
+
+    #[test]
+    fn reset_restarts_from_the_construction_seed() {
+        let mut rng = Rng::new(0x0042);
+
+        let first = rng.read16(RNG_DATA);
+
+        rng.write16(RNG_DATA, 0x9999);
+        rng.reset();
+
+        assert_eq!(rng.read16(RNG_DATA), first);
+    }
+
+    #[test]
+    fn zero_seed_does_not_get_stuck() {
+        let rng = Rng::new(0x0000);
+
+        assert_ne!(rng.read16(RNG_DATA), 0x0000);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut rng = Rng::new(0x1234);
+
+        rng.read16(RNG_DATA);
+
+        let state = rng.save_state().unwrap();
+        let next = rng.read16(RNG_DATA);
+
+        rng.load_state(&state).unwrap();
+
+        assert_eq!(rng.read16(RNG_DATA), next);
+    }
+}
@@ -0,0 +1,204 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Register offsets, all values are binary (not BCD) and in UTC
+pub const RTC_SECONDS: usize = 0x0; // Reading latches every register, so read this first
+pub const RTC_MINUTES: usize = 0x1;
+pub const RTC_HOURS: usize = 0x2;
+pub const RTC_DAY: usize = 0x3; // 1-31
+pub const RTC_MONTH: usize = 0x4; // 1-12
+pub const RTC_YEAR: usize = 0x5; // 16-bit, little-endian
+pub const RTC_SIZE: usize = 0x7;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+pub trait WallClock {
+    // Seconds since the Unix epoch
+    fn now(&self) -> u64;
+}
+
+#[cfg(feature = "std")]
+pub struct SystemWallClock;
+
+#[cfg(feature = "std")]
+impl WallClock for SystemWallClock {
+    fn now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+    }
+}
+
+// Read-only calendar clock, registers hold the time latched by the last read of RTC_SECONDS
+pub struct RealTimeClock {
+    wall_clock: Box<dyn WallClock>,
+    latched: Cell<[u8; RTC_SIZE]>,
+}
+
+impl RealTimeClock {
+    #[cfg(feature = "std")]
+    pub fn new() -> Self {
+        Self::with_wall_clock(Box::new(SystemWallClock))
+    }
+
+    pub fn with_wall_clock(wall_clock: Box<dyn WallClock>) -> Self {
+        Self {
+            wall_clock,
+            latched: Cell::new([0x00; RTC_SIZE]),
+        }
+    }
+
+    fn latch(&self) {
+        let timestamp = self.wall_clock.now();
+        let seconds_of_day = timestamp % SECONDS_PER_DAY;
+        let (year, month, day) = civil_from_days(timestamp / SECONDS_PER_DAY);
+
+        self.latched.set([
+            (seconds_of_day % 60) as u8,
+            (seconds_of_day / 60 % 60) as u8,
+            (seconds_of_day / 3600) as u8,
+            day,
+            month,
+            year as u8,
+            (year >> 8) as u8,
+        ]);
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for RealTimeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Converts days since the Unix epoch into a (year, month, day) date in the proleptic Gregorian calendar
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    // Shift the epoch to 0000-03-01 so leap days fall at the end of each 400 year era
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153; // March is 0
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+
+    (year as u16, month as u8, day as u8)
+}
+
+impl MappedDevice for RealTimeClock {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    // Registers are read-only
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), RTC_SIZE)
+    }
+
+    fn size(&self) -> usize {
+        RTC_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        if address == RTC_SECONDS {
+            self.latch();
+        }
+
+        self.latched.get().get(address).copied().unwrap_or(0x00)
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, _: usize, _: u8) {}
+
+    fn write16(&mut self, _: usize, _: u16) {}
+
+    fn reset(&mut self) {
+        self.latched.set([0x00; RTC_SIZE]);
+    }
+
+    // The wall clock is outside the machine, only the latched time is state
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.latched.get().to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        self.latched.set(fixed_state(state)?);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+
+    use super::*;
+
+    // Reports whatever the test last set
+    struct FixedWallClock(Rc<Cell<u64>>);
+
+    impl WallClock for FixedWallClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn clock(seconds: u64) -> (RealTimeClock, Rc<Cell<u64>>) {
+        let now = Rc::new(Cell::new(seconds));
+
+        (RealTimeClock::with_wall_clock(Box::new(FixedWallClock(Rc::clone(&now)))), now)
+    }
+
+    #[test]
+    fn reading_seconds_latches_the_date() {
+        let (rtc, now) = clock(1_709_210_096); // 2024-02-29 12:34:56
+
+        assert_eq!(rtc.read8(RTC_SECONDS), 56);
+
+        // Still the latched time until seconds is read again
+        now.set(0);
+
+        assert_eq!(rtc.read8(RTC_MINUTES), 34);
+        assert_eq!(rtc.read8(RTC_HOURS), 12);
+        assert_eq!(rtc.read8(RTC_DAY), 29);
+        assert_eq!(rtc.read8(RTC_MONTH), 2);
+        assert_eq!(rtc.read16(RTC_YEAR), 2024);
+
+        assert_eq!(rtc.read8(RTC_SECONDS), 0);
+        assert_eq!((rtc.read8(RTC_DAY), rtc.read8(RTC_MONTH), rtc.read16(RTC_YEAR)), (1, 1, 1970));
+    }
+
+    #[test]
+    fn century_leap_day_is_counted() {
+        let (rtc, _) = clock(951_782_400); // 2000-02-29
+
+        rtc.read8(RTC_SECONDS);
+
+        assert_eq!((rtc.read8(RTC_DAY), rtc.read8(RTC_MONTH), rtc.read16(RTC_YEAR)), (29, 2, 2000));
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let (mut rtc, now) = clock(1_709_210_096);
+
+        rtc.read8(RTC_SECONDS);
+
+        let state = rtc.save_state().unwrap();
+
+        rtc.reset();
+        now.set(0);
+        rtc.load_state(&state).unwrap();
+
+        assert_eq!(rtc.read16(RTC_YEAR), 2024);
+    }
+}
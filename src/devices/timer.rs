@@ -0,0 +1,212 @@
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Register offsets, 16-bit registers are little-endian
+pub const TIMER_RELOAD: usize = 0x0; // Writing also loads the count
+pub const TIMER_COUNT: usize = 0x2;
+pub const TIMER_CONTROL: usize = 0x4; // Bit 0 enables counting
+pub const TIMER_STATUS: usize = 0x5; // Bit 0 is the interrupt pending flag, reading clears it
+pub const TIMER_SIZE: usize = 0x6;
+
+// Counts down once per tick and flags an interrupt each time the count reaches zero
+pub struct Timer {
+    reload: u16,
+    count: u16,
+    enabled: bool,
+    pending: Cell<bool>,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            reload: 0x0000,
+            count: 0x0000,
+            enabled: false,
+            pending: Cell::new(false),
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        self.count = self.count.wrapping_sub(1);
+
+        if self.count == 0 {
+            self.pending.set(true);
+            self.count = self.reload;
+        }
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.pending.get()
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending.set(false);
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MappedDevice for Timer {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), TIMER_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        TIMER_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            TIMER_RELOAD => self.reload as u8,
+            0x1 => (self.reload >> 8) as u8,
+            TIMER_COUNT => self.count as u8,
+            0x3 => (self.count >> 8) as u8,
+            TIMER_CONTROL => self.enabled as u8,
+            TIMER_STATUS => self.pending.replace(false) as u8,
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        match address {
+            TIMER_RELOAD => {
+                self.reload = self.reload & 0xFF00 | value as u16;
+                self.count = self.reload;
+            },
+            0x1 => {
+                self.reload = self.reload & 0x00FF | (value as u16) << 8;
+                self.count = self.reload;
+            },
+            TIMER_COUNT => {
+                self.count = self.count & 0xFF00 | value as u16;
+            },
+            0x3 => {
+                self.count = self.count & 0x00FF | (value as u16) << 8;
+            },
+            TIMER_CONTROL => {
+                self.enabled = value & 0x01 != 0;
+            },
+            _ => (),
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.write8(address, value as u8);
+        self.write8(address + 1, (value >> 8) as u8);
+    }
+
+    fn reset(&mut self) {
+        self.reload = 0x0000;
+        self.count = 0x0000;
+        self.enabled = false;
+        self.pending.set(false);
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let [reload_low, reload_high] = self.reload.to_le_bytes();
+        let [count_low, count_high] = self.count.to_le_bytes();
+
+        Some([reload_low, reload_high, count_low, count_high, self.enabled as u8, self.pending.get() as u8].to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        let [reload_low, reload_high, count_low, count_high, enabled, pending] = fixed_state(state)?;
+
+        self.reload = u16::from_le_bytes([reload_low, reload_high]);
+        self.count = u16::from_le_bytes([count_low, count_high]);
+        self.enabled = enabled != 0;
+        self.pending.set(pending != 0);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_is_flagged_when_the_count_reaches_zero() {
+        let mut timer = Timer::new();
+
+        timer.write16(TIMER_RELOAD, 3);
+        timer.write8(TIMER_CONTROL, 0x01);
+
+        for _ in 0..2 {
+            timer.tick();
+            assert!(!timer.interrupt_pending());
+        }
+
+        timer.tick();
+
+        assert!(timer.interrupt_pending());
+        assert_eq!(timer.read16(TIMER_COUNT), 3);
+    }
+
+    #[test]
+    fn reading_the_status_acknowledges() {
+        let mut timer = Timer::new();
+
+        timer.write16(TIMER_RELOAD, 1);
+        timer.write8(TIMER_CONTROL, 0x01);
+        timer.tick();
+
+        assert_eq!(timer.read8(TIMER_STATUS), 0x01);
+        assert_eq!(timer.read8(TIMER_STATUS), 0x00);
+        assert!(!timer.interrupt_pending());
+    }
+
+    #[test]
+    fn disabled_timer_does_not_count() {
+        let mut timer = Timer::new();
+
+        timer.write16(TIMER_RELOAD, 5);
+        timer.tick();
+
+        assert_eq!(timer.read16(TIMER_COUNT), 5);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut timer = Timer::new();
+
+        timer.write16(TIMER_RELOAD, 0x1234);
+        timer.write8(TIMER_CONTROL, 0x01);
+        timer.tick();
+
+        let state = timer.save_state().unwrap();
+
+        timer.reset();
+        timer.load_state(&state).unwrap();
+
+        assert_eq!(timer.read16(TIMER_RELOAD), 0x1234);
+        assert_eq!(timer.read16(TIMER_COUNT), 0x1233);
+        assert_eq!(timer.read8(TIMER_CONTROL), 0x01);
+        assert!(timer.load_state(&state[1..]).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use alloc::vec::Vec;
+
+use crate::memory::{MappedDevice, MemoryError, check_range, fixed_state};
+
+// Register offsets, 16-bit registers are little-endian
+pub const TONE_FREQUENCY: usize = 0x0; // In Hz
+pub const TONE_DURATION: usize = 0x2; // Ticks left to sound, 0 sounds until disabled
+pub const TONE_CONTROL: usize = 0x4; // Bit 0 enables the tone, cleared when the duration runs out
+pub const TONE_SIZE: usize = 0x5;
+
+// Models the registers of a square-wave beeper, the host polls the output and synthesizes the audio itself
+pub struct ToneGenerator {
+    frequency: u16,
+    duration: u16,
+    enabled: bool,
+}
+
+impl ToneGenerator {
+    pub fn new() -> Self {
+        Self {
+            frequency: 0x0000,
+            duration: 0x0000,
+            enabled: false,
+        }
+    }
+
+    // Counts down the duration while the tone is sounding
+    pub fn tick(&mut self) {
+        if !self.enabled || self.duration == 0 {
+            return;
+        }
+
+        self.duration -= 1;
+
+        if self.duration == 0 {
+            self.enabled = false;
+        }
+    }
+
+    pub fn frequency(&self) -> u16 {
+        self.frequency
+    }
+
+    // A frequency of 0 is silent even when enabled
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.frequency != 0
+    }
+}
+
+impl Default for ToneGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MappedDevice for ToneGenerator {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), TONE_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        TONE_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            TONE_FREQUENCY => self.frequency as u8,
+            0x1 => (self.frequency >> 8) as u8,
+            TONE_DURATION => self.duration as u8,
+            0x3 => (self.duration >> 8) as u8,
+            TONE_CONTROL => self.enabled as u8,
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        match address {
+            TONE_FREQUENCY => {
+                self.frequency = self.frequency & 0xFF00 | value as u16;
+            },
+            0x1 => {
+                self.frequency = self.frequency & 0x00FF | (value as u16) << 8;
+            },
+            TONE_DURATION => {
+                self.duration = self.duration & 0xFF00 | value as u16;
+            },
+            0x3 => {
+                self.duration = self.duration & 0x00FF | (value as u16) << 8;
+            },
+            TONE_CONTROL => {
+                self.enabled = value & 0x01 != 0;
+            },
+            _ => (),
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.write8(address, value as u8);
+        self.write8(address + 1, (value >> 8) as u8);
+    }
+
+    fn reset(&mut self) {
+        self.frequency = 0x0000;
+        self.duration = 0x0000;
+        self.enabled = false;
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let [frequency_low, frequency_high] = self.frequency.to_le_bytes();
+        let [duration_low, duration_high] = self.duration.to_le_bytes();
+
+        Some([frequency_low, frequency_high, duration_low, duration_high, self.enabled as u8].to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        let [frequency_low, frequency_high, duration_low, duration_high, enabled] = fixed_state(state)?;
+
+        self.frequency = u16::from_le_bytes([frequency_low, frequency_high]);
+        self.duration = u16::from_le_bytes([duration_low, duration_high]);
+        self.enabled = enabled != 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_sees_the_programmed_tone() {
+        let mut tone = ToneGenerator::new();
+
+        tone.write16(TONE_FREQUENCY, 440);
+
+        assert_eq!(tone.frequency(), 440);
+        assert!(!tone.is_active());
+
+        tone.write8(TONE_CONTROL, 0x01);
+
+        assert!(tone.is_active());
+        assert_eq!(tone.read8(TONE_CONTROL), 0x01);
+
+        tone.write16(TONE_FREQUENCY, 0);
+
+        assert!(!tone.is_active());
+    }
+
+    #[test]
+    fn duration_runs_out_and_disables_the_tone() {
+        let mut tone = ToneGenerator::new();
+
+        tone.write16(TONE_FREQUENCY, 440);
+        tone.write16(TONE_DURATION, 2);
+        tone.write8(TONE_CONTROL, 0x01);
+        tone.tick();
+
+        assert!(tone.is_active());
+        assert_eq!(tone.read16(TONE_DURATION), 1);
+
+        tone.tick();
+
+        assert!(!tone.is_active());
+        assert_eq!(tone.read8(TONE_CONTROL), 0x00);
+    }
+
+    #[test]
+    fn zero_duration_sounds_until_disabled() {
+        let mut tone = ToneGenerator::new();
+
+        tone.write16(TONE_FREQUENCY, 440);
+        tone.write8(TONE_CONTROL, 0x01);
+
+        for _ in 0..100 {
+            tone.tick();
+        }
+
+        assert!(tone.is_active());
+
+        tone.write8(TONE_CONTROL, 0x00);
+
+        assert!(!tone.is_active());
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut tone = ToneGenerator::new();
+
+        tone.write16(TONE_FREQUENCY, 440);
+        tone.write16(TONE_DURATION, 10);
+        tone.write8(TONE_CONTROL, 0x01);
+
+        let state = tone.save_state().unwrap();
+
+        tone.reset();
+        tone.load_state(&state).unwrap();
+
+        assert!(tone.is_active());
+        assert_eq!(tone.frequency(), 440);
+        assert_eq!(tone.read16(TONE_DURATION), 10);
+    }
+}
@@ -0,0 +1,210 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::memory::{MappedDevice, MemoryError, check_range};
+
+// Register offsets
+pub const UART_DATA: usize = 0x0; // Reading pops from the RX FIFO, writing pushes to the TX FIFO
+pub const UART_STATUS: usize = 0x1; // Bit 0 is RX not empty, bit 1 is TX not full
+pub const UART_SIZE: usize = 0x2;
+
+pub const UART_FIFO_CAPACITY: usize = 16;
+
+pub struct Uart {
+    rx: RefCell<VecDeque<u8>>,
+    tx: VecDeque<u8>,
+}
+
+impl Uart {
+    pub fn new() -> Self {
+        Self {
+            rx: RefCell::new(VecDeque::with_capacity(UART_FIFO_CAPACITY)),
+            tx: VecDeque::with_capacity(UART_FIFO_CAPACITY),
+        }
+    }
+
+    // Returns false if the RX FIFO is full and the byte was dropped
+    pub fn push_rx(&mut self, byte: u8) -> bool {
+        let rx = self.rx.get_mut();
+
+        if rx.len() == UART_FIFO_CAPACITY {
+            return false;
+        }
+
+        rx.push_back(byte);
+
+        true
+    }
+
+    pub fn pop_tx(&mut self) -> Option<u8> {
+        self.tx.pop_front()
+    }
+}
+
+impl Default for Uart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MappedDevice for Uart {
+    // Registers have no backing memory to peek
+    fn peek_bytes(&self, _: usize, _: usize) -> Result<&[u8], MemoryError> {
+        Ok(&[])
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), UART_SIZE)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write8(address + i, *byte);
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        UART_SIZE
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        match address {
+            UART_DATA => self.rx.borrow_mut().pop_front().unwrap_or(0x00),
+            UART_STATUS => {
+                let mut status = 0x00;
+
+                if !self.rx.borrow().is_empty() {
+                    status |= 0x01;
+                }
+                if self.tx.len() < UART_FIFO_CAPACITY {
+                    status |= 0x02;
+                }
+
+                status
+            },
+            _ => 0x00,
+        }
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.read8(address) as u16 | (self.read8(address + 1) as u16) << 8
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        // Writes to a full TX FIFO are dropped
+        if address == UART_DATA && self.tx.len() < UART_FIFO_CAPACITY {
+            self.tx.push_back(value);
+        }
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.write8(address, value as u8);
+        self.write8(address + 1, (value >> 8) as u8);
+    }
+
+    fn reset(&mut self) {
+        self.rx.get_mut().clear();
+        self.tx.clear();
+    }
+
+    // The RX FIFO's length, then both FIFOs oldest first
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let rx = self.rx.borrow();
+        let mut state = Vec::with_capacity(1 + rx.len() + self.tx.len());
+
+        state.push(rx.len() as u8);
+        state.extend(rx.iter());
+        state.extend(self.tx.iter());
+
+        Some(state)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        let mismatch = MemoryError::SizeMismatch { expected: 1 + UART_FIFO_CAPACITY * 2, actual: state.len() };
+
+        let Some((&rx_length, fifos)) = state.split_first() else {
+            return Err(mismatch);
+        };
+        let Some((rx, tx)) = fifos.split_at_checked(rx_length as usize) else {
+            return Err(mismatch);
+        };
+
+        if rx.len() > UART_FIFO_CAPACITY || tx.len() > UART_FIFO_CAPACITY {
+            return Err(mismatch);
+        }
+
+        *self.rx.get_mut() = rx.iter().copied().collect();
+        self.tx = tx.iter().copied().collect();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmitted_bytes_come_out_in_order() {
+        let mut uart = Uart::new();
+
+        uart.write8(UART_DATA, b'h');
+        uart.write8(UART_DATA, b'i');
+
+        assert_eq!(uart.pop_tx(), Some(b'h'));
+        assert_eq!(uart.pop_tx(), Some(b'i'));
+        assert_eq!(uart.pop_tx(), None);
+    }
+
+    #[test]
+    fn received_bytes_are_read_from_data() {
+        let mut uart = Uart::new();
+
+        assert_eq!(uart.read8(UART_STATUS), 0x02);
+
+        uart.push_rx(0x07);
+
+        assert_eq!(uart.read8(UART_STATUS), 0x03);
+        assert_eq!(uart.read8(UART_DATA), 0x07);
+        assert_eq!(uart.read8(UART_STATUS), 0x02);
+    }
+
+    #[test]
+    fn full_fifos_drop_bytes() {
+        let mut uart = Uart::new();
+
+        for byte in 0..UART_FIFO_CAPACITY as u8 {
+            assert!(uart.push_rx(byte));
+            uart.write8(UART_DATA, byte);
+        }
+
+        assert!(!uart.push_rx(0xFF));
+        assert_eq!(uart.read8(UART_STATUS), 0x01);
+
+        uart.write8(UART_DATA, 0xFF);
+
+        assert_eq!((0..UART_FIFO_CAPACITY).filter_map(|_| uart.pop_tx()).last(), Some(UART_FIFO_CAPACITY as u8 - 1));
+        assert_eq!(uart.pop_tx(), None);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut uart = Uart::new();
+
+        uart.push_rx(0x01);
+        uart.push_rx(0x02);
+        uart.write8(UART_DATA, 0x03);
+
+        let state = uart.save_state().unwrap();
+
+        uart.reset();
+        uart.load_state(&state).unwrap();
+
+        assert_eq!(uart.read8(UART_DATA), 0x01);
+        assert_eq!(uart.read8(UART_DATA), 0x02);
+        assert_eq!(uart.pop_tx(), Some(0x03));
+        assert_eq!(uart.pop_tx(), None);
+        assert!(uart.load_state(&[0x03, 0x01]).is_err());
+    }
+}
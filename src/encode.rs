@@ -0,0 +1,142 @@
+// Instruction word layout, the inverse of the CPU's decoder:
+//
+//   15..12       11..8   7      6          5..0
+//   destination  source  lo_hi  byte_mode  operation
+//
+// Operand bytes follow the word, the source's before the destination's. An EXT word before an instruction replaces either
+// location with an extended one through its own destination and source nibbles
+
+pub const OPERATION_MASK: u16 = 0x003F;
+pub const BYTE_MODE_BIT: u16 = 0x0040;
+pub const LO_HI_BIT: u16 = 0x0080; // Selects the high byte of byte registers in byte mode
+pub const LOCATION_MASK: u16 = 0xF; // After shifting
+pub const SOURCE_SHIFT: u32 = 8;
+pub const DESTINATION_SHIFT: u32 = 12;
+
+// Operations
+pub const OPCODE_MOV: u16 = 0x00;
+pub const OPCODE_ADC: u16 = 0x01;
+pub const OPCODE_SBC: u16 = 0x02;
+pub const OPCODE_XCHG: u16 = 0x03;
+pub const OPCODE_BIT: u16 = 0x04;
+pub const OPCODE_INX: u16 = 0x05;
+pub const OPCODE_DEX: u16 = 0x06;
+pub const OPCODE_INY: u16 = 0x07;
+pub const OPCODE_DEY: u16 = 0x08;
+pub const OPCODE_SWAP: u16 = 0x09;
+pub const OPCODE_JTB: u16 = 0x0A;
+pub const OPCODE_DBNZ: u16 = 0x0B;
+pub const OPCODE_SWP: u16 = 0x0C;
+pub const OPCODE_CMP: u16 = 0x0D;
+pub const OPCODE_ADCL: u16 = 0x0E;
+pub const OPCODE_SBCL: u16 = 0x0F;
+pub const OPCODE_BGT: u16 = 0x10;
+pub const OPCODE_BLT: u16 = 0x11;
+pub const OPCODE_BGE: u16 = 0x12;
+pub const OPCODE_BLE: u16 = 0x13;
+pub const OPCODE_BHI: u16 = 0x14;
+pub const OPCODE_BLO: u16 = 0x15;
+pub const OPCODE_BHS: u16 = 0x16;
+pub const OPCODE_BLS: u16 = 0x17;
+pub const OPCODE_ADDSAT: u16 = 0x18;
+pub const OPCODE_SUBSAT: u16 = 0x19;
+pub const OPCODE_MIN: u16 = 0x1A;
+pub const OPCODE_MAX: u16 = 0x1B;
+pub const OPCODE_LEA: u16 = 0x1C;
+pub const OPCODE_CLZ: u16 = 0x1D;
+pub const OPCODE_POPCNT: u16 = 0x1E;
+pub const OPCODE_SYSCALL: u16 = 0x1F;
+pub const OPCODE_MOVIF: u16 = 0x20; // Through OPCODE_MOVIF_LAST, the low bits are the condition
+pub const OPCODE_MOVIF_LAST: u16 = 0x27;
+pub const OPCODE_STP: u16 = 0x30;
+pub const OPCODE_RST: u16 = 0x31;
+pub const OPCODE_WAI: u16 = 0x32;
+pub const OPCODE_NOP: u16 = 0x3E;
+pub const OPCODE_EXT: u16 = 0x3F;
+
+// MOVIF conditions, a flag in the upper two bits and whether to invert it in the lowest
+pub const CONDITION_MASK: u16 = 0x7;
+pub const CONDITION_NEGATE: u16 = 0x1;
+pub const CONDITION_ZERO: u16 = 0x0;
+pub const CONDITION_CARRY: u16 = 0x2;
+pub const CONDITION_SIGN: u16 = 0x4;
+pub const CONDITION_OVERFLOW: u16 = 0x6;
+
+// Location nibbles
+pub const LOCATION_IMMEDIATE: u16 = 0x0; // As a destination the result is discarded
+pub const LOCATION_A: u16 = 0x1;
+pub const LOCATION_B: u16 = 0x2;
+pub const LOCATION_C: u16 = 0x3;
+pub const LOCATION_D: u16 = 0x4;
+pub const LOCATION_IDX: u16 = 0x5;
+pub const LOCATION_IDY: u16 = 0x6;
+pub const LOCATION_ADDRESS: u16 = 0x7; // [$nnnn]
+pub const LOCATION_INDEXED_ADDRESS: u16 = 0x8; // [$nnnn+IDX]
+pub const LOCATION_INDIRECT_ADDRESS: u16 = 0x9; // [[$nnnn]]
+pub const LOCATION_INDIRECT_INDEXED_ADDRESS: u16 = 0xA; // [[$nnnn]+IDX]
+pub const LOCATION_INDEXED_INDIRECT_ADDRESS: u16 = 0xB; // [[$nnnn+IDX]]
+pub const LOCATION_INDEXED_POINTER: u16 = 0xC; // [IDY+IDX]
+pub const LOCATION_INDIRECT_POINTER: u16 = 0xD; // [[IDY]]
+pub const LOCATION_INDIRECT_INDEXED_POINTER: u16 = 0xE; // [[IDY]+IDX]
+pub const LOCATION_INDEXED_INDIRECT_POINTER: u16 = 0xF; // [[IDY+IDX]]
+
+// Extended location nibbles, 0 leaves the instruction's own location in place
+pub const EXTENDED_POST_INCREMENT_POINTER: u16 = 0x1; // [IDY+]
+pub const EXTENDED_PRE_DECREMENT_POINTER: u16 = 0x2; // [-IDY]
+pub const EXTENDED_STACK_RELATIVE: u16 = 0x3; // [SP+$nn]
+pub const EXTENDED_BYTE_INDEXED_ADDRESS: u16 = 0x4; // [$nnnn+XL]
+pub const EXTENDED_BYTE_INDEXED_POINTER: u16 = 0x5; // [IDY+XL]
+pub const EXTENDED_ZERO_PAGE: u16 = 0x6; // [$nn]
+pub const EXTENDED_PROGRAM_COUNTER_RELATIVE: u16 = 0x7; // [PC+$nnnn]
+
+pub fn encode_instruction(operation: u16, byte_mode: bool, lo_hi: bool, destination: u16, source: u16) -> u16 {
+    assert!(operation <= OPERATION_MASK, "Operation 0x{:02X} doesn't fit in 6 bits", operation);
+    assert!(destination <= LOCATION_MASK && source <= LOCATION_MASK, "Locations should be nibbles");
+
+    operation
+        | if byte_mode { BYTE_MODE_BIT } else { 0 }
+        | if lo_hi { LO_HI_BIT } else { 0 }
+        | (source << SOURCE_SHIFT)
+        | (destination << DESTINATION_SHIFT)
+}
+
+// The EXT prefix word for extended destination and source locations
+pub fn encode_extension(destination: u16, source: u16) -> u16 {
+    encode_instruction(OPCODE_EXT, false, false, destination, source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_land_in_their_bits() {
+        assert_eq!(encode_instruction(OPCODE_MOV, false, false, LOCATION_A, LOCATION_IMMEDIATE), 0x1000);
+        assert_eq!(encode_instruction(OPCODE_ADC, true, true, LOCATION_B, LOCATION_C), 0x23C1);
+        assert_eq!(encode_instruction(OPCODE_EXT, false, false, LOCATION_INDEXED_INDIRECT_POINTER, LOCATION_INDEXED_INDIRECT_POINTER), 0xFF3F);
+        assert_eq!(encode_extension(EXTENDED_ZERO_PAGE, EXTENDED_PROGRAM_COUNTER_RELATIVE), 0x673F);
+    }
+
+    #[test]
+    fn fields_mask_back_out() {
+        let instruction = encode_instruction(OPCODE_SBC, true, false, LOCATION_IDY, LOCATION_ADDRESS);
+
+        assert_eq!(instruction & OPERATION_MASK, OPCODE_SBC);
+        assert_ne!(instruction & BYTE_MODE_BIT, 0);
+        assert_eq!(instruction & LO_HI_BIT, 0);
+        assert_eq!((instruction >> SOURCE_SHIFT) & LOCATION_MASK, LOCATION_ADDRESS);
+        assert_eq!((instruction >> DESTINATION_SHIFT) & LOCATION_MASK, LOCATION_IDY);
+    }
+
+    #[test]
+    #[should_panic(expected = "Operation 0x40 doesn't fit in 6 bits")]
+    fn operation_wider_than_six_bits_panics() {
+        encode_instruction(0x40, false, false, LOCATION_A, LOCATION_A);
+    }
+
+    #[test]
+    #[should_panic(expected = "Locations should be nibbles")]
+    fn location_wider_than_a_nibble_panics() {
+        encode_instruction(OPCODE_MOV, false, false, 0x10, LOCATION_A);
+    }
+}
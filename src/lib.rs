@@ -0,0 +1,14 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod clock;
+pub mod cpu;
+pub mod devices;
+pub mod encode;
+pub mod machine;
+pub mod memory;
+#[cfg(feature = "wasm")]
+pub mod wasm;
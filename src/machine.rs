@@ -0,0 +1,187 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::cpu::{ADDRESS_BUS_WIDTH, CPU, IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR};
+use crate::memory::{Endianness, MAP_BLOCK_SIZE, MappedDevice, MemoryController, MemoryError, RAM, ROM};
+
+// Wires devices, vectors and a program into a reset-ready CPU
+pub struct MachineBuilder {
+    address_bus_width: u32,
+    block_size: usize,
+    endianness: Endianness,
+    devices: Vec<(usize, Box<dyn MappedDevice>)>,
+    vector_locations: [usize; 3], // Reset, NMI, IRQ
+    vectors: [Option<u16>; 3],
+    programs: Vec<(usize, Vec<u8>)>,
+    stack_top: Option<u16>,
+}
+
+impl MachineBuilder {
+    pub fn new() -> Self {
+        Self {
+            address_bus_width: ADDRESS_BUS_WIDTH,
+            block_size: MAP_BLOCK_SIZE,
+            endianness: Endianness::Little,
+            devices: vec![],
+            vector_locations: [RESET_VECTOR, NMI_VECTOR, IRQ_VECTOR],
+            vectors: [None; 3],
+            programs: vec![],
+            stack_top: None,
+        }
+    }
+
+    // Granularity devices are mapped at, see MemoryController::with_block_size
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    // See MemoryController::with_address_bus_width, the vectors alias to the top of the narrower space
+    pub fn address_bus_width(mut self, address_bus_width: u32) -> Self {
+        self.address_bus_width = address_bus_width;
+        self
+    }
+
+    // Byte order of words in memory, including the vectors
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    pub fn ram(self, first_address: usize, capacity: usize) -> Self {
+        self.device(first_address, Box::new(RAM::new(capacity)))
+    }
+
+    pub fn rom(self, first_address: usize, capacity: usize) -> Self {
+        self.device(first_address, Box::new(ROM::new(capacity)))
+    }
+
+    // The device is mapped at the block containing first_address, over as many blocks as its size needs
+    pub fn device(mut self, first_address: usize, device: Box<dyn MappedDevice>) -> Self {
+        self.devices.push((first_address, device));
+        self
+    }
+
+    // Moves where the CPU looks for its vectors, defaults to the standard locations
+    pub fn vector_locations(mut self, reset_vector: usize, nmi_vector: usize, irq_vector: usize) -> Self {
+        self.vector_locations = [reset_vector, nmi_vector, irq_vector];
+        self
+    }
+
+    pub fn reset_vector(mut self, address: u16) -> Self {
+        self.vectors[0] = Some(address);
+        self
+    }
+
+    pub fn nmi_vector(mut self, address: u16) -> Self {
+        self.vectors[1] = Some(address);
+        self
+    }
+
+    pub fn irq_vector(mut self, address: u16) -> Self {
+        self.vectors[2] = Some(address);
+        self
+    }
+
+    // Defaults to the end of the lowest RAM
+    pub fn stack_top(mut self, stack_top: u16) -> Self {
+        self.stack_top = Some(stack_top);
+        self
+    }
+
+    // Programs should be loaded into ROM, because resetting the CPU clears RAM
+    pub fn program(mut self, address: usize, code: &[u8]) -> Self {
+        self.programs.push((address, code.to_vec()));
+        self
+    }
+
+    pub fn build(self) -> Result<CPU, MemoryError> {
+        let mut cpu = CPU::new();
+
+        cpu.memory_controller = MemoryController::with_address_bus_width(self.address_bus_width, self.block_size);
+        cpu.memory_controller.set_endianness(self.endianness);
+
+        let [reset_vector, nmi_vector, irq_vector] = self.vector_locations;
+
+        cpu.set_reset_vector(reset_vector);
+        cpu.set_nmi_vector(nmi_vector);
+        cpu.set_irq_vector(irq_vector);
+
+        if let Some(stack_top) = self.stack_top {
+            cpu.set_stack_top(stack_top);
+        }
+
+        for (first_address, device) in self.devices {
+            let blocks = device.size().div_ceil(self.block_size);
+
+            cpu.memory_controller.map_device(first_address / self.block_size, blocks, device)?;
+        }
+
+        for (location, vector) in self.vector_locations.into_iter().zip(self.vectors) {
+            if let Some(address) = vector {
+                let location = location % cpu.memory_controller.address_space();
+
+                cpu.memory_controller.poke_bytes(location, &self.endianness.to_bytes(address))?;
+            }
+        }
+
+        for (address, code) in self.programs {
+            cpu.memory_controller.poke_bytes(address, &code)?;
+        }
+
+        cpu.reset();
+
+        Ok(cpu)
+    }
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_a_machine_that_starts_at_the_reset_vector() {
+        let mut cpu = MachineBuilder::new()
+            .ram(0x0000, 0x1000)
+            .rom(0x8000, 0x8000)
+            .reset_vector(0x8100)
+            .program(0x8100, &[0x00, 0x10, 0x34, 0x12]) // MOV A, #$1234
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.program_counter(), 0x8100);
+        assert_eq!(cpu.stack_pointer(), 0x1000);
+
+        cpu.process(false, None);
+
+        assert_eq!(cpu.a(), 0x1234);
+    }
+
+    #[test]
+    fn overlapping_devices_fail_to_build() {
+        let result = MachineBuilder::new().ram(0x0000, 0x2000).rom(0x1000, 0x1000).build();
+
+        assert!(matches!(result, Err(MemoryError::BlockAlreadyMapped { block: 1, .. })));
+    }
+    #[test]
+    fn vectors_are_written_to_their_locations() {
+        let cpu = MachineBuilder::new()
+            .rom(0x8000, 0x8000)
+            .vector_locations(0x8000, 0x8002, 0x8004)
+            .reset_vector(0x8010)
+            .irq_vector(0x8020)
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.reset_vector(), 0x8000);
+        assert_eq!(cpu.program_counter(), 0x8010);
+        assert_eq!(cpu.memory_controller.read16(0x8004), 0x8020);
+    }
+}
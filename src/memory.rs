@@ -1,183 +1,1026 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::{Any, type_name};
+use core::cell::{Cell, RefCell};
+use core::error::Error;
+use core::fmt;
+use core::mem;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::cpu::ADDRESS_BUS_WIDTH;
 
-pub const ADDRESS_SPACE: usize = 2_usize.pow(ADDRESS_BUS_WIDTH);
-pub const MAP_BLOCK_SIZE: usize = 0x1000; // 4 KiB
+mod static_controller;
+#[cfg(test)]
+mod tests;
+
+pub use static_controller::*;
+
+pub const ADDRESS_SPACE: usize = 2_usize.pow(ADDRESS_BUS_WIDTH); // For the default bus width
+pub const MIN_ADDRESS_BUS_WIDTH: u32 = 8; // One block of the smallest size
+pub const MAP_BLOCK_SIZE: usize = 0x1000; // 4 KiB, the default
 pub const MAP_BLOCKS: usize = ADDRESS_SPACE / MAP_BLOCK_SIZE;
+pub const MIN_MAP_BLOCK_SIZE: usize = JOURNAL_PAGE_SIZE; // Journal pages can't straddle two mappings
+pub const JOURNAL_PAGE_SIZE: usize = 0x100; // 256 B
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    BlockAlreadyMapped { block: usize, mapping_index: usize, offset: usize }, // The mapping in the way and the address it starts at
+    IndexOutOfBounds { index: usize },
+    SizeMismatch { expected: usize, actual: usize },
+    WrongDeviceType { index: usize, expected: &'static str },
+    RangeOutOfBounds { address: usize, count: usize, size: usize },
+    BlocksOutOfBounds { first_block: usize, blocks: usize, block_count: usize }, // Blocks past the end of the address space
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BlockAlreadyMapped { block, mapping_index, offset } => write!(f, "Block {} is already mapped to device #{} at 0x{:04X}", block, mapping_index, offset),
+            Self::IndexOutOfBounds { index } => write!(f, "Index {} is out-of-bounds", index),
+            Self::SizeMismatch { expected, actual } => write!(f, "Expected a size of {} but got {}", expected, actual),
+            Self::WrongDeviceType { index, expected } => write!(f, "Device {} is not a {}", index, expected),
+            Self::RangeOutOfBounds { address, count, size } => write!(f, "{} bytes at 0x{:04X} overrun a device of size 0x{:04X}", count, address, size),
+            Self::BlocksOutOfBounds { first_block, blocks, block_count } => write!(f, "{} blocks from block {} overrun the {} blocks of the address space", blocks, first_block, block_count),
+        }
+    }
+}
+
+impl Error for MemoryError {}
+
+// Byte order of 16-bit bus accesses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    // The value's bytes in the order they sit in memory
+    pub fn to_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            Self::Little => value.to_le_bytes(),
+            Self::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub fn from_bytes(self, bytes: [u8; 2]) -> u16 {
+        match self {
+            Self::Little => u16::from_le_bytes(bytes),
+            Self::Big => u16::from_be_bytes(bytes),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessWidth {
+    Byte,
+    Word,
+}
+
+// Called with the address, width and value of a bus access
+pub type AccessHook = Box<dyn FnMut(usize, AccessWidth, u16)>;
+
+// For devices to check a peek or poke span fits inside them
+pub fn check_range(address: usize, count: usize, size: usize) -> Result<(), MemoryError> {
+    if address.checked_add(count).is_none_or(|end| end > size) {
+        return Err(MemoryError::RangeOutOfBounds { address, count, size });
+    }
+
+    Ok(())
+}
+
+// For devices whose saved state is always the same size to check and unpack it in load_state
+pub fn fixed_state<const N: usize>(state: &[u8]) -> Result<[u8; N], MemoryError> {
+    state.try_into().map_err(|_| MemoryError::SizeMismatch { expected: N, actual: state.len() })
+}
 
 pub struct MemoryController {
-    blocks: [Option<usize>; MAP_BLOCKS],
+    address_space: usize,
+    block_size: usize,
+    endianness: Endianness,
+    blocks: Vec<Option<usize>>,
     mappings: Vec<Mapping>,
+    watchpoints: Vec<usize>,
+    watchpoint_hits: Vec<WatchpointHit>,
+    last_block: Cell<Option<(usize, usize, usize)>>, // Block, mapping index and offset of the last mapped block accessed
+    dirty_pages: Option<Vec<bool>>, // RAM pages already in the journal, journaling is disabled when None
+    journal: PageJournal,
+    read_hook: Option<RefCell<AccessHook>>, // Reads only borrow the controller, so the hook needs its own mutability
+    write_hook: Option<AccessHook>,
+    bus_writes: u64,
+    wait_states: Cell<u64>, // Total over every bus access so far, a Cell since reads only borrow the controller
 }
 
 impl MemoryController {
     pub fn new() -> Self {
+        Self::with_block_size(MAP_BLOCK_SIZE)
+    }
+
+    // Smaller blocks let devices occupy tighter regions, at the cost of a larger block table
+    pub fn with_block_size(block_size: usize) -> Self {
+        Self::with_address_bus_width(ADDRESS_BUS_WIDTH, block_size)
+    }
+
+    // Emulates a narrower address bus, addresses are masked to the bus width so everything above the space aliases back into it.
+    // The CPU's registers are 16 bits wide, so the bus can't be any wider than the default
+    pub fn with_address_bus_width(address_bus_width: u32, block_size: usize) -> Self {
+        assert!(
+            (MIN_ADDRESS_BUS_WIDTH..=ADDRESS_BUS_WIDTH).contains(&address_bus_width),
+            "Address bus width should be between {} and {} bits",
+            MIN_ADDRESS_BUS_WIDTH,
+            ADDRESS_BUS_WIDTH,
+        );
+
+        let address_space = 2_usize.pow(address_bus_width);
+
+        assert!(
+            block_size.is_power_of_two() && (MIN_MAP_BLOCK_SIZE..=address_space).contains(&block_size),
+            "Block size should be a power of two between 0x{:X} and 0x{:X}",
+            MIN_MAP_BLOCK_SIZE,
+            address_space,
+        );
+
         Self {
-            blocks: [None; MAP_BLOCKS],
+            address_space,
+            block_size,
+            endianness: Endianness::Little,
+            blocks: vec![None; address_space / block_size],
             mappings: vec![],
+            watchpoints: vec![],
+            watchpoint_hits: vec![],
+            last_block: Cell::new(None),
+            dirty_pages: None,
+            journal: PageJournal::default(),
+            read_hook: None,
+            write_hook: None,
+            bus_writes: 0,
+            wait_states: Cell::new(0),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn address_space(&self) -> usize {
+        self.address_space
+    }
+
+    // Drops the address lines the bus doesn't have
+    fn mask_address(&self, address: usize) -> usize {
+        address & (self.address_space - 1)
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    // Devices store words little-endian, so big-endian accesses are byte-swapped on the way through
+    pub fn set_endianness(&mut self, endianness: Endianness) {
+        self.endianness = endianness;
+    }
+
+    fn check_blocks(&self, first_block: usize, blocks: usize) -> Result<(), MemoryError> {
+        match first_block.checked_add(blocks) {
+            Some(end) if end <= self.blocks.len() => Ok(()),
+            _ => Err(MemoryError::BlocksOutOfBounds { first_block, blocks, block_count: self.blocks.len() }),
         }
     }
 
     // Returns index of device mapping or an error
-    pub fn map_device(&mut self, first_block: usize, blocks: usize, device: Box<dyn MappedDevice>) -> Result<usize, String> {
+    pub fn map_device(&mut self, first_block: usize, blocks: usize, device: Box<dyn MappedDevice>) -> Result<usize, MemoryError> {
+        self.check_blocks(first_block, blocks)?;
+
         for block in first_block..first_block + blocks {
-            if self.blocks[block] != None {
-                return Err(format!("Block {} is already mapped to another device", block));
+            if let Some(mapping_index) = self.blocks[block] {
+                return Err(MemoryError::BlockAlreadyMapped { block, mapping_index, offset: self.mappings[mapping_index].offset });
             }
         }
 
         self.mappings.push(Mapping {
-            offset: first_block * MAP_BLOCK_SIZE,
-            device,
+            offset: first_block * self.block_size,
+            backing: Backing::new(device),
+            executable: true,
+            wait_states: 0,
          });
 
-        let mapping_index = self.mappings.len() - 1;
+        let mapping_index = self.mappings.len() - 1;
+
+        self.last_block.set(None);
+
+        for block in first_block..first_block + blocks {
+            self.blocks[block] = Some(mapping_index);
+        }
+
+        Ok(mapping_index)
+    }
+
+    pub fn unmap_device(&mut self, mapping_index: usize) -> Result<(), MemoryError> {
+        if mapping_index >= self.mappings.len() {
+            return Err(MemoryError::IndexOutOfBounds { index: mapping_index });
+        }
+
+        self.mappings.swap_remove(mapping_index);
+
+        self.last_block.set(None);
+
+        // Because swap_remove removes an element and replaces it with the last element in the vector, we need to update all the blocks that pointed to the last element
+                
+        let last_mapping_index = self.mappings.len(); // The current length of the vector equals the index of what the last element in the vector *used* to be
+
+        for block in &mut self.blocks {
+            // Remove all indecies to the element we just removed
+            if *block == Some(mapping_index) {
+                *block = None;
+            }
+            // Change all indecies that used to point to the last element to point to the element it just replaced
+            if *block == Some(last_mapping_index) {
+                *block = Some(mapping_index);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Moves a mapping to other blocks in place, unlike unmapping and mapping again its index stays the same
+    pub fn remap_device(&mut self, mapping_index: usize, new_first_block: usize, blocks: usize) -> Result<(), MemoryError> {
+        if mapping_index >= self.mappings.len() {
+            return Err(MemoryError::IndexOutOfBounds { index: mapping_index });
+        }
+
+        self.check_blocks(new_first_block, blocks)?;
+
+        // Blocks the mapping already occupies can be reused
+        for block in new_first_block..new_first_block + blocks {
+            if let Some(other_index) = self.blocks[block]
+                && other_index != mapping_index
+            {
+                return Err(MemoryError::BlockAlreadyMapped { block, mapping_index: other_index, offset: self.mappings[other_index].offset });
+            }
+        }
+
+        for block in &mut self.blocks {
+            if *block == Some(mapping_index) {
+                *block = None;
+            }
+        }
+
+        for block in new_first_block..new_first_block + blocks {
+            self.blocks[block] = Some(mapping_index);
+        }
+
+        self.mappings[mapping_index].offset = new_first_block * self.block_size;
+
+        self.last_block.set(None);
+
+        Ok(())
+    }
+
+    pub fn get_device(&self, mapping_index: usize) -> Result<&dyn MappedDevice, MemoryError> {
+        if mapping_index >= self.mappings.len() {
+            return Err(MemoryError::IndexOutOfBounds { index: mapping_index });
+        }
+
+        Ok(self.mappings[mapping_index].device())
+    }
+
+    pub fn get_device_mut(&mut self, mapping_index: usize) -> Result<&mut dyn MappedDevice, MemoryError> {
+        if mapping_index >= self.mappings.len() {
+            return Err(MemoryError::IndexOutOfBounds { index: mapping_index });
+        }
+
+        Ok(self.mappings[mapping_index].device_mut())
+    }
+
+    pub fn get_device_as<T: MappedDevice>(&self, mapping_index: usize) -> Result<&T, MemoryError> {
+        let device: &dyn Any = self.get_device(mapping_index)?;
+
+        device.downcast_ref::<T>().ok_or(MemoryError::WrongDeviceType { index: mapping_index, expected: type_name::<T>() })
+    }
+
+    pub fn get_device_as_mut<T: MappedDevice>(&mut self, mapping_index: usize) -> Result<&mut T, MemoryError> {
+        let device: &mut dyn Any = self.get_device_mut(mapping_index)?;
+
+        device.downcast_mut::<T>().ok_or(MemoryError::WrongDeviceType { index: mapping_index, expected: type_name::<T>() })
+    }
+
+    // Mappings are executable when mapped, the CPU's execute guard halts on instructions fetched from ones that aren't
+    pub fn set_executable(&mut self, mapping_index: usize, executable: bool) -> Result<(), MemoryError> {
+        if mapping_index >= self.mappings.len() {
+            return Err(MemoryError::IndexOutOfBounds { index: mapping_index });
+        }
+
+        self.mappings[mapping_index].executable = executable;
+
+        Ok(())
+    }
+
+    // Unmapped addresses have nothing to execute
+    pub fn is_executable(&self, address: usize) -> bool {
+        self.translate(self.mask_address(address)).is_some_and(|(mapping_index, _)| self.mappings[mapping_index].executable)
+    }
+
+    // Writes over the bus so far, mapped or not, the CPU compares it across an instruction to tell whether memory changed
+    pub fn bus_writes(&self) -> u64 {
+        self.bus_writes
+    }
+
+    // Slow devices stall the CPU on every read or write through the bus, peeks and pokes are free
+    pub fn set_wait_states(&mut self, mapping_index: usize, wait_states: u8) -> Result<(), MemoryError> {
+        if mapping_index >= self.mappings.len() {
+            return Err(MemoryError::IndexOutOfBounds { index: mapping_index });
+        }
+
+        self.mappings[mapping_index].wait_states = wait_states;
+
+        Ok(())
+    }
+
+    // Wait states of every bus access so far, the CPU adds the difference over each instruction to its cycle count
+    pub fn wait_states(&self) -> u64 {
+        self.wait_states.get()
+    }
+
+    fn add_wait_states(&self, mapping_index: usize) {
+        let wait_states = self.mappings[mapping_index].wait_states as u64;
+
+        self.wait_states.set(self.wait_states.get().wrapping_add(wait_states));
+    }
+
+    // Returns the mapping index and the address within the device, consecutive accesses usually hit the same block so it is cached
+    fn translate(&self, address: usize) -> Option<(usize, usize)> {
+        let block = address / self.block_size;
+
+        if let Some((last_block, mapping_index, offset)) = self.last_block.get()
+            && last_block == block
+        {
+            return Some((mapping_index, address - offset));
+        }
+
+        let mapping_index = self.blocks[block]?;
+        let offset = self.mappings[mapping_index].offset;
+
+        self.last_block.set(Some((block, mapping_index, offset)));
+
+        Some((mapping_index, address - offset))
+    }
+
+    // The bytes of RAM or a writable region behind a span within one block, cut short where the device ends
+    fn writable_storage(&mut self, address: usize, len: usize) -> Option<&mut [u8]> {
+        let (mapping_index, translated_address) = self.translate(address)?;
+
+        let region = match &mut self.mappings[mapping_index].backing {
+            Backing::Ram(ram) => &mut ram.region,
+            Backing::Device(device) => (device.as_mut() as &mut dyn Any).downcast_mut::<MappedRegion>().filter(|region| region.writable)?,
+        };
+
+        let start = translated_address.min(region.memory.len());
+        let end = (translated_address + len).min(region.memory.len());
+
+        Some(&mut region.memory[start..end])
+    }
+
+    pub fn read8(&self, address: usize) -> u8 {
+        let address = self.mask_address(address);
+        let value = self.read8_unhooked(address);
+
+        if let Some((mapping_index, _)) = self.translate(address) {
+            self.add_wait_states(mapping_index);
+        }
+
+        if let Some(read_hook) = &self.read_hook {
+            (read_hook.borrow_mut())(address, AccessWidth::Byte, value as u16);
+        }
+
+        value
+    }
+
+    fn read8_unhooked(&self, address: usize) -> u8 {
+        match self.translate(address) {
+            Some((mapping_index, translated_address)) => match &self.mappings[mapping_index].backing {
+                Backing::Ram(ram) => ram.read8(translated_address),
+                Backing::Device(device) => device.read8(translated_address),
+            },
+            None => 0x00,
+        }
+    }
+
+    pub fn read16(&self, address: usize) -> u16 {
+        let address = self.mask_address(address);
+        let value = match self.translate(address) {
+            Some((mapping_index, translated_address)) => {
+                self.add_wait_states(mapping_index);
+
+                match &self.mappings[mapping_index].backing {
+                    Backing::Ram(ram) => ram.read16(translated_address),
+                    Backing::Device(device) => device.read16(translated_address),
+                }
+            },
+            None => 0x00,
+        };
+
+        let value = match self.endianness {
+            Endianness::Little => value,
+            Endianness::Big => value.swap_bytes(),
+        };
+
+        if let Some(read_hook) = &self.read_hook {
+            (read_hook.borrow_mut())(address, AccessWidth::Word, value);
+        }
+
+        value
+    }
+
+    // Loads bytes into whichever devices are mapped, ignoring writability, unmapped bytes are dropped but overrunning a device is an error
+    pub fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), self.address_space)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            let address = address + i;
+
+            if self.dirty_pages.is_some() {
+                self.record_page(address);
+            }
+
+            if let Some((mapping_index, translated_address)) = self.translate(address) {
+                self.mappings[mapping_index].device_mut().poke_bytes(translated_address, &[*byte])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Side-effect free view of memory for debuggers, unmapped addresses and devices without storage read as open bus
+    pub fn peek_range(&self, start: usize, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| {
+                let address = self.mask_address(start + i);
+
+                self.translate(address)
+                    .and_then(|(mapping_index, translated_address)| self.mappings[mapping_index].device().peek8(translated_address))
+                    .unwrap_or(0x00)
+            })
+            .collect()
+    }
+
+    // One past the last byte of the lowest-addressed RAM, where a stack can start
+    pub fn first_ram_end(&self) -> Option<usize> {
+        self.mappings.iter()
+            .filter_map(|mapping| match &mapping.backing {
+                Backing::Ram(ram) => Some((mapping.offset, mapping.offset + ram.size())),
+                Backing::Device(_) => None,
+            })
+            .min()
+            .map(|(_, end)| end)
+    }
+
+    // Contiguous runs of (start, length, mapping index) covering the whole address space, unmapped runs have no index
+    pub fn coverage(&self) -> Vec<(usize, usize, Option<usize>)> {
+        let mut runs = vec![];
+        let mut first_block = 0;
+
+        for block in 1..=self.blocks.len() {
+            if block == self.blocks.len() || self.blocks[block] != self.blocks[first_block] {
+                runs.push((first_block * self.block_size, (block - first_block) * self.block_size, self.blocks[first_block]));
+
+                first_block = block;
+            }
+        }
+
+        runs
+    }
+
+    // Hooks see every bus access as it happens, but not peeks and pokes
+    pub fn set_read_hook(&mut self, hook: impl FnMut(usize, AccessWidth, u16) + 'static) {
+        self.read_hook = Some(RefCell::new(Box::new(hook)));
+    }
+
+    pub fn clear_read_hook(&mut self) {
+        self.read_hook = None;
+    }
+
+    pub fn set_write_hook(&mut self, hook: impl FnMut(usize, AccessWidth, u16) + 'static) {
+        self.write_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_write_hook(&mut self) {
+        self.write_hook = None;
+    }
+
+    pub fn watch_write(&mut self, address: usize) {
+        if !self.watchpoints.contains(&address) {
+            self.watchpoints.push(address);
+        }
+    }
+
+    pub fn unwatch_write(&mut self, address: usize) {
+        self.watchpoints.retain(|watched_address| *watched_address != address);
+    }
+
+    // Writes to watched addresses since the last call to clear_watchpoint_hits, oldest first
+    pub fn watchpoint_hits(&self) -> &[WatchpointHit] {
+        &self.watchpoint_hits
+    }
+
+    pub fn clear_watchpoint_hits(&mut self) {
+        self.watchpoint_hits.clear();
+    }
+
+    // Must be called before the write happens so the old value can be recorded
+    fn check_watchpoint(&mut self, address: usize, new_value: u8) {
+        if self.watchpoints.contains(&address) {
+            let old_value = self.read8_unhooked(address);
+
+            self.watchpoint_hits.push(WatchpointHit {
+                address,
+                old_value,
+                new_value,
+            });
+        }
+    }
+
+    pub fn write8(&mut self, address: usize, value: u8) {
+        let address = self.mask_address(address);
+
+        self.bus_writes = self.bus_writes.wrapping_add(1);
+
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoint(address, value);
+        }
+
+        if self.dirty_pages.is_some() {
+            self.record_page(address);
+        }
+
+        if let Some((mapping_index, translated_address)) = self.translate(address) {
+            self.add_wait_states(mapping_index);
+
+            match &mut self.mappings[mapping_index].backing {
+                Backing::Ram(ram) => ram.write8(translated_address, value),
+                Backing::Device(device) => device.write8(translated_address, value),
+            }
+        }
+
+        if let Some(write_hook) = &mut self.write_hook {
+            write_hook(address, AccessWidth::Byte, value as u16);
+        }
+    }
+
+    pub fn write16(&mut self, address: usize, value: u16) {
+        let address = self.mask_address(address);
+
+        self.bus_writes = self.bus_writes.wrapping_add(1);
+
+        if !self.watchpoints.is_empty() {
+            let [first, second] = self.endianness.to_bytes(value);
+
+            self.check_watchpoint(address, first);
+            self.check_watchpoint(self.mask_address(address + 1), second);
+        }
+
+        let stored_value = match self.endianness {
+            Endianness::Little => value,
+            Endianness::Big => value.swap_bytes(),
+        };
+
+        if self.dirty_pages.is_some() {
+            self.record_page(address);
+            self.record_page(self.mask_address(address + 1));
+        }
+
+        if let Some((mapping_index, translated_address)) = self.translate(address) {
+            self.add_wait_states(mapping_index);
+
+            match &mut self.mappings[mapping_index].backing {
+                Backing::Ram(ram) => ram.write16(translated_address, stored_value),
+                Backing::Device(device) => device.write16(translated_address, stored_value),
+            }
+        }
+
+        if let Some(write_hook) = &mut self.write_hook {
+            write_hook(address, AccessWidth::Word, value);
+        }
+    }
+
+    // Like read8 but None for unmapped addresses, for tooling that needs to tell open bus from a device returning 0
+    pub fn try_read8(&self, address: usize) -> Option<u8> {
+        self.translate(self.mask_address(address))?;
+
+        Some(self.read8(address))
+    }
+
+    // Like write8 but false for unmapped addresses, a write a read-only device ignores still hit it
+    pub fn try_write8(&mut self, address: usize, value: u8) -> bool {
+        if self.translate(self.mask_address(address)).is_none() {
+            return false;
+        }
+
+        self.write8(address, value);
+
+        true
+    }
+
+    // Fills RAM and writable regions directly, block by block, so hooks, watchpoints and wait states don't see it. Read-only
+    // and MMIO devices are left untouched
+    pub fn fill_range(&mut self, start: usize, len: usize, value: u8) {
+        let mut offset = 0;
+
+        while offset < len {
+            let address = self.mask_address(start + offset);
+            let chunk = (len - offset).min(self.block_size - address % self.block_size);
+
+            self.record_pages(address, chunk);
+
+            if let Some(storage) = self.writable_storage(address, chunk) {
+                storage.fill(value);
+            }
+
+            offset += chunk;
+        }
+    }
+
+    // Like memmove, an overlapping destination gets the span as it was before the copy. Like fill_range it bypasses the bus,
+    // bytes are read the way peek_range does and bytes landing outside RAM or writable regions are dropped
+    pub fn copy_within(&mut self, src: usize, dst: usize, len: usize) {
+        let mut chunks = Vec::new();
+        let mut offset = 0;
+
+        // Each chunk stays within one block at both ends
+        while offset < len {
+            let src_address = self.mask_address(src + offset);
+            let dst_address = self.mask_address(dst + offset);
+            let chunk = (len - offset)
+                .min(self.block_size - src_address % self.block_size)
+                .min(self.block_size - dst_address % self.block_size);
+
+            chunks.push((src_address, dst_address, chunk));
+
+            offset += chunk;
+        }
+
+        // Copying a higher destination from the end reads each overlapping source byte before it is overwritten
+        if dst > src {
+            chunks.reverse();
+        }
+
+        for (src_address, dst_address, chunk) in chunks {
+            self.copy_chunk(src_address, dst_address, chunk);
+        }
+    }
+
+    fn copy_chunk(&mut self, src: usize, dst: usize, len: usize) {
+        self.record_pages(dst, len);
+
+        // Within one RAM the slice copy takes care of the overlap
+        if let (Some((src_index, src_address)), Some((dst_index, dst_address))) = (self.translate(src), self.translate(dst))
+            && src_index == dst_index
+            && let Backing::Ram(ram) = &mut self.mappings[dst_index].backing
+            && src_address.max(dst_address) + len <= ram.size()
+        {
+            ram.region.memory.copy_within(src_address..src_address + len, dst_address);
+
+            return;
+        }
+
+        let bytes = self.peek_range(src, len);
+
+        if let Some(storage) = self.writable_storage(dst, len) {
+            let count = storage.len();
+
+            storage.copy_from_slice(&bytes[..count]);
+        }
+    }
+
+    // Clears every device's memory, RAM to its reset value. Unlike reset, device registers keep their state
+    pub fn clear(&mut self) {
+        self.record_all_pages();
+
+        for mapping in self.mappings.iter_mut() {
+            mapping.device_mut().clear();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.record_all_pages();
+
+        for mapping in self.mappings.iter_mut() {
+            mapping.device_mut().reset();
+        }
+    }
+
+    pub fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            devices: self.mappings.iter().map(|mapping| mapping.device().save_state()).collect(),
+        }
+    }
+
+    // The snapshot must have been taken with the same devices mapped
+    pub fn restore(&mut self, snapshot: &MemorySnapshot) -> Result<(), MemoryError> {
+        if snapshot.devices.len() != self.mappings.len() {
+            return Err(MemoryError::SizeMismatch { expected: self.mappings.len(), actual: snapshot.devices.len() });
+        }
+
+        for (mapping, state) in self.mappings.iter_mut().zip(&snapshot.devices) {
+            if let Some(state) = state {
+                mapping.device_mut().load_state(state)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Like snapshot but RAM is left out, for callers that track RAM through the journal instead
+    pub fn snapshot_devices(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            devices: self.mappings.iter()
+                .map(|mapping| match &mapping.backing {
+                    Backing::Ram(_) => None,
+                    Backing::Device(device) => device.save_state(),
+                })
+                .collect(),
+        }
+    }
+
+    // While journaling, each RAM page is copied before its first write since the journal was last taken
+    pub fn enable_journal(&mut self) {
+        self.dirty_pages = Some(vec![false; self.address_space / JOURNAL_PAGE_SIZE]);
+        self.journal = PageJournal::default();
+    }
+
+    pub fn disable_journal(&mut self) {
+        self.dirty_pages = None;
+        self.journal = PageJournal::default();
+    }
+
+    // Returns the pages copied so far and starts a new journal from the current contents
+    pub fn take_journal(&mut self) -> PageJournal {
+        if let Some(dirty_pages) = &mut self.dirty_pages {
+            dirty_pages.fill(false);
+        }
+
+        mem::take(&mut self.journal)
+    }
+
+    // Puts the journaled pages back, returning RAM to how it was when the journal was started
+    pub fn undo_journal(&mut self, journal: &PageJournal) -> Result<(), MemoryError> {
+        for (address, bytes) in &journal.pages {
+            self.poke_bytes(*address, bytes)?;
+        }
+
+        Ok(())
+    }
+
+    fn record_page(&mut self, address: usize) {
+        let page = address / JOURNAL_PAGE_SIZE;
+
+        if self.dirty_pages.as_ref().is_none_or(|dirty_pages| dirty_pages[page]) {
+            return;
+        }
+
+        let Some((mapping_index, translated_address)) = self.translate(address) else {
+            return;
+        };
+
+        let Backing::Ram(ram) = &self.mappings[mapping_index].backing else {
+            return;
+        };
+
+        if translated_address >= ram.size() {
+            return;
+        }
 
-        for block in first_block..first_block + blocks {
-            self.blocks[block] = Some(mapping_index);
+        // Mappings start on a block boundary, so a page never straddles two of them
+        let page_offset = address % JOURNAL_PAGE_SIZE;
+        let start = translated_address - page_offset;
+
+        if let Ok(bytes) = ram.peek_bytes(start, JOURNAL_PAGE_SIZE.min(ram.size() - start)) {
+            self.journal.pages.push((address - page_offset, bytes.to_vec()));
         }
 
-        Ok(mapping_index)
+        if let Some(dirty_pages) = &mut self.dirty_pages {
+            dirty_pages[page] = true;
+        }
     }
 
-    pub fn unmap_device(&mut self, mapping_index: usize) -> Result<(), String> {
-        if mapping_index >= self.mappings.len() {
-            return Err(format!("Index {} is out-of-bounds", mapping_index));
+    fn record_pages(&mut self, address: usize, len: usize) {
+        if self.dirty_pages.is_none() {
+            return;
         }
 
-        self.mappings.swap_remove(mapping_index);
+        for page_address in (address - address % JOURNAL_PAGE_SIZE..address + len).step_by(JOURNAL_PAGE_SIZE) {
+            self.record_page(page_address);
+        }
+    }
 
-        // Because swap_remove removes an element and replaces it with the last element in the vector, we need to update all the blocks that pointed to the last element
-                
-        let last_mapping_index = self.mappings.len(); // The current length of the vector equals the index of what the last element in the vector *used* to be
+    fn record_all_pages(&mut self) {
+        if self.dirty_pages.is_none() {
+            return;
+        }
 
-        for block in &mut self.blocks {
-            // Remove all indecies to the element we just removed
-            if *block == Some(mapping_index) {
-                *block = None;
-            }
-            // Change all indecies that used to point to the last element to point to the element it just replaced
-            if *block == Some(last_mapping_index) {
-                *block = Some(mapping_index);
-            }
+        for page in 0..self.address_space / JOURNAL_PAGE_SIZE {
+            self.record_page(page * JOURNAL_PAGE_SIZE);
         }
+    }
+}
 
-        Ok(())
+impl Default for MemoryController {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub fn get_device(&self, mapping_index: usize) -> Result<&dyn MappedDevice, String> {
-        if mapping_index >= self.mappings.len() {
-            return Err(format!("Index {} is out-of-bounds", mapping_index));
-        }
+// Devices aren't formatted, only which address ranges they occupy
+impl fmt::Debug for MemoryController {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ranges: Vec<String> = self.coverage()
+            .into_iter()
+            .filter_map(|(start, length, mapping_index)| {
+                mapping_index.map(|mapping_index| format!("0x{:04X}..0x{:04X} -> {}", start, start + length, mapping_index))
+            })
+            .collect();
 
-        Ok(self.mappings[mapping_index].device.as_ref())
+        f.debug_struct("MemoryController")
+            .field("block_size", &format_args!("0x{:X}", self.block_size))
+            .field("mappings", &self.mappings.len())
+            .field("ranges", &ranges)
+            .finish()
     }
+}
 
-    pub fn get_device_mut(&mut self, mapping_index: usize) -> Result<&mut dyn MappedDevice, String> {
-        if mapping_index >= self.mappings.len() {
-            return Err(format!("Index {} is out-of-bounds", mapping_index));
-        }
+// Saved contents of each device, in mapping index order
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MemorySnapshot {
+    devices: Vec<Option<Vec<u8>>>,
+}
+
+// Copies of RAM pages, by the address of each page
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PageJournal {
+    pages: Vec<(usize, Vec<u8>)>,
+}
 
-        Ok(self.mappings[mapping_index].device.as_mut())
+impl PageJournal {
+    pub fn pages(&self) -> usize {
+        self.pages.len()
     }
 
-    pub fn read8(&self, address: usize) -> u8 {
-        match self.blocks[address / MAP_BLOCK_SIZE] {
-            Some(mapping_index) => {
-                let translated_address = address - self.mappings[mapping_index].offset;
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
 
-                self.mappings[mapping_index].device.read8(translated_address)
-            },
-            None => 0x00,
-        }
+    // Bytes of page contents held, the bulk of what the journal costs
+    pub fn bytes(&self) -> usize {
+        self.pages.iter().map(|(_, bytes)| bytes.len()).sum()
     }
+}
 
-    pub fn read16(&self, address: usize) -> u16 {
-        match self.blocks[address / MAP_BLOCK_SIZE] {
-            Some(mapping_index) => {
-                let translated_address = address - self.mappings[mapping_index].offset;
-                
-                self.mappings[mapping_index].device.read16(translated_address)
-            },
-            None => 0x00,
+#[derive(Clone, Copy, Debug)]
+pub struct WatchpointHit {
+    pub address: usize,
+    pub old_value: u8,
+    pub new_value: u8,
+}
+
+struct Mapping {
+    offset: usize,
+    backing: Backing,
+    executable: bool,
+    wait_states: u8, // Extra cycles each bus access to the device takes
+}
+
+impl Mapping {
+    fn device(&self) -> &dyn MappedDevice {
+        match &self.backing {
+            Backing::Ram(ram) => ram,
+            Backing::Device(device) => device.as_ref(),
         }
     }
 
-    pub fn write8(&mut self, address: usize, value: u8) {
-        match self.blocks[address / MAP_BLOCK_SIZE] {
-            Some(mapping_index) => {
-                let translated_address = address - self.mappings[mapping_index].offset;
-                
-                self.mappings[mapping_index].device.write8(translated_address, value)
-            },
-            None => (),
+    fn device_mut(&mut self) -> &mut dyn MappedDevice {
+        match &mut self.backing {
+            Backing::Ram(ram) => ram,
+            Backing::Device(device) => device.as_mut(),
         }
     }
+}
 
-    pub fn write16(&mut self, address: usize, value: u16) {
-        match self.blocks[address / MAP_BLOCK_SIZE] {
-            Some(mapping_index) => {
-                let translated_address = address - self.mappings[mapping_index].offset;
-                
-                self.mappings[mapping_index].device.write16(translated_address, value)
-            },
-            None => (),
+// RAM is unboxed when it's mapped so bus accesses to it are statically dispatched, which is most of them in tight loops
+enum Backing {
+    Ram(RAM),
+    Device(Box<dyn MappedDevice>),
+}
+
+impl Backing {
+    fn new(device: Box<dyn MappedDevice>) -> Self {
+        if !(device.as_ref() as &dyn Any).is::<RAM>() {
+            return Self::Device(device);
         }
-    }
 
-    pub fn reset(&mut self) {
-        for mapping in self.mappings.iter_mut() {
-            mapping.device.reset();
+        let device: Box<dyn Any> = device;
+
+        match device.downcast::<RAM>() {
+            Ok(ram) => Self::Ram(*ram),
+            Err(_) => unreachable!("Device was checked to be RAM"),
         }
     }
 }
 
-struct Mapping {
-    offset: usize,
-    device: Box<dyn MappedDevice>,
-}
-
-pub trait MappedDevice {
-    fn peek_bytes(&mut self, address: usize, count: usize) -> &[u8];
-    fn poke_bytes(&mut self, address: usize, bytes: &[u8]);
+pub trait MappedDevice: Any {
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError>;
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError>;
     fn size(&self) -> usize;
     fn read8(&self, address: usize) -> u8;
     fn read16(&self, address: usize) -> u16;
     fn write8(&mut self, address: usize, value: u8);
     fn write16(&mut self, address: usize, value: u16);
     fn reset(&mut self);
+
+    // Reads a byte without side effects, None for devices with no inspectable storage such as MMIO registers
+    fn peek8(&self, _: usize) -> Option<u8> {
+        None
+    }
+
+    // Little-endian like read16, whatever the controller's endianness
+    fn peek16(&self, address: usize) -> Option<u16> {
+        Some(u16::from_le_bytes([self.peek8(address)?, self.peek8(address + 1)?]))
+    }
+
+    fn poke16(&mut self, address: usize, value: u16) -> Result<(), MemoryError> {
+        self.poke_bytes(address, &value.to_le_bytes())
+    }
+
+    // Clears the device's memory, devices with only registers have nothing to clear
+    fn clear(&mut self) {}
+
+    // Devices with volatile contents or registers return them so they can be snapshotted and rewound, the default of None
+    // leaves the device out and is only right for devices whose state never changes
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_state(&mut self, _: &[u8]) -> Result<(), MemoryError> {
+        Ok(())
+    }
 }
 
-pub struct RAM {
+// Plain byte buffer, bus writes are ignored when it isn't writable
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MappedRegion {
     memory: Box<[u8]>,
+    writable: bool,
 }
 
-impl RAM {
-    pub fn new(capacity: usize) -> Self {
+impl MappedRegion {
+    pub fn new(capacity: usize, writable: bool) -> Self {
         Self {
             memory: vec![0x00_u8; capacity].into_boxed_slice(),
+            writable,
         }
     }
 
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    pub fn set_writable(&mut self, writable: bool) {
+        self.writable = writable;
+    }
+
     pub fn fill(&mut self, value: u8) {
         self.memory.fill(value);
     }
 }
 
-impl MappedDevice for RAM {
-    fn peek_bytes(&mut self, address: usize, count: usize) -> &[u8] {
-        &self.memory[address..address + count]
+impl MappedDevice for MappedRegion {
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        check_range(address, count, self.memory.len())?;
+
+        Ok(&self.memory[address..address + count])
     }
 
-    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) {
-        for (i, byte) in bytes.iter().enumerate() {
-            self.memory[address + i] = *byte;
-        }
+    // Loads bytes regardless of writability
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        check_range(address, bytes.len(), self.memory.len())?;
+
+        self.memory[address..address + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
     }
 
     fn size(&self) -> usize {
         self.memory.len()
     }
 
+    fn peek8(&self, address: usize) -> Option<u8> {
+        self.memory.get(address).copied()
+    }
+
     fn read8(&self, address: usize) -> u8 {
         if address >= self.memory.len() {
             return 0x00;
@@ -199,13 +1042,16 @@ impl MappedDevice for RAM {
     }
 
     fn write8(&mut self, address: usize, value: u8) {
-        if address >= self.memory.len() {
+        if !self.writable || address >= self.memory.len() {
             return;
         }
         self.memory[address] = value;
     }
 
     fn write16(&mut self, address: usize, value: u16) {
+        if !self.writable {
+            return;
+        }
         if address < self.memory.len() {
             self.memory[address] = value as u8;
         }
@@ -215,62 +1061,275 @@ impl MappedDevice for RAM {
     }
 
     fn reset(&mut self) {
-        self.memory.fill(0x00);
+        self.clear();
+    }
+
+    // Only writable regions are volatile
+    fn clear(&mut self) {
+        if self.writable {
+            self.memory.fill(0x00);
+        }
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        if !self.writable {
+            return None;
+        }
+
+        Some(self.memory.to_vec())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        if state.len() != self.memory.len() {
+            return Err(MemoryError::SizeMismatch { expected: self.memory.len(), actual: state.len() });
+        }
+
+        self.memory.copy_from_slice(state);
+
+        Ok(())
+    }
+}
+
+// Repeats a device across a mapping larger than the device, so every alias reaches the same storage
+pub struct MirroredDevice {
+    device: Box<dyn MappedDevice>,
+}
+
+impl MirroredDevice {
+    pub fn new(device: Box<dyn MappedDevice>) -> Self {
+        Self {
+            device,
+        }
+    }
+
+    pub fn device(&self) -> &dyn MappedDevice {
+        self.device.as_ref()
+    }
+
+    pub fn device_mut(&mut self) -> &mut dyn MappedDevice {
+        self.device.as_mut()
+    }
+
+    fn translate(&self, address: usize) -> usize {
+        address % self.device.size()
+    }
+}
+
+impl MappedDevice for MirroredDevice {
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        let translated_address = self.translate(address);
+
+        self.device.peek_bytes(translated_address, count)
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        let translated_address = self.translate(address);
+
+        self.device.poke_bytes(translated_address, bytes)
+    }
+
+    fn size(&self) -> usize {
+        self.device.size()
+    }
+
+    fn peek8(&self, address: usize) -> Option<u8> {
+        self.device.peek8(self.translate(address))
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        self.device.read8(self.translate(address))
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        let translated_address = self.translate(address);
+
+        // A word at the end of the device wraps around to its start
+        if translated_address + 1 == self.device.size() {
+            return self.device.read8(translated_address) as u16 | (self.device.read8(0) as u16) << 8;
+        }
+
+        self.device.read16(translated_address)
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        let translated_address = self.translate(address);
+
+        self.device.write8(translated_address, value);
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        let translated_address = self.translate(address);
+
+        if translated_address + 1 == self.device.size() {
+            self.device.write8(translated_address, value as u8);
+            self.device.write8(0, (value >> 8) as u8);
+            return;
+        }
+
+        self.device.write16(translated_address, value);
+    }
+
+    fn reset(&mut self) {
+        self.device.reset();
+    }
+
+    fn clear(&mut self) {
+        self.device.clear();
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.device.save_state()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        self.device.load_state(state)
+    }
+}
+
+// Fill for poisoned RAM, conspicuous in dumps and traces where a zero would pass for real data
+pub const RAM_POISON: u8 = 0xAA;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RAM {
+    region: MappedRegion,
+    reset_value: u8, // Every byte powers up and resets to this
+}
+
+impl RAM {
+    pub fn new(capacity: usize) -> Self {
+        Self::new_with_reset_value(capacity, 0x00)
+    }
+
+    pub fn new_with_reset_value(capacity: usize, reset_value: u8) -> Self {
+        let mut region = MappedRegion::new(capacity, true);
+
+        region.fill(reset_value);
+
+        Self {
+            region,
+            reset_value,
+        }
+    }
+
+    // Filled with RAM_POISON instead of zeroes so reads of never-written cells stand out, resets poison it again
+    pub fn poisoned(capacity: usize) -> Self {
+        Self::new_with_reset_value(capacity, RAM_POISON)
+    }
+
+    pub fn reset_value(&self) -> u8 {
+        self.reset_value
+    }
+
+    // Takes effect on the next reset, the current contents are kept
+    pub fn set_reset_value(&mut self, reset_value: u8) {
+        self.reset_value = reset_value;
+    }
+
+    pub fn fill(&mut self, value: u8) {
+        self.region.fill(value);
+    }
+}
+
+impl MappedDevice for RAM {
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        self.region.peek_bytes(address, count)
+    }
+
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        self.region.poke_bytes(address, bytes)
+    }
+
+    fn size(&self) -> usize {
+        self.region.size()
+    }
+
+    fn peek8(&self, address: usize) -> Option<u8> {
+        self.region.peek8(address)
+    }
+
+    fn read8(&self, address: usize) -> u8 {
+        self.region.read8(address)
+    }
+
+    fn read16(&self, address: usize) -> u16 {
+        self.region.read16(address)
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        self.region.write8(address, value);
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        self.region.write16(address, value);
+    }
+
+    fn reset(&mut self) {
+        self.clear();
+    }
+
+    fn clear(&mut self) {
+        self.region.fill(self.reset_value);
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.region.save_state()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        self.region.load_state(state)
     }
 }
 
 pub struct ROM {
-    memory: Box<[u8]>,
+    region: MappedRegion,
 }
 
 impl ROM {
     pub fn new(capacity: usize) -> Self {
         Self {
-            memory: vec![0x00_u8; capacity].into_boxed_slice(),
+            region: MappedRegion::new(capacity, false),
         }
     }
 
     pub fn fill(&mut self, value: u8) {
-        self.memory.fill(value);
+        self.region.fill(value);
     }
 }
 
 impl MappedDevice for ROM {
-    fn peek_bytes(&mut self, address: usize, count: usize) -> &[u8] {
-        &self.memory[address..address + count]
+    fn peek_bytes(&self, address: usize, count: usize) -> Result<&[u8], MemoryError> {
+        self.region.peek_bytes(address, count)
     }
 
-    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) {
-        for (i, byte) in bytes.iter().enumerate() {
-            self.memory[address + i] = *byte;
-        }
+    fn poke_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        self.region.poke_bytes(address, bytes)
     }
 
     fn size(&self) -> usize {
-        self.memory.len()
+        self.region.size()
+    }
+
+    fn peek8(&self, address: usize) -> Option<u8> {
+        self.region.peek8(address)
     }
 
     fn read8(&self, address: usize) -> u8 {
-        if address >= self.memory.len() {
-            return 0x00;
-        }
-        self.memory[address]
+        self.region.read8(address)
     }
 
     fn read16(&self, address: usize) -> u16 {
-        let mut value: u16 = 0x0000;
+        self.region.read16(address)
+    }
 
-        if address < self.memory.len() {
-            value |= self.memory[address] as u16;
-        }
-        if address + 1 < self.memory.len() {
-            value |= (self.memory[address + 1] as u16) << 8;
-        }
+    fn write8(&mut self, address: usize, value: u8) {
+        self.region.write8(address, value);
+    }
 
-        value
+    fn write16(&mut self, address: usize, value: u16) {
+        self.region.write16(address, value);
     }
 
-    fn write8(&mut self, _: usize, _: u8) {}
-    fn write16(&mut self, _: usize, _: u16) {}
-    fn reset(&mut self) {}
-}
\ No newline at end of file
+    fn reset(&mut self) {
+        self.region.reset();
+    }
+}
@@ -0,0 +1,150 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::memory::{MAP_BLOCK_SIZE, MAP_BLOCKS, MappedDevice, MemoryError, RAM, ROM};
+
+// Devices known at compile time are matched on directly, only custom devices go through a trait object
+pub enum StaticDevice {
+    Ram(RAM),
+    Rom(ROM),
+    Custom(Box<dyn MappedDevice>),
+}
+
+impl StaticDevice {
+    pub fn as_device(&self) -> &dyn MappedDevice {
+        match self {
+            Self::Ram(ram) => ram,
+            Self::Rom(rom) => rom,
+            Self::Custom(device) => device.as_ref(),
+        }
+    }
+
+    pub fn as_device_mut(&mut self) -> &mut dyn MappedDevice {
+        match self {
+            Self::Ram(ram) => ram,
+            Self::Rom(rom) => rom,
+            Self::Custom(device) => device.as_mut(),
+        }
+    }
+}
+
+// Same mapping rules as MemoryController, for fixed hardware where dynamic dispatch on every access isn't wanted
+pub struct StaticMemoryController {
+    blocks: [Option<usize>; MAP_BLOCKS],
+    mappings: Vec<StaticMapping>,
+}
+
+impl StaticMemoryController {
+    pub fn new() -> Self {
+        Self {
+            blocks: [None; MAP_BLOCKS],
+            mappings: vec![],
+        }
+    }
+
+    // Returns index of device mapping or an error
+    pub fn map_device(&mut self, first_block: usize, blocks: usize, device: StaticDevice) -> Result<usize, MemoryError> {
+        for block in first_block..first_block + blocks {
+            if let Some(mapping_index) = self.blocks[block] {
+                return Err(MemoryError::BlockAlreadyMapped { block, mapping_index, offset: self.mappings[mapping_index].offset });
+            }
+        }
+
+        self.mappings.push(StaticMapping {
+            offset: first_block * MAP_BLOCK_SIZE,
+            device,
+        });
+
+        let mapping_index = self.mappings.len() - 1;
+
+        for block in first_block..first_block + blocks {
+            self.blocks[block] = Some(mapping_index);
+        }
+
+        Ok(mapping_index)
+    }
+
+    pub fn get_device(&self, mapping_index: usize) -> Result<&StaticDevice, MemoryError> {
+        self.mappings.get(mapping_index).map(|mapping| &mapping.device).ok_or(MemoryError::IndexOutOfBounds { index: mapping_index })
+    }
+
+    pub fn get_device_mut(&mut self, mapping_index: usize) -> Result<&mut StaticDevice, MemoryError> {
+        self.mappings.get_mut(mapping_index).map(|mapping| &mut mapping.device).ok_or(MemoryError::IndexOutOfBounds { index: mapping_index })
+    }
+
+    pub fn read8(&self, address: usize) -> u8 {
+        match self.blocks[address / MAP_BLOCK_SIZE] {
+            Some(mapping_index) => {
+                let mapping = &self.mappings[mapping_index];
+                let translated_address = address - mapping.offset;
+
+                match &mapping.device {
+                    StaticDevice::Ram(ram) => ram.read8(translated_address),
+                    StaticDevice::Rom(rom) => rom.read8(translated_address),
+                    StaticDevice::Custom(device) => device.read8(translated_address),
+                }
+            },
+            None => 0x00,
+        }
+    }
+
+    pub fn read16(&self, address: usize) -> u16 {
+        match self.blocks[address / MAP_BLOCK_SIZE] {
+            Some(mapping_index) => {
+                let mapping = &self.mappings[mapping_index];
+                let translated_address = address - mapping.offset;
+
+                match &mapping.device {
+                    StaticDevice::Ram(ram) => ram.read16(translated_address),
+                    StaticDevice::Rom(rom) => rom.read16(translated_address),
+                    StaticDevice::Custom(device) => device.read16(translated_address),
+                }
+            },
+            None => 0x00,
+        }
+    }
+
+    pub fn write8(&mut self, address: usize, value: u8) {
+        if let Some(mapping_index) = self.blocks[address / MAP_BLOCK_SIZE] {
+            let mapping = &mut self.mappings[mapping_index];
+            let translated_address = address - mapping.offset;
+
+            match &mut mapping.device {
+                StaticDevice::Ram(ram) => ram.write8(translated_address, value),
+                StaticDevice::Rom(rom) => rom.write8(translated_address, value),
+                StaticDevice::Custom(device) => device.write8(translated_address, value),
+            }
+        }
+    }
+
+    pub fn write16(&mut self, address: usize, value: u16) {
+        if let Some(mapping_index) = self.blocks[address / MAP_BLOCK_SIZE] {
+            let mapping = &mut self.mappings[mapping_index];
+            let translated_address = address - mapping.offset;
+
+            match &mut mapping.device {
+                StaticDevice::Ram(ram) => ram.write16(translated_address, value),
+                StaticDevice::Rom(rom) => rom.write16(translated_address, value),
+                StaticDevice::Custom(device) => device.write16(translated_address, value),
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for mapping in self.mappings.iter_mut() {
+            mapping.device.as_device_mut().reset();
+        }
+    }
+}
+
+impl Default for StaticMemoryController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct StaticMapping {
+    offset: usize,
+    device: StaticDevice,
+}
@@ -0,0 +1,684 @@
+use crate::devices::Timer;
+use alloc::rc::Rc;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use super::*;
+
+// 4 KiB of RAM in block 0
+fn controller() -> MemoryController {
+    let mut memory = MemoryController::new();
+
+    memory.map_device(0, 1, Box::new(RAM::new(MAP_BLOCK_SIZE))).unwrap();
+
+    memory
+}
+
+#[test]
+fn watchpoints_record_old_and_new_values() {
+    let mut memory = controller();
+
+    memory.write8(0x0010, 0x11);
+    memory.watch_write(0x0010);
+    memory.write8(0x0011, 0x22);
+
+    assert!(memory.watchpoint_hits().is_empty());
+
+    // Little-endian, so the high byte lands on the watched address
+    memory.write16(0x000F, 0xABCD);
+
+    let hit = memory.watchpoint_hits()[0];
+
+    assert_eq!(memory.watchpoint_hits().len(), 1);
+    assert_eq!((hit.address, hit.old_value, hit.new_value), (0x0010, 0x11, 0xAB));
+
+    memory.clear_watchpoint_hits();
+    memory.unwatch_write(0x0010);
+    memory.write8(0x0010, 0x33);
+
+    assert!(memory.watchpoint_hits().is_empty());
+}
+
+#[test]
+fn read_only_region_ignores_bus_writes() {
+    let mut memory = MemoryController::new();
+    let mut region = MappedRegion::new(MAP_BLOCK_SIZE, false);
+
+    region.poke_bytes(0, &[0x01, 0x02, 0x03]).unwrap();
+
+    let index = memory.map_device(0, 1, Box::new(region)).unwrap();
+
+    memory.write8(0x0000, 0x09);
+    memory.write16(0x0001, 0x0909);
+
+    assert_eq!(memory.read8(0x0000), 0x01);
+    assert_eq!(memory.read16(0x0001), 0x0302);
+
+    memory.get_device_as_mut::<MappedRegion>(index).unwrap().set_writable(true);
+    memory.write8(0x0000, 0x09);
+
+    assert_eq!(memory.read8(0x0000), 0x09);
+}
+
+#[test]
+fn mirrored_device_repeats_across_its_blocks() {
+    let mut memory = MemoryController::new();
+
+    memory.map_device(0, 4, Box::new(MirroredDevice::new(Box::new(RAM::new(MAP_BLOCK_SIZE))))).unwrap();
+
+    memory.write8(0x0010, 0x5A);
+
+    assert_eq!(memory.read8(0x1010), 0x5A);
+    assert_eq!(memory.read8(0x3010), 0x5A);
+
+    // Wraps within the device at the end of a mirror
+    memory.write16(0x2FFF, 0xBBAA);
+
+    assert_eq!(memory.read8(0x0FFF), 0xAA);
+    assert_eq!(memory.read8(0x0000), 0xBB);
+    assert_eq!(memory.read16(0x1FFF), 0xBBAA);
+}
+
+#[test]
+fn errors_describe_what_went_wrong() {
+    let mut memory = controller();
+
+    assert_eq!(
+        memory.map_device(0, 1, Box::new(RAM::new(MAP_BLOCK_SIZE))),
+        Err(MemoryError::BlockAlreadyMapped { block: 0, mapping_index: 0, offset: 0x0000 })
+    );
+    assert_eq!(memory.unmap_device(5), Err(MemoryError::IndexOutOfBounds { index: 5 }));
+    assert_eq!(memory.get_device_as::<ROM>(0).err(), Some(MemoryError::WrongDeviceType { index: 0, expected: type_name::<ROM>() }));
+    assert_eq!(MemoryError::IndexOutOfBounds { index: 5 }.to_string(), "Index 5 is out-of-bounds");
+}
+
+// Xorshift, so fuzzed tests are repeatable
+fn next_random(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+
+    *state
+}
+
+#[test]
+fn ram_fast_path_matches_trait_dispatch() {
+    let mut fast = MemoryController::new();
+    let mut dispatched = MemoryController::new();
+
+    fast.map_device(0, 2, Box::new(RAM::new(0x2000))).unwrap();
+    dispatched.map_device(0, 2, Box::new(MappedRegion::new(0x2000, true))).unwrap();
+
+    let mut state = 12345;
+
+    for _ in 0..20_000 {
+        let random = next_random(&mut state);
+        let address = random as usize % 0x2100; // Includes unmapped addresses past the end
+        let value = (random >> 16) as u16;
+
+        match random % 4 {
+            0 => {
+                fast.write8(address, value as u8);
+                dispatched.write8(address, value as u8);
+            },
+            1 => {
+                fast.write16(address, value);
+                dispatched.write16(address, value);
+            },
+            2 => assert_eq!(fast.read8(address), dispatched.read8(address), "0x{address:04X}"),
+            _ => assert_eq!(fast.read16(address), dispatched.read16(address), "0x{address:04X}"),
+        }
+    }
+}
+
+#[test]
+fn fast_path_ram_is_still_a_device() {
+    let mut memory = controller();
+
+    memory.write8(0x0005, 0x42);
+
+    assert_eq!(memory.get_device_as::<RAM>(0).unwrap().peek8(0x0005), Some(0x42));
+    assert_eq!(memory.get_device(0).unwrap().size(), MAP_BLOCK_SIZE);
+
+    memory.unmap_device(0).unwrap();
+
+    assert_eq!(memory.read8(0x0005), 0x00);
+}
+
+#[test]
+fn cached_translation_follows_unmapping() {
+    let mut memory = controller();
+
+    memory.map_device(1, 1, Box::new(RAM::new(MAP_BLOCK_SIZE))).unwrap();
+
+    memory.write8(0x0FFF, 0x01);
+    memory.write8(0x1000, 0x02);
+
+    // Words don't straddle two mappings
+    assert_eq!(memory.read16(0x0FFF), 0x0001);
+    assert_eq!(memory.read8(0x1000), 0x02);
+    assert_eq!(memory.read8(0x0FFF), 0x01);
+
+    memory.unmap_device(0).unwrap();
+
+    assert_eq!(memory.read8(0x0FFF), 0x00);
+    assert_eq!(memory.read8(0x1000), 0x02);
+
+    // The second device is now index 0
+    memory.unmap_device(0).unwrap();
+
+    assert_eq!(memory.read8(0x1000), 0x00);
+}
+
+#[test]
+fn peek_range_spans_devices_without_side_effects() {
+    use crate::devices::Uart;
+
+    let mut memory = controller();
+
+    memory.map_device(1, 1, Box::new(ROM::new(MAP_BLOCK_SIZE))).unwrap();
+    let uart = memory.map_device(3, 1, Box::new(Uart::new())).unwrap();
+
+    memory.write8(0x0FFF, 0xAA);
+    memory.poke_bytes(0x1000, &[0xBB, 0xCC]).unwrap();
+
+    assert_eq!(memory.peek_range(0x0FFE, 4), [0x00, 0xAA, 0xBB, 0xCC]);
+    assert_eq!(memory.peek_range(0x1FFF, 2), [0x00, 0x00]); // Unmapped reads as zero
+
+    memory.get_device_as_mut::<Uart>(uart).unwrap().push_rx(0x07);
+
+    assert_eq!(memory.peek_range(0x3000, 2), [0x00, 0x00]);
+    assert_eq!(memory.read8(0x3000), 0x07);
+}
+
+#[test]
+fn devices_can_be_peeked_through_a_shared_reference() {
+    let mut memory = controller();
+
+    memory.poke_bytes(0x0010, &[0x12, 0x34]).unwrap();
+
+    let memory = &memory;
+
+    assert_eq!(memory.get_device(0).unwrap().peek_bytes(0x0010, 2).unwrap(), &[0x12, 0x34]);
+}
+
+#[test]
+fn spans_are_bounds_checked_against_the_device() {
+    let mut ram = RAM::new(4);
+
+    assert_eq!(ram.poke_bytes(2, &[0x01, 0x02, 0x03]), Err(MemoryError::RangeOutOfBounds { address: 2, count: 3, size: 4 }));
+    assert!(ram.peek_bytes(3, 2).is_err());
+    assert!(ram.peek_bytes(usize::MAX, 2).is_err());
+
+    ram.poke_bytes(2, &[0x01, 0x02]).unwrap();
+
+    assert_eq!(ram.peek_bytes(2, 2).unwrap(), &[0x01, 0x02]);
+
+    let mut memory = MemoryController::new();
+
+    memory.map_device(0, 1, Box::new(RAM::new(0x10))).unwrap();
+
+    assert!(memory.poke_bytes(0x000E, &[0x01, 0x02, 0x03]).is_err());
+}
+
+#[test]
+fn fill_range_fills_writable_storage_without_the_bus() {
+    let mut memory = controller();
+
+    memory.map_device(1, 1, Box::new(ROM::new(MAP_BLOCK_SIZE))).unwrap();
+    memory.map_device(2, 1, Box::new(MappedRegion::new(MAP_BLOCK_SIZE, true))).unwrap();
+    memory.poke_bytes(0x1000, &[0x05]).unwrap();
+    memory.set_write_hook(|_, _, _| panic!("fill_range shouldn't write over the bus"));
+
+    memory.fill_range(0x0FF0, 0x1020, 0xAA);
+
+    assert_eq!(memory.read8(0x0FEF), 0x00);
+    assert_eq!(memory.peek_range(0x0FF0, 0x10), [0xAA; 0x10]);
+    assert_eq!(memory.read8(0x1000), 0x05); // ROM is read-only
+    assert_eq!(memory.peek_range(0x2000, 0x10), [0xAA; 0x10]);
+    assert_eq!(memory.read8(0x2010), 0x00);
+    assert_eq!(memory.bus_writes(), 0);
+}
+
+#[test]
+fn fill_range_is_journaled() {
+    let mut memory = controller();
+
+    memory.poke_bytes(0x0100, &[0x12, 0x34]).unwrap();
+    memory.enable_journal();
+    memory.fill_range(0x00F0, 0x20, 0xAA);
+
+    let journal = memory.take_journal();
+
+    memory.undo_journal(&journal).unwrap();
+
+    assert_eq!(memory.peek_range(0x00F0, 0x12), [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x12, 0x34]);
+}
+
+#[test]
+fn clear_resets_memory_of_every_device() {
+    use crate::devices::Framebuffer;
+
+    let mut memory = MemoryController::new();
+
+    memory.map_device(0, 1, Box::new(RAM::new_with_reset_value(MAP_BLOCK_SIZE, 0xAA))).unwrap();
+    memory.map_device(1, 1, Box::new(MappedRegion::new(MAP_BLOCK_SIZE, true))).unwrap();
+    memory.map_device(2, 2, Box::new(MirroredDevice::new(Box::new(RAM::new(MAP_BLOCK_SIZE))))).unwrap();
+    memory.map_device(4, 1, Box::new(Framebuffer::new(4, 4))).unwrap();
+    memory.map_device(5, 1, Box::new(ROM::new(MAP_BLOCK_SIZE))).unwrap();
+
+    for address in [0x0000, 0x1000, 0x2000, 0x4000] {
+        memory.write8(address, 0x55);
+    }
+    memory.poke_bytes(0x5000, &[0x55]).unwrap();
+
+    memory.clear();
+
+    assert_eq!(memory.read8(0x0000), 0xAA);
+    assert_eq!(memory.read8(0x1000), 0x00);
+    assert_eq!(memory.read8(0x3000), 0x00);
+    assert_eq!(memory.read8(0x4000), 0x00);
+    assert_eq!(memory.read8(0x5000), 0x55);
+}
+
+#[test]
+fn clear_keeps_device_registers() {
+    use crate::devices::{TIMER_RELOAD, Timer};
+
+    let mut memory = MemoryController::new();
+
+    memory.map_device(0, 1, Box::new(Timer::new())).unwrap();
+    memory.write16(TIMER_RELOAD, 0x1234);
+
+    memory.clear();
+
+    assert_eq!(memory.read16(TIMER_RELOAD), 0x1234);
+}
+
+#[test]
+fn static_controller_matches_boxed_controller() {
+    // Both controllers get the same ROM contents
+    let rom = || {
+        let mut rom = ROM::new(MAP_BLOCK_SIZE * 2);
+        let mut state = 54321;
+
+        for address in 0..rom.size() {
+            rom.poke_bytes(address, &[next_random(&mut state) as u8]).unwrap();
+        }
+
+        rom
+    };
+
+    let mut boxed = MemoryController::new();
+    let mut fixed = StaticMemoryController::new();
+
+    boxed.map_device(0, 2, Box::new(RAM::new(MAP_BLOCK_SIZE * 2))).unwrap();
+    boxed.map_device(4, 1, Box::new(MappedRegion::new(MAP_BLOCK_SIZE, true))).unwrap();
+    boxed.map_device(8, 2, Box::new(rom())).unwrap();
+
+    fixed.map_device(0, 2, StaticDevice::Ram(RAM::new(MAP_BLOCK_SIZE * 2))).unwrap();
+    fixed.map_device(4, 1, StaticDevice::Custom(Box::new(MappedRegion::new(MAP_BLOCK_SIZE, true)))).unwrap();
+    fixed.map_device(8, 2, StaticDevice::Rom(rom())).unwrap();
+
+    let mut state = 12345;
+
+    for _ in 0..20_000 {
+        let random = next_random(&mut state);
+        let address = random as usize % (MAP_BLOCK_SIZE * 11); // Includes the unmapped blocks between and after devices
+        let value = (random >> 16) as u16;
+
+        match random % 4 {
+            0 => {
+                boxed.write8(address, value as u8);
+                fixed.write8(address, value as u8);
+            },
+            1 => {
+                boxed.write16(address, value);
+                fixed.write16(address, value);
+            },
+            2 => assert_eq!(boxed.read8(address), fixed.read8(address), "0x{address:04X}"),
+            _ => assert_eq!(boxed.read16(address), fixed.read16(address), "0x{address:04X}"),
+        }
+    }
+}
+
+#[test]
+fn small_blocks_map_a_device_right_after_ram() {
+    let mut memory = MemoryController::with_block_size(MIN_MAP_BLOCK_SIZE);
+
+    assert_eq!(memory.block_size(), 0x100);
+
+    memory.map_device(0, 0x10, Box::new(RAM::new(0x1000))).unwrap();
+    memory.map_device(0x10, 1, Box::new(RAM::new(0x100))).unwrap();
+
+    memory.write8(0x0FFF, 0x11);
+    memory.write8(0x1000, 0x22);
+    memory.write8(0x10FF, 0x33);
+
+    assert_eq!(memory.read8(0x0FFF), 0x11);
+    assert_eq!(memory.read8(0x1000), 0x22);
+    assert_eq!(memory.get_device(1).unwrap().read8(0x00), 0x22);
+    assert_eq!(memory.get_device(1).unwrap().read8(0xFF), 0x33);
+    assert_eq!(memory.get_device(0).unwrap().read8(0xFFF), 0x11);
+    assert_eq!(memory.try_read8(0x1100), None);
+}
+
+#[test]
+#[should_panic(expected = "Block size should be a power of two")]
+fn block_size_below_a_journal_page_panics() {
+    MemoryController::with_block_size(0x80);
+}
+
+#[test]
+#[should_panic(expected = "Block size should be a power of two")]
+fn block_size_that_is_not_a_power_of_two_panics() {
+    MemoryController::with_block_size(0x300);
+}
+
+#[test]
+fn remapped_device_keeps_its_index_and_leaves_its_old_blocks() {
+    let mut memory = controller();
+    let rom = memory.map_device(1, 1, Box::new(ROM::new(MAP_BLOCK_SIZE))).unwrap();
+
+    memory.write8(0x0010, 0x42);
+    memory.remap_device(0, 4, 1).unwrap();
+
+    assert_eq!(memory.try_read8(0x0010), None);
+    assert_eq!(memory.read8(0x4010), 0x42);
+    assert!(memory.get_device_as::<RAM>(0).is_ok());
+    assert!(memory.get_device_as::<ROM>(rom).is_ok());
+
+    // Overlapping its own blocks is fine, another mapping's isn't
+    memory.remap_device(0, 3, 2).unwrap();
+
+    assert_eq!(memory.read8(0x3010), 0x42);
+    assert_eq!(memory.coverage()[3], (0x3000, 0x2000, Some(0)));
+    assert_eq!(
+        memory.remap_device(0, 0, 2),
+        Err(MemoryError::BlockAlreadyMapped { block: 1, mapping_index: rom, offset: 0x1000 })
+    );
+    assert_eq!(memory.read8(0x3010), 0x42);
+}
+
+#[test]
+fn mapping_past_the_last_block_is_an_error() {
+    let mut memory = controller();
+
+    assert_eq!(
+        memory.map_device(MAP_BLOCKS - 1, 2, Box::new(RAM::new(MAP_BLOCK_SIZE))),
+        Err(MemoryError::BlocksOutOfBounds { first_block: MAP_BLOCKS - 1, blocks: 2, block_count: MAP_BLOCKS })
+    );
+    assert_eq!(
+        memory.map_device(usize::MAX, 2, Box::new(RAM::new(MAP_BLOCK_SIZE))),
+        Err(MemoryError::BlocksOutOfBounds { first_block: usize::MAX, blocks: 2, block_count: MAP_BLOCKS })
+    );
+    assert_eq!(
+        memory.remap_device(0, MAP_BLOCKS, 1),
+        Err(MemoryError::BlocksOutOfBounds { first_block: MAP_BLOCKS, blocks: 1, block_count: MAP_BLOCKS })
+    );
+    assert_eq!(memory.read8(0x0000), 0x00);
+    assert_eq!(
+        MemoryError::BlocksOutOfBounds { first_block: 15, blocks: 2, block_count: 16 }.to_string(),
+        "2 blocks from block 15 overrun the 16 blocks of the address space"
+    );
+
+    memory.map_device(MAP_BLOCKS - 1, 1, Box::new(RAM::new(MAP_BLOCK_SIZE))).unwrap();
+}
+
+#[test]
+fn big_endian_words_store_the_high_byte_first() {
+    let mut memory = controller();
+
+    assert_eq!(memory.endianness(), Endianness::Little);
+
+    memory.set_endianness(Endianness::Big);
+    memory.write16(0x0010, 0x1234);
+
+    assert_eq!(memory.read8(0x0010), 0x12);
+    assert_eq!(memory.read8(0x0011), 0x34);
+    assert_eq!(memory.read16(0x0010), 0x1234);
+
+    memory.set_endianness(Endianness::Little);
+
+    assert_eq!(memory.read16(0x0010), 0x3412);
+}
+
+#[test]
+fn hooks_see_each_access_in_order() {
+    let mut memory = controller();
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let reads = Rc::new(RefCell::new(Vec::new()));
+
+    let log = Rc::clone(&writes);
+    memory.set_write_hook(move |address, width, value| log.borrow_mut().push((address, width, value)));
+
+    let log = Rc::clone(&reads);
+    memory.set_read_hook(move |address, width, value| log.borrow_mut().push((address, width, value)));
+
+    memory.write8(0x0010, 0x12);
+    memory.write16(0x0020, 0x3456);
+    memory.write8(0x5000, 0x78); // Unmapped writes are still seen
+    memory.read16(0x0020);
+    memory.read8(0x0010);
+
+    assert_eq!(
+        *writes.borrow(),
+        [(0x0010, AccessWidth::Byte, 0x12), (0x0020, AccessWidth::Word, 0x3456), (0x5000, AccessWidth::Byte, 0x78)]
+    );
+    assert_eq!(*reads.borrow(), [(0x0020, AccessWidth::Word, 0x3456), (0x0010, AccessWidth::Byte, 0x12)]);
+
+    // Peeking isn't a bus access
+    memory.peek_range(0x0010, 0x10);
+
+    assert_eq!(reads.borrow().len(), 2);
+
+    memory.clear_write_hook();
+    memory.clear_read_hook();
+    memory.write8(0x0010, 0x00);
+    memory.read8(0x0010);
+
+    assert_eq!(writes.borrow().len(), 3);
+    assert_eq!(reads.borrow().len(), 2);
+}
+
+#[test]
+fn overlap_error_names_the_device_in_the_way() {
+    let mut memory = controller();
+
+    memory.map_device(8, 8, Box::new(ROM::new(0x8000))).unwrap();
+
+    let error = memory.map_device(6, 4, Box::new(RAM::new(0x4000))).unwrap_err();
+
+    assert_eq!(error, MemoryError::BlockAlreadyMapped { block: 8, mapping_index: 1, offset: 0x8000 });
+    assert_eq!(error.to_string(), "Block 8 is already mapped to device #1 at 0x8000");
+
+    // Nothing was mapped, so the blocks before the conflict are still free
+    assert_eq!(memory.try_read8(0x6000), None);
+}
+
+#[test]
+fn coverage_lists_ram_a_gap_then_rom() {
+    let mut memory = controller();
+
+    memory.map_device(8, 8, Box::new(ROM::new(0x8000))).unwrap();
+
+    assert_eq!(memory.coverage(), [(0x0000, 0x1000, Some(0)), (0x1000, 0x7000, None), (0x8000, 0x8000, Some(1))]);
+}
+
+#[test]
+fn coverage_keeps_neighbouring_mappings_apart() {
+    let mut memory = MemoryController::new();
+
+    assert_eq!(memory.coverage(), [(0x0000, ADDRESS_SPACE, None)]);
+
+    memory.map_device(0, 2, Box::new(RAM::new(0x2000))).unwrap();
+    memory.map_device(2, 1, Box::new(RAM::new(0x1000))).unwrap();
+    memory.map_device(MAP_BLOCKS - 1, 1, Box::new(RAM::new(0x1000))).unwrap();
+
+    assert_eq!(
+        memory.coverage(),
+        [(0x0000, 0x2000, Some(0)), (0x2000, 0x1000, Some(1)), (0x3000, 0xC000, None), (0xF000, 0x1000, Some(2))]
+    );
+}
+
+#[test]
+fn narrow_bus_wraps_addresses_at_its_space() {
+    let mut memory = MemoryController::with_address_bus_width(12, MIN_MAP_BLOCK_SIZE);
+
+    assert_eq!(memory.address_space(), 0x1000);
+    assert_eq!(memory.coverage(), [(0x0000, 0x1000, None)]);
+
+    memory.map_device(0, 0x10, Box::new(RAM::new(0x1000))).unwrap();
+    memory.write8(0x0010, 0x12);
+
+    assert_eq!(memory.read8(0x1010), 0x12);
+    assert_eq!(memory.read8(0xF010), 0x12);
+
+    memory.write16(0x1FF0, 0x3456);
+
+    assert_eq!(memory.read16(0x0FF0), 0x3456);
+    assert_eq!(
+        memory.map_device(0x10, 1, Box::new(RAM::new(0x100))),
+        Err(MemoryError::BlocksOutOfBounds { first_block: 0x10, blocks: 1, block_count: 0x10 })
+    );
+}
+
+#[test]
+#[should_panic(expected = "Address bus width should be between 8 and 16 bits")]
+fn bus_wider_than_the_registers_panics() {
+    MemoryController::with_address_bus_width(17, MAP_BLOCK_SIZE);
+}
+
+#[test]
+fn try_accessors_report_unmapped_addresses() {
+    let mut memory = controller();
+
+    memory.map_device(8, 8, Box::new(ROM::new(0x8000))).unwrap();
+
+    assert_eq!(memory.try_read8(0x0010), Some(0x00));
+    assert_eq!(memory.try_read8(0x5000), None);
+    assert!(memory.try_write8(0x0010, 0x42));
+    assert_eq!(memory.try_read8(0x0010), Some(0x42));
+    assert!(!memory.try_write8(0x5000, 0x42));
+
+    // ROM ignores the write but is still mapped
+    assert!(memory.try_write8(0x8000, 0x42));
+    assert_eq!(memory.try_read8(0x8000), Some(0x00));
+}
+
+#[test]
+fn poke16_and_peek16_split_words_little_endian() {
+    let mut rom = ROM::new(0x100);
+
+    rom.poke16(0x00FE, 0x8000).unwrap();
+
+    assert_eq!(rom.read16(0x00FE), 0x8000);
+    assert_eq!(rom.peek16(0x00FE), Some(0x8000));
+    assert_eq!(rom.peek_bytes(0x00FE, 2).unwrap(), [0x00, 0x80]);
+    assert_eq!(rom.peek16(0x00FF), None);
+    assert_eq!(rom.poke16(0x00FF, 0x1234), Err(MemoryError::RangeOutOfBounds { address: 0x00FF, count: 2, size: 0x100 }));
+}
+
+#[test]
+fn peek16_has_nothing_to_show_for_registers() {
+    let timer = Timer::new();
+
+    assert_eq!(timer.peek16(0x0000), None);
+}
+
+#[test]
+fn poisoned_ram_reads_the_poison_until_written() {
+    let mut memory = MemoryController::new();
+
+    memory.map_device(0, 1, Box::new(RAM::poisoned(MAP_BLOCK_SIZE))).unwrap();
+    memory.write8(0x0010, 0x00);
+
+    assert_eq!(memory.read8(0x0011), RAM_POISON);
+    assert_eq!(memory.read16(0x0FFE), 0xAAAA);
+    assert_eq!(memory.read8(0x0010), 0x00);
+
+    memory.reset();
+
+    assert_eq!(memory.read8(0x0010), RAM_POISON);
+}
+
+#[test]
+fn new_ram_is_zeroed() {
+    let ram = RAM::new(0x100);
+
+    assert!(ram.peek_bytes(0, 0x100).unwrap().iter().all(|byte| *byte == 0x00));
+}
+
+#[test]
+fn reset_fills_ram_with_its_reset_value() {
+    let mut ram = RAM::new(0x100);
+
+    ram.set_reset_value(0xFF);
+    ram.write8(0x10, 0x42);
+
+    // The new value only applies from the next reset
+    assert_eq!(ram.read8(0x11), 0x00);
+
+    ram.reset();
+
+    assert_eq!(ram.reset_value(), 0xFF);
+    assert!(ram.peek_bytes(0, 0x100).unwrap().iter().all(|byte| *byte == 0xFF));
+}
+
+#[test]
+fn ram_powers_up_with_its_reset_value() {
+    let ram = RAM::new_with_reset_value(0x100, 0x5A);
+
+    assert_eq!(ram.reset_value(), 0x5A);
+    assert_eq!(ram.read16(0xFE), 0x5A5A);
+}
+
+#[test]
+fn copy_within_handles_forward_overlap() {
+    let mut memory = controller();
+
+    memory.poke_bytes(0x0010, &[1, 2, 3, 4, 5]).unwrap();
+    memory.copy_within(0x0010, 0x0012, 5);
+
+    assert_eq!(memory.peek_range(0x0010, 7), [1, 2, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn copy_within_handles_backward_overlap() {
+    let mut memory = controller();
+
+    memory.poke_bytes(0x0012, &[1, 2, 3, 4, 5]).unwrap();
+    memory.copy_within(0x0012, 0x0010, 5);
+
+    assert_eq!(memory.peek_range(0x0010, 7), [1, 2, 3, 4, 5, 4, 5]);
+}
+
+#[test]
+fn copy_within_skips_rom_destinations() {
+    let mut memory = controller();
+
+    memory.map_device(1, 1, Box::new(ROM::new(MAP_BLOCK_SIZE))).unwrap();
+    memory.poke_bytes(0x0FFE, &[0xAA, 0xBB, 0xCC, 0xDD]).unwrap();
+    memory.copy_within(0x0FFC, 0x0FFE, 4);
+
+    // Only the bytes that land in RAM change, the ROM keeps what was poked into it
+    assert_eq!(memory.peek_range(0x0FFE, 4), [0x00, 0x00, 0xCC, 0xDD]);
+}
+
+#[test]
+fn copy_within_overlaps_across_devices_without_the_bus() {
+    let mut memory = controller();
+
+    memory.map_device(1, 1, Box::new(MappedRegion::new(MAP_BLOCK_SIZE, true))).unwrap();
+    memory.poke_bytes(0x0FFC, &[1, 2, 3, 4, 5, 6]).unwrap();
+    memory.set_read_hook(|_, _, _| panic!("copy_within shouldn't read over the bus"));
+    memory.set_write_hook(|_, _, _| panic!("copy_within shouldn't write over the bus"));
+
+    memory.copy_within(0x0FFC, 0x0FFE, 6);
+
+    assert_eq!(memory.peek_range(0x0FFC, 8), [1, 2, 1, 2, 3, 4, 5, 6]);
+
+    memory.copy_within(0x0FFE, 0x0FFC, 6);
+
+    assert_eq!(memory.peek_range(0x0FFC, 8), [1, 2, 3, 4, 5, 6, 5, 6]);
+}
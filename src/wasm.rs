@@ -0,0 +1,147 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::cpu::CPU;
+use crate::machine::MachineBuilder;
+use crate::memory::MemoryError;
+
+const RAM_FIRST_ADDRESS: usize = 0x0000;
+const RAM_CAPACITY: usize = 0x8000; // 32 KiB
+const ROM_FIRST_ADDRESS: usize = 0x8000;
+const ROM_CAPACITY: usize = 0x8000; // 32 KiB
+
+// Machine with RAM in the lower half and ROM in the upper half, everything in and out is a plain integer or byte array
+#[wasm_bindgen]
+pub struct WasmMachine {
+    cpu: CPU,
+}
+
+impl Default for WasmMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmMachine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            cpu: Self::build(&[]).expect("Empty program should fit"),
+        }
+    }
+
+    // Rebuilds the machine with the program at the start of ROM and resets into it
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), String> {
+        self.cpu = Self::build(program).map_err(|error| error.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn step(&mut self) {
+        self.cpu.process(false, None);
+    }
+
+    pub fn step_with_nmi(&mut self) {
+        self.cpu.process(true, None);
+    }
+
+    pub fn step_with_irq(&mut self, code: u8) {
+        self.cpu.process(false, Some(code));
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.cpu.program_counter
+    }
+
+    pub fn stack_pointer(&self) -> u16 {
+        self.cpu.stack_pointer
+    }
+
+    pub fn index_x(&self) -> u16 {
+        self.cpu.index_x
+    }
+
+    pub fn index_y(&self) -> u16 {
+        self.cpu.index_y
+    }
+
+    pub fn status(&self) -> u8 {
+        self.cpu.status
+    }
+
+    pub fn a(&self) -> u16 {
+        self.cpu.a
+    }
+
+    pub fn b(&self) -> u16 {
+        self.cpu.b
+    }
+
+    pub fn c(&self) -> u16 {
+        self.cpu.c
+    }
+
+    pub fn d(&self) -> u16 {
+        self.cpu.d
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    // Side-effect free, unmapped addresses read as open bus
+    pub fn memory(&self, start: u16, len: u16) -> Vec<u8> {
+        self.cpu.memory_controller.peek_range(start as usize, len as usize)
+    }
+
+    fn build(program: &[u8]) -> Result<CPU, MemoryError> {
+        MachineBuilder::new()
+            .ram(RAM_FIRST_ADDRESS, RAM_CAPACITY)
+            .rom(ROM_FIRST_ADDRESS, ROM_CAPACITY)
+            .reset_vector(ROM_FIRST_ADDRESS as u16)
+            .program(ROM_FIRST_ADDRESS, program)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_a_loaded_program() {
+        let mut machine = WasmMachine::new();
+
+        machine.load_program(&[0x40, 0x10, 0x80, 0x30, 0x00]).unwrap(); // MOV AL, #$80 / STP
+
+        assert_eq!(machine.program_counter(), 0x8000);
+
+        machine.step();
+
+        assert_eq!(machine.a(), 0x0080);
+        assert!(!machine.is_halted());
+
+        machine.step();
+
+        assert!(machine.is_halted());
+        assert_eq!(machine.memory(0x8000, 3), [0x40, 0x10, 0x80]);
+    }
+
+    #[test]
+    fn oversized_program_is_an_error() {
+        let mut machine = WasmMachine::new();
+
+        assert!(machine.load_program(&[0x00; ROM_CAPACITY + 1]).is_err());
+    }
+}